@@ -0,0 +1,148 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// an audit trail of wrong passwords: a `failed <unix time>` line per attempt and an
+// `unlocked <unix time>` line per unlock, never anything that was typed. Like the state
+// file the lock never depends on it, failing to read or write is only logged
+pub(crate) struct AttemptLog {
+    path: PathBuf,
+    // opened once and kept, the path is never looked up again; None when it couldn't be
+    file: Option<File>,
+    since_unlock: u32,
+}
+
+impl AttemptLog {
+    // counts what was logged after the last unlock, before this lock adds to it
+    pub(crate) fn open(path: &Path) -> Self {
+        let file = match open_own(path) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                log::warn!("not logging attempts to {}: {e}", path.display());
+                None
+            }
+        };
+        let since_unlock = file.as_ref().map_or(0, |file| {
+            tighten(path, file);
+            count_since_unlock(BufReader::new(file))
+        });
+        if since_unlock > 0 {
+            log::info!("{since_unlock} failed attempt(s) since the last unlock");
+        }
+        Self {
+            path: path.to_owned(),
+            file,
+            since_unlock,
+        }
+    }
+
+    pub(crate) fn since_unlock(&self) -> u32 {
+        self.since_unlock
+    }
+
+    pub(crate) fn failed(&self) {
+        self.append("failed");
+    }
+
+    pub(crate) fn unlocked(&self) {
+        self.append("unlocked");
+    }
+
+    fn append(&self, event: &str) {
+        let Some(mut file) = self.file.as_ref() else {
+            return;
+        };
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if let Err(e) = writeln!(file, "{event} {secs}") {
+            log::warn!("failed to write {}: {e}", self.path.display());
+        }
+    }
+}
+
+// only the owner may read it, the times alone tell when nobody was at the machine. A
+// symlink isn't followed and a file someone else owns is refused, so a setuid zlock never
+// writes to or chmods anything the user couldn't already
+fn open_own(path: &Path) -> std::io::Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+    let meta = file.metadata()?;
+    if !meta.is_file() || meta.uid() != unsafe { libc::getuid() } {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "not a regular file of your own",
+        ));
+    }
+    Ok(file)
+}
+
+// a log created by hand or by an older umask may be readable by others
+fn tighten(path: &Path, file: &File) {
+    let Ok(meta) = file.metadata() else {
+        return;
+    };
+    let mode = meta.permissions().mode();
+    if mode & 0o077 == 0 {
+        return;
+    }
+    log::warn!(
+        "{} is readable by others ({:o}), making it private",
+        path.display(),
+        mode & 0o777
+    );
+    if let Err(e) = file.set_permissions(PermissionsExt::from_mode(0o600)) {
+        log::warn!("failed to change the mode of {}: {e}", path.display());
+    }
+}
+
+fn count_since_unlock(log: impl BufRead) -> u32 {
+    let mut failed = 0;
+    for line in log.lines().map_while(Result::ok) {
+        match line.split_whitespace().next() {
+            Some("failed") => failed += 1,
+            Some("unlocked") => failed = 0,
+            _ => {}
+        }
+    }
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_failures_after_the_last_unlock_count() {
+        let log = "failed 100\nunlocked 200\nfailed 300\nfailed 400\ngarbage\n";
+        assert_eq!(count_since_unlock(log.as_bytes()), 2);
+        assert_eq!(count_since_unlock("failed 1\nunlocked 2\n".as_bytes()), 0);
+        assert_eq!(count_since_unlock("".as_bytes()), 0);
+    }
+
+    #[test]
+    fn a_symlink_is_not_followed() {
+        let dir = std::env::temp_dir().join(format!("zlock-attempts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("target");
+        let link = dir.join("link");
+        std::fs::write(&target, "").unwrap();
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let log = AttemptLog::open(&link);
+        assert!(log.file.is_none());
+        log.failed();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
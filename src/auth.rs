@@ -0,0 +1,448 @@
+use crate::{privilege, LockError};
+use std::{
+    ffi::{CStr, CString},
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    str::{FromStr, Utf8Error},
+};
+
+#[cfg(feature = "pam")]
+const PAM_SERVICE: &str = "zlock";
+
+/// What a submitted password turned out to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Auth {
+    /// Unlocks
+    Correct,
+    /// Keeps the lock up and counts as a failed attempt
+    Incorrect,
+    /// The duress password: the lock stays and it looks like a wrong password, but the
+    /// duress command runs if one is configured
+    Duress,
+}
+
+/// Checks the passwords typed into a [`crate::Lock`], in place of the configured backends,
+/// PIN and duress password; for credentials zlock doesn't know about, like a hardware
+/// token. Backoff, lockout and the failure count still apply to what it rejects.
+///
+/// ```no_run
+/// use zlock::{Auth, Authenticator, Lock, Locker};
+///
+/// // one-time codes, each good for a single unlock
+/// struct Codes(Vec<String>);
+///
+/// impl Authenticator for Codes {
+///     fn verify(&mut self, password: &str) -> Auth {
+///         match self.0.iter().position(|code| code == password) {
+///             Some(used) => {
+///                 self.0.remove(used);
+///                 Auth::Correct
+///             }
+///             None => Auth::Incorrect,
+///         }
+///     }
+/// }
+///
+/// let codes = Codes(vec!["271828".to_owned(), "314159".to_owned()]);
+/// let mut lock = Lock::builder().authenticator(codes).build()?;
+/// lock.authenticate()?;
+/// # Ok::<(), zlock::LockError>(())
+/// ```
+pub trait Authenticator: Send {
+    /// Called with every non-empty password submitted. The lock waits for the answer, so it
+    /// shouldn't take more than a moment
+    fn verify(&mut self, password: &str) -> Auth;
+}
+
+// what a lock checks with unless it's given an Authenticator: the duress password, the PIN,
+// then the backends
+pub(crate) struct SystemAuth {
+    pub(crate) backends: AuthBackends,
+    pub(crate) user: String,
+    pub(crate) pin: Option<String>,
+    pub(crate) duress: Option<Duress>,
+}
+
+impl Authenticator for SystemAuth {
+    fn verify(&mut self, password: &str) -> Auth {
+        self.backends.check(
+            &self.user,
+            self.pin.as_deref(),
+            self.duress.as_ref(),
+            password,
+        )
+    }
+}
+
+// a second password that never unlocks, only runs `cmd`
+#[derive(Debug, Clone)]
+pub(crate) struct Duress {
+    pub(crate) hash: String,
+    pub(crate) cmd: String,
+}
+
+/// How typed passwords are verified
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuthBackend {
+    /// Compare against the crypt hash from shadow (or passwd)
+    #[cfg_attr(not(feature = "pam"), default)]
+    Shadow,
+    /// Run a PAM conversation against the `zlock` service
+    #[cfg(feature = "pam")]
+    #[default]
+    Pam,
+}
+
+impl AuthBackend {
+    // shadow needs the hash readable before the screen is locked, otherwise nothing could
+    // unlock it. PAM never touches shadow here: pam_unix hands the compare to its setuid
+    // unix_chkpwd helper, which only verifies the invoking user's own password. Either way
+    // this runs before anything is grabbed, a lock that can't check passwords never starts
+    pub(crate) fn preflight(&self, user: &str) -> Result<(), LockError> {
+        let name = CString::new(user)
+            .map_err(|_| LockError::HashUnavailable("user name contains a nul byte"))?;
+        if unsafe { libc::getpwnam(name.as_ptr()) }.is_null() {
+            return Err(LockError::AuthUnavailable(format!(
+                "there's no user {user}"
+            )));
+        }
+        match self {
+            AuthBackend::Shadow => match get_hash(user) {
+                Err(LockError::HashUnavailable(_)) if !privilege::root() => {
+                    Err(LockError::AuthUnavailable(
+                        "/etc/shadow is only readable by root, run zlock as root (or setuid \
+                         root) or build it with --features pam"
+                            .to_owned(),
+                    ))
+                }
+                hash => hash.map(drop),
+            },
+            #[cfg(feature = "pam")]
+            AuthBackend::Pam => {
+                // PAM would fall back to the `other` service, which usually denies everyone
+                if !pam_service_exists(PAM_SERVICE) {
+                    return Err(LockError::AuthUnavailable(format!(
+                        "there's no PAM service {PAM_SERVICE}, create /etc/pam.d/{PAM_SERVICE}"
+                    )));
+                }
+                let uid = unsafe { libc::getuid() };
+                if !privilege::root() && user_name(uid).as_deref() != Some(user) {
+                    log::warn!("pam: not running as root, the password of {user} may be rejected");
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn verify(&self, user: &str, pass: &str) -> Auth {
+        match self {
+            AuthBackend::Shadow => pass_check(user, pass),
+            #[cfg(feature = "pam")]
+            AuthBackend::Pam => privilege::privileged(|| pam_check(PAM_SERVICE, user, pass)),
+        }
+    }
+}
+
+/// Password backends tried in order until one accepts the password, e.g. `pam,shadow`.
+/// A fingerprint reader, the control socket and `run` unlock alongside whichever they are
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthBackends(Vec<AuthBackend>);
+
+impl AuthBackends {
+    /// Duplicates are dropped, no backends at all means the default one
+    pub fn new(backends: impl IntoIterator<Item = AuthBackend>) -> Self {
+        let mut unique = Vec::new();
+        for backend in backends {
+            if !unique.contains(&backend) {
+                unique.push(backend);
+            }
+        }
+        if unique.is_empty() {
+            return Self::default();
+        }
+        Self(unique)
+    }
+
+    // the backends that can check passwords for `user` here; one that can't is dropped with
+    // a warning as long as another one can, otherwise the first one's error stops the lock
+    pub(crate) fn preflight(&self, user: &str) -> Result<AuthBackends, LockError> {
+        let mut usable = Vec::new();
+        let mut first_error = None;
+        for backend in &self.0 {
+            match backend.preflight(user) {
+                Ok(()) => usable.push(*backend),
+                Err(e) => {
+                    log::warn!("{backend:?}: {e}");
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match first_error {
+            Some(e) if usable.is_empty() => Err(e),
+            _ => Ok(Self(usable)),
+        }
+    }
+
+    // `pin` is a crypt(3) hash from the config, never from shadow and never the PIN itself;
+    // it's tried first since it's cheap next to a PAM conversation with a fail delay. The
+    // duress hash goes before anything else so it wins even if it matches a real password.
+    // The backends follow in order and the first to accept wins, the rest never see it
+    pub(crate) fn check(
+        &self,
+        user: &str,
+        pin: Option<&str>,
+        duress: Option<&Duress>,
+        pass: &str,
+    ) -> Auth {
+        if duress.is_some_and(|duress| pwhash::unix::verify(pass, &duress.hash)) {
+            return Auth::Duress;
+        }
+        if pin.is_some_and(|hash| pwhash::unix::verify(pass, hash)) {
+            log::debug!("unlocked with the PIN");
+            return Auth::Correct;
+        }
+        for backend in &self.0 {
+            if let Auth::Correct = backend.verify(user, pass) {
+                log::debug!("unlocked through {backend:?}");
+                return Auth::Correct;
+            }
+        }
+        Auth::Incorrect
+    }
+}
+
+impl Default for AuthBackends {
+    fn default() -> Self {
+        Self(vec![AuthBackend::default()])
+    }
+}
+
+impl From<AuthBackend> for AuthBackends {
+    fn from(backend: AuthBackend) -> Self {
+        Self(vec![backend])
+    }
+}
+
+impl FromStr for AuthBackends {
+    type Err = String;
+
+    /// Backend names separated by spaces or commas, in the order they're tried
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let backends = s
+            .split([' ', ','])
+            .filter(|name| !name.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if backends.is_empty() {
+            return Err("expected at least one auth backend, shadow or pam".to_owned());
+        }
+        Ok(Self::new(backends))
+    }
+}
+
+impl FromStr for AuthBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shadow" => Ok(AuthBackend::Shadow),
+            #[cfg(feature = "pam")]
+            "pam" => Ok(AuthBackend::Pam),
+            #[cfg(not(feature = "pam"))]
+            "pam" => Err("zlock was built without the pam feature".to_owned()),
+            other => Err(format!(
+                "invalid auth backend `{other}`, expected shadow or pam"
+            )),
+        }
+    }
+}
+
+#[cfg(feature = "pam")]
+fn pam_check(service: &str, user: &str, pass: &str) -> Auth {
+    // any failure along the way (missing user, module error, timeout) is treated as a failed attempt
+    let mut authenticator = match pam::Authenticator::with_password(service) {
+        Ok(authenticator) => authenticator,
+        Err(e) => {
+            log::warn!("pam: failed to start the `{service}` service: {e}");
+            return Auth::Incorrect;
+        }
+    };
+    authenticator.get_handler().set_credentials(user, pass);
+    match authenticator.authenticate() {
+        Ok(()) => Auth::Correct,
+        Err(e) => {
+            log::debug!("pam: {e}");
+            Auth::Incorrect
+        }
+    }
+}
+
+// Linux-PAM reads /etc/pam.d, then the vendor directory; without /etc/pam.d at all every
+// service lives in /etc/pam.conf
+#[cfg(feature = "pam")]
+fn pam_service_exists(service: &str) -> bool {
+    use std::path::Path;
+    let etc = Path::new("/etc/pam.d");
+    if !etc.is_dir() {
+        return Path::new("/etc/pam.conf").is_file();
+    }
+    [etc, Path::new("/usr/lib/pam.d")]
+        .iter()
+        .any(|dir| dir.join(service).is_file())
+}
+
+fn pass_check(user: &str, pass: &str) -> Auth {
+    let hash = match get_hash(user) {
+        Ok(hash) => hash,
+        Err(e) => {
+            log::warn!("{e}");
+            return Auth::Incorrect;
+        }
+    };
+    if pwhash::unix::verify(pass, &hash) {
+        return Auth::Correct;
+    }
+    Auth::Incorrect
+}
+
+// looked up on every attempt, a password changed while locked takes effect right away.
+// The only thing a setuid zlock needs its privileges for
+pub(crate) fn get_hash(user: &str) -> Result<String, LockError> {
+    let name = CString::new(user)
+        .map_err(|_| LockError::HashUnavailable("user name contains a nul byte"))?;
+    match privilege::privileged(|| shadow_hash(&name))? {
+        Some(hash) => Ok(hash),
+        None => passwd_hash(&name)?.ok_or(LockError::HashUnavailable(
+            "make sure the executable is running as root",
+        )),
+    }
+}
+
+/// The account whose password unlocks: `user` if given, else the owner of the terminal
+/// zlock was started from (the logged-in user even under sudo), else `$USER`, else the
+/// account of the real uid. Only root may pick an account other than its own
+pub(crate) fn resolve_user(user: Option<&str>) -> Result<String, LockError> {
+    let uid = unsafe { libc::getuid() };
+    let user = pick_user(
+        user,
+        tty_owner,
+        || std::env::var("USER").ok(),
+        || user_name(uid),
+    )?;
+    if !may_check(&user, uid, user_name(uid).as_deref()) {
+        return Err(LockError::AuthUnavailable(format!(
+            "only root can check the password of {user}"
+        )));
+    }
+    Ok(user)
+}
+
+// resolve_user with the lookups passed in, each only made if the ones before found nothing
+fn pick_user(
+    user: Option<&str>,
+    tty_owner: impl FnOnce() -> Option<String>,
+    env_user: impl FnOnce() -> Option<String>,
+    uid_user: impl FnOnce() -> Option<String>,
+) -> Result<String, LockError> {
+    user.map(str::to_owned)
+        .or_else(tty_owner)
+        .or_else(env_user)
+        .or_else(uid_user)
+        .ok_or(LockError::HashUnavailable(
+            "cannot tell which user to authenticate",
+        ))
+}
+
+// anyone may check their own password, only root someone else's: otherwise a setuid zlock
+// would answer for every account on the machine, in every mode
+fn may_check(user: &str, uid: libc::uid_t, own: Option<&str>) -> bool {
+    uid == 0 || own == Some(user)
+}
+
+// root owning the terminal says nothing, e.g. a console logged in as root or /dev/null
+fn tty_owner() -> Option<String> {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+        return None;
+    }
+    let meta = std::fs::metadata("/proc/self/fd/0").ok()?;
+    if !meta.file_type().is_char_device() || meta.uid() == 0 {
+        return None;
+    }
+    user_name(meta.uid())
+}
+
+fn user_name(uid: libc::uid_t) -> Option<String> {
+    let info = unsafe { libc::getpwuid(uid) };
+    if info.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr((*info).pw_name) };
+    name.to_str().ok().map(str::to_owned)
+}
+
+fn shadow_hash(name: &CStr) -> Result<Option<String>, LockError> {
+    let info = unsafe { libc::getspnam(name.as_ptr()) };
+    if info.is_null() {
+        return Ok(None);
+    }
+    let pass = unsafe { CStr::from_ptr((*info).sp_pwdp) };
+    Ok(Some(pass.to_str().map_err(invalid_hash)?.to_owned()))
+}
+
+fn invalid_hash(_: Utf8Error) -> LockError {
+    LockError::HashUnavailable("cannot convert to String")
+}
+
+// minimal systems may keep the hash in passwd itself; "x", "*" and friends are placeholders, not hashes
+fn passwd_hash(name: &CStr) -> Result<Option<String>, LockError> {
+    let info = unsafe { libc::getpwnam(name.as_ptr()) };
+    if info.is_null() {
+        return Ok(None);
+    }
+    let pass = unsafe { CStr::from_ptr((*info).pw_passwd) }
+        .to_str()
+        .map_err(invalid_hash)?;
+    let is_des = pass.len() == 13
+        && pass
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'/');
+    if !pass.starts_with('$') && !is_des {
+        return Ok(None);
+    }
+    Ok(Some(pass.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{may_check, pick_user};
+
+    fn some(name: &str) -> impl FnOnce() -> Option<String> + '_ {
+        move || Some(name.to_owned())
+    }
+
+    #[test]
+    fn the_configured_user_comes_first_then_the_terminal_owner() {
+        let user = pick_user(Some("alice"), some("bob"), some("carol"), some("dave"));
+        assert_eq!(user.unwrap(), "alice");
+        let user = pick_user(None, some("bob"), some("carol"), some("dave"));
+        assert_eq!(user.unwrap(), "bob");
+        let user = pick_user(None, || None, some("carol"), some("dave"));
+        assert_eq!(user.unwrap(), "carol");
+    }
+
+    // service managers often start programs without $USER; the account behind the real uid
+    // stands in for it
+    #[test]
+    fn a_missing_user_variable_falls_back_to_the_uid() {
+        let user = pick_user(None, || None, || None, some("dave"));
+        assert_eq!(user.unwrap(), "dave");
+        assert!(pick_user(None, || None, || None, || None).is_err());
+    }
+
+    #[test]
+    fn only_root_may_check_another_account() {
+        assert!(may_check("alice", 1000, Some("alice")));
+        assert!(!may_check("root", 1000, Some("alice")));
+        assert!(!may_check("alice", 1000, None));
+        assert!(may_check("alice", 0, Some("root")));
+    }
+}
@@ -0,0 +1,44 @@
+use std::ffi::CString;
+
+// the time drawn above the indicator, refreshed whenever the formatted text changes
+pub(crate) struct Clock {
+    format: String,
+    text: String,
+}
+
+impl Clock {
+    pub(crate) fn new(format: String) -> Self {
+        Self {
+            format,
+            text: String::new(),
+        }
+    }
+
+    pub(crate) fn text(&self) -> &str {
+        &self.text
+    }
+
+    // true when the text changed and needs to be drawn again
+    pub(crate) fn tick(&mut self) -> bool {
+        let text = format_time(&self.format);
+        if text == self.text {
+            return false;
+        }
+        self.text = text;
+        true
+    }
+}
+
+pub(crate) fn format_time(format: &str) -> String {
+    let Ok(format) = CString::new(format) else {
+        return String::new();
+    };
+    let mut buf = [0u8; 256];
+    let len = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        libc::strftime(buf.as_mut_ptr().cast(), buf.len(), format.as_ptr(), &tm)
+    };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
@@ -0,0 +1,107 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    str::FromStr,
+};
+use xcb::x;
+
+/// Background of the lock window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    /// A fixed color as `0xRRGGBB`
+    Rgb(u32),
+    /// A different random color every time the screen is locked
+    Random,
+}
+
+impl Background {
+    pub(crate) fn resolve(self) -> u32 {
+        match self {
+            Background::Rgb(rgb) => rgb,
+            // RandomState is seeded per instance, good enough for picking a color
+            Background::Random => RandomState::new().build_hasher().finish() as u32 & 0xffffff,
+        }
+    }
+}
+
+impl From<u32> for Background {
+    fn from(rgb: u32) -> Self {
+        Background::Rgb(rgb)
+    }
+}
+
+impl FromStr for Background {
+    type Err = String;
+
+    /// Accepts `random`, `#RRGGBB` or `RRGGBB`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("random") {
+            return Ok(Background::Random);
+        }
+        parse_rgb(s)
+            .map(Background::Rgb)
+            .ok_or_else(|| format!("invalid color `{s}`, expected #RRGGBB or random"))
+    }
+}
+
+pub(crate) fn parse_rgb(s: &str) -> Option<u32> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+// mixes two 0xRRGGBB colors channel by channel, `level` 0 is all `from`, 255 all `to`
+pub(crate) fn blend(from: u32, to: u32, level: u8) -> u32 {
+    [16, 8, 0].into_iter().fold(0, |rgb, shift| {
+        let (a, b) = ((from >> shift & 0xff) as i32, (to >> shift & 0xff) as i32);
+        rgb | ((a + (b - a) * level as i32 / 255) as u32) << shift
+    })
+}
+
+// a 32-bit TrueColor visual, which compositors treat as carrying an alpha channel
+pub(crate) fn argb_visual(screen: &x::Screen) -> Option<x::Visualid> {
+    screen
+        .allowed_depths()
+        .filter(|depth| depth.depth() == 32)
+        .flat_map(|depth| depth.visuals())
+        .find(|visual| visual.class() == x::VisualClass::TrueColor)
+        .map(|visual| visual.visual_id())
+}
+
+// scales each 8 bit channel into the masks of the screen's root visual; visuals without
+// masks (PseudoColor and friends) get the value as is
+pub(crate) fn rgb_to_pixel(screen: &x::Screen, rgb: u32) -> u32 {
+    pixel_converter(screen)(rgb)
+}
+
+// looks the visual up once, for converting many colors in a row
+pub(crate) fn pixel_converter(screen: &x::Screen) -> impl Fn(u32) -> u32 {
+    let masks = screen
+        .allowed_depths()
+        .flat_map(|depth| depth.visuals())
+        .find(|visual| visual.visual_id() == screen.root_visual())
+        .filter(|visual| {
+            matches!(
+                visual.class(),
+                x::VisualClass::TrueColor | x::VisualClass::DirectColor
+            )
+        })
+        .map(|visual| (visual.red_mask(), visual.green_mask(), visual.blue_mask()));
+    move |rgb| match masks {
+        Some((red, green, blue)) => {
+            scale(rgb >> 16, red) | scale(rgb >> 8, green) | scale(rgb, blue)
+        }
+        None => rgb,
+    }
+}
+
+fn scale(channel: u32, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+    let max = (1u64 << mask.count_ones()) - 1;
+    let value = (channel & 0xff) as u64 * max / 0xff;
+    (value as u32) << mask.trailing_zeros()
+}
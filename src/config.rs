@@ -0,0 +1,776 @@
+use crate::{
+    auth::Duress, color::parse_rgb, input::MAX_BUF_SIZE, lock::Lockout, privilege, AuthBackends,
+    Background, Colors, EscapeKey, KeyAction, PointerMode, ScaleMode, StatusField, TextPosition,
+    UserLine, WrongPassword,
+};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const MAX_DOTS: usize = 16;
+
+/// Settings for a lock, optionally read from `$XDG_CONFIG_HOME/zlock/config` (one
+/// `key = value` per line, values may be quoted)
+#[derive(Debug)]
+pub struct Config {
+    /// Account whose password unlocks, the owner of the terminal or `$USER` if unset
+    pub user: Option<String>,
+    /// crypt(3) hash of a PIN that unlocks as well as the password, e.g. from `mkpasswd`
+    pub pin_hash: Option<String>,
+    /// crypt(3) hash of a duress password, which runs `duress_cmd` instead of unlocking
+    pub duress_hash: Option<String>,
+    /// Command run when the duress password is entered
+    pub duress_cmd: Option<String>,
+    /// Keys sent on instead of typed, keysym names or `media`, see
+    /// [`crate::LockBuilder::passthrough_keys`]
+    pub passthrough_keys: Vec<String>,
+    /// Keysym names that submit, clear and delete instead of the defaults,
+    /// the default key if empty
+    pub submit_keys: Vec<String>,
+    pub clear_keys: Vec<String>,
+    pub delete_keys: Vec<String>,
+    /// What the pointer looks like and does over the lock windows
+    pub pointer: PointerMode,
+    /// Let the volume keys work through `pactl` while locked
+    pub volume_keys: bool,
+    /// Draw the lock windows with a 32-bit ARGB visual, for compositors
+    pub argb: bool,
+    /// Also grab every physical keyboard and pointer through XInput2
+    pub xi2_grab: bool,
+    /// Lock the console instead when `$DISPLAY` isn't set
+    pub tty_fallback: bool,
+    /// Core X font for on-screen text, `fixed` if unset
+    pub font: Option<String>,
+    /// Write `locked`/`unlocked` to `$XDG_RUNTIME_DIR/zlock.state`
+    pub state_file: bool,
+    /// Log the time of every wrong password and unlock here, see
+    /// [`crate::LockBuilder::attempt_log`]
+    pub attempt_log: Option<PathBuf>,
+    /// Base32 TOTP secret asked for after the password, see
+    /// [`crate::LockBuilder::totp_secret_file`]
+    pub totp_secret_file: Option<PathBuf>,
+    /// Also unlock with a fingerprint through fprintd, needs the `fprint` feature
+    pub fingerprint: bool,
+    /// Unlock on `unlock` sent to `$XDG_RUNTIME_DIR/zlock.sock`, see
+    /// [`crate::LockBuilder::control_socket`]
+    pub control_socket: bool,
+    /// Token a control socket client has to send after `unlock`
+    pub control_token: Option<String>,
+    /// Wrong passwords per lock after which the lockout fires, see [`crate::LockBuilder::lockout`]
+    pub lockout_attempts: Option<u32>,
+    /// Command spawned by the lockout; without one the lock stops accepting passwords
+    pub lockout_cmd: Option<String>,
+    /// Wrong passwords in a row before each further one waits, 3 if unset
+    pub retry_limit: Option<u32>,
+    /// The longest wait after a wrong password, 30s if unset
+    pub backoff_cap: Option<Duration>,
+    /// Shell command spawned right before the screen is grabbed
+    pub pre_lock: Option<String>,
+    /// Shell command spawned once the user has authenticated
+    pub post_unlock: Option<String>,
+    /// X bell volume (-100 to 100, relative to the base volume) rung on a rejected password,
+    /// silent if unset
+    pub bell: Option<i8>,
+    /// Shell command spawned on a rejected password, e.g. to play a sound
+    pub bell_cmd: Option<String>,
+    /// Idle time after which the monitors are powered off while locked, never if unset
+    pub dpms: Option<Duration>,
+    /// Switch the X screensaver and DPMS timeouts off while locked
+    pub inhibit_screensaver: bool,
+    /// Idle time after which the machine is suspended while locked, needs the `logind` feature
+    pub suspend_after: Option<Duration>,
+    /// How long a half-typed password survives without keystrokes, 10s if unset
+    pub input_timeout: Option<Duration>,
+    /// How long the indicator flashes on input or a wrong password, 2s if unset
+    pub indicator_timeout: Option<Duration>,
+    /// Holding BackSpace this long clears the whole password, never if unset
+    pub backspace_hold: Option<Duration>,
+    pub escape: EscapeKey,
+    /// Whether a wrong password clears what was typed or keeps it to fix
+    pub wrong_password: WrongPassword,
+    /// Check the password once this many characters are typed, only on Enter if unset
+    pub auto_submit_len: Option<usize>,
+    /// Frame interval of the breathing idle indicator, still if unset
+    pub breathe: Option<Duration>,
+    /// How long the lock takes to fade in, at once if unset
+    pub fade_in: Option<Duration>,
+    /// How long the lock takes to fade out once unlocked, at once if unset
+    pub fade_out: Option<Duration>,
+    /// How long the whole screen flashes `colors.flash` after a wrong password, never if unset
+    pub flash: Option<Duration>,
+    /// Longest password accepted in bytes, further keys are ignored. The password buffer
+    /// is allocated at this size once, so it never has to grow and copy itself
+    pub max_password_len: usize,
+    /// strftime(3) format of the clock shown above the indicator, no clock if unset
+    pub clock: Option<String>,
+    /// Glyph drawn once per typed character below the indicator, no dots if unset
+    pub dots: Option<char>,
+    /// Show the number of wrong passwords entered while locked
+    pub show_failures: bool,
+    /// Whose password is asked for, shown next to the indicator
+    pub show_user: UserLine,
+    /// Draw a keyboard to type on by touch or mouse
+    pub osk: bool,
+    pub user_position: TextPosition,
+    /// Battery, time and network fields of the status line, none for no line
+    pub status: Vec<StatusField>,
+    pub status_position: TextPosition,
+    /// Most dots ever drawn, so long passwords don't give away their length
+    pub max_dots: usize,
+    pub colors: Colors,
+    /// Black if unset
+    pub background: Option<Background>,
+    /// PNG or JPEG drawn over the background, needs the `image` feature
+    pub image: Option<PathBuf>,
+    pub image_mode: ScaleMode,
+    /// Backgrounds for single outputs by RandR name, the last one for an output wins
+    pub output_backgrounds: Vec<(String, Background)>,
+    /// Images for single outputs by RandR name, fitted by `image_mode`
+    pub output_images: Vec<(String, PathBuf)>,
+    /// Lock only this RandR output, the whole desktop if unset
+    pub lock_output: Option<String>,
+    /// Tried in order until one accepts the password
+    pub backends: AuthBackends,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            user: None,
+            pin_hash: None,
+            duress_hash: None,
+            duress_cmd: None,
+            pointer: PointerMode::Hidden,
+            passthrough_keys: Vec::new(),
+            submit_keys: Vec::new(),
+            clear_keys: Vec::new(),
+            delete_keys: Vec::new(),
+            volume_keys: false,
+            argb: false,
+            xi2_grab: false,
+            tty_fallback: false,
+            font: None,
+            state_file: false,
+            attempt_log: None,
+            totp_secret_file: None,
+            fingerprint: false,
+            control_socket: false,
+            control_token: None,
+            lockout_attempts: None,
+            lockout_cmd: None,
+            retry_limit: None,
+            backoff_cap: None,
+            pre_lock: None,
+            post_unlock: None,
+            bell: None,
+            bell_cmd: None,
+            dpms: None,
+            inhibit_screensaver: false,
+            suspend_after: None,
+            input_timeout: None,
+            indicator_timeout: None,
+            backspace_hold: None,
+            escape: EscapeKey::default(),
+            wrong_password: WrongPassword::default(),
+            auto_submit_len: None,
+            breathe: None,
+            fade_in: None,
+            fade_out: None,
+            flash: None,
+            max_password_len: MAX_BUF_SIZE,
+            clock: None,
+            dots: None,
+            max_dots: MAX_DOTS,
+            show_failures: false,
+            show_user: UserLine::default(),
+            osk: false,
+            user_position: TextPosition::default(),
+            status: Vec::new(),
+            status_position: TextPosition::Below,
+            colors: Colors::default(),
+            background: None,
+            image: None,
+            image_mode: ScaleMode::default(),
+            output_backgrounds: Vec::new(),
+            output_images: Vec::new(),
+            lock_output: None,
+            backends: AuthBackends::default(),
+        }
+    }
+}
+
+impl Config {
+    // the rebound editing keys, in the form `LockBuilder::bind_keys` takes them
+    pub(crate) fn key_bindings(&self) -> Vec<(KeyAction, Vec<String>)> {
+        vec![
+            (KeyAction::Submit, self.submit_keys.clone()),
+            (KeyAction::Clear, self.clear_keys.clone()),
+            (KeyAction::Delete, self.delete_keys.clone()),
+        ]
+    }
+
+    /// Reads the config file; a missing file just means defaults, a broken one is reported and skipped
+    pub fn load() -> Self {
+        match Config::path() {
+            Some(path) => Config::load_from(&path),
+            None => {
+                let mut config = Config::default();
+                config.apply_env(env_vars());
+                config
+            }
+        }
+    }
+
+    /// Reads the config file at `path` instead of the default location. Run setuid, the keys
+    /// naming commands, files or the user are skipped until [`crate::drop_privileges`] is called
+    pub fn load_from(path: &Path) -> Self {
+        let mut config = Config::default();
+        match std::fs::read_to_string(path) {
+            Ok(contents) => config.parse(&contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("failed to read {}: {e}", path.display()),
+        }
+        config.apply_env(env_vars());
+        config
+    }
+
+    // `ZLOCK_<KEY>` sets the config key of the same name, lowercased, over whatever the file
+    // said; the values are taken as they are, without the file's quoting
+    fn apply_env(&mut self, vars: impl IntoIterator<Item = (String, String)>) {
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix("ZLOCK_") else {
+                continue;
+            };
+            let key = match key.to_ascii_lowercase() {
+                key if key == "bg" => "background".to_owned(),
+                key => key,
+            };
+            self.set(&key, value);
+        }
+    }
+
+    // the format isn't TOML, so the file isn't named like it; a config.toml from before is
+    // still read when there's nothing else
+    fn path() -> Option<PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        let dir = base.join("zlock");
+        let config = dir.join("config");
+        let toml = dir.join("config.toml");
+        if !config.exists() && toml.exists() {
+            log::warn!(
+                "{} is read as zlock's own `key = value` format, not TOML; rename it to config",
+                toml.display()
+            );
+            return Some(toml);
+        }
+        Some(config)
+    }
+
+    // both halves are needed, a duress password that does nothing would only be confusing
+    pub(crate) fn duress(&self) -> Option<Duress> {
+        match (&self.duress_hash, &self.duress_cmd) {
+            (Some(hash), Some(cmd)) => Some(Duress {
+                hash: hash.clone(),
+                cmd: cmd.clone(),
+            }),
+            (None, None) => None,
+            _ => {
+                log::warn!("duress_hash and duress_cmd only work together, ignoring them");
+                None
+            }
+        }
+    }
+
+    // the same for every locker, however lockout_attempts was set
+    pub(crate) fn lockout(&self) -> Option<Lockout> {
+        self.lockout_attempts
+            .map(|attempts| Lockout::new(attempts, self.lockout_cmd.clone()))
+    }
+
+    fn parse(&mut self, contents: &str) {
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                log::warn!("ignoring malformed config line: {line}");
+                continue;
+            };
+            let Some(value) = unquote(value.trim()) else {
+                log::warn!("ignoring malformed config line: {line}");
+                continue;
+            };
+            let key = key.trim();
+            if privilege::elevated() && runs_or_opens(key) {
+                log::warn!("running with elevated privileges, ignoring config key: {key}");
+                continue;
+            }
+            self.set(key, value);
+        }
+    }
+
+    // one setting, from the file or the environment
+    fn set(&mut self, key: &str, value: String) {
+        match key {
+            "user" => self.user = Some(value),
+            // only ever a hash, a plain PIN in the config would be readable by anyone who
+            // can read the file
+            "pin_hash" if value.starts_with('$') => self.pin_hash = Some(value),
+            "pin_hash" => log::warn!("pin_hash expects a crypt(3) hash such as $6$..."),
+            "duress_hash" if value.starts_with('$') => self.duress_hash = Some(value),
+            "duress_hash" => log::warn!("duress_hash expects a crypt(3) hash such as $6$..."),
+            "duress_cmd" => self.duress_cmd = Some(value),
+            "control_socket" => match value.parse() {
+                Ok(enable) => self.control_socket = enable,
+                Err(_) => log::warn!("control_socket expects true or false"),
+            },
+            "control_token" => self.control_token = Some(value),
+            "fingerprint" => match value.parse() {
+                Ok(enable) => self.fingerprint = enable,
+                Err(_) => log::warn!("fingerprint expects true or false"),
+            },
+            "state_file" => match value.parse() {
+                Ok(enable) => self.state_file = enable,
+                Err(_) => log::warn!("state_file expects true or false"),
+            },
+            "attempt_log" => self.attempt_log = Some(PathBuf::from(value)),
+            "totp_secret_file" => self.totp_secret_file = Some(PathBuf::from(value)),
+            "argb" => match value.parse() {
+                Ok(enable) => self.argb = enable,
+                Err(_) => log::warn!("argb expects true or false"),
+            },
+            "xi2_grab" => match value.parse() {
+                Ok(enable) => self.xi2_grab = enable,
+                Err(_) => log::warn!("xi2_grab expects true or false"),
+            },
+            "tty_fallback" => match value.parse() {
+                Ok(enable) => self.tty_fallback = enable,
+                Err(_) => log::warn!("tty_fallback expects true or false"),
+            },
+            "passthrough_keys" => self.passthrough_keys = parse_keys(&value),
+            "submit_keys" => self.submit_keys = parse_keys(&value),
+            "clear_keys" => self.clear_keys = parse_keys(&value),
+            "delete_keys" => self.delete_keys = parse_keys(&value),
+            "pointer" => match value.parse() {
+                Ok(mode) => self.pointer = mode,
+                Err(e) => log::warn!("{e}"),
+            },
+            // from before `pointer`, still read
+            "hide_cursor" => match value.parse() {
+                Ok(true) => self.pointer = PointerMode::Hidden,
+                Ok(false) => self.pointer = PointerMode::Visible,
+                Err(_) => log::warn!("hide_cursor expects true or false"),
+            },
+            "volume_keys" => match value.parse() {
+                Ok(enable) => self.volume_keys = enable,
+                Err(_) => log::warn!("volume_keys expects true or false"),
+            },
+            "lockout_attempts" => match value.parse() {
+                Ok(0) | Err(_) => log::warn!("lockout_attempts expects a positive number"),
+                Ok(attempts) => self.lockout_attempts = Some(attempts),
+            },
+            "lockout_cmd" => self.lockout_cmd = Some(value),
+            "retry_limit" => match value.parse() {
+                Ok(attempts) => self.retry_limit = Some(attempts),
+                Err(_) => log::warn!("retry_limit expects a number of attempts"),
+            },
+            "backoff_cap" => match value.parse() {
+                Ok(secs) => self.backoff_cap = Some(Duration::from_secs(secs)),
+                Err(_) => log::warn!("backoff_cap expects a number of seconds"),
+            },
+            "pre_lock" => self.pre_lock = Some(value),
+            "post_unlock" => self.post_unlock = Some(value),
+            "lock_output" => self.lock_output = Some(value),
+            "bell" => match parse_bell(&value) {
+                Ok(bell) => self.bell = bell,
+                Err(e) => log::warn!("{e}"),
+            },
+            "bell_cmd" => self.bell_cmd = Some(value),
+            "background" => match value.parse() {
+                Ok(background) => self.background = Some(background),
+                Err(e) => log::warn!("{e}"),
+            },
+            "clock" => self.clock = Some(value),
+            "dots" => match parse_glyph(&value) {
+                Ok(glyph) => self.dots = Some(glyph),
+                Err(e) => log::warn!("{e}"),
+            },
+            "font" => self.font = Some(value),
+            "text_color" => match parse_color(&value) {
+                Ok(rgb) => self.colors.text = rgb,
+                Err(e) => log::warn!("{e}"),
+            },
+            "breathe_color" => match parse_color(&value) {
+                Ok(rgb) => self.colors.breathe = rgb,
+                Err(e) => log::warn!("{e}"),
+            },
+            "idle_color" => match parse_color(&value) {
+                Ok(rgb) => self.colors.idle = rgb,
+                Err(e) => log::warn!("{e}"),
+            },
+            "input_color" => match parse_color(&value) {
+                Ok(rgb) => self.colors.input = rgb,
+                Err(e) => log::warn!("{e}"),
+            },
+            "error_color" => match parse_color(&value) {
+                Ok(rgb) => self.colors.error = rgb,
+                Err(e) => log::warn!("{e}"),
+            },
+            "code_color" => match parse_color(&value) {
+                Ok(rgb) => self.colors.code = rgb,
+                Err(e) => log::warn!("{e}"),
+            },
+            "flash_color" => match parse_color(&value) {
+                Ok(rgb) => self.colors.flash = rgb,
+                Err(e) => log::warn!("{e}"),
+            },
+            "cursor_color" => match parse_color(&value) {
+                Ok(rgb) => self.colors.cursor = rgb,
+                Err(e) => log::warn!("{e}"),
+            },
+            "cursor_outline_color" => match parse_color(&value) {
+                Ok(rgb) => self.colors.cursor_outline = rgb,
+                Err(e) => log::warn!("{e}"),
+            },
+            "caps_color" => match parse_color(&value) {
+                Ok(rgb) => self.colors.caps = rgb,
+                Err(e) => log::warn!("{e}"),
+            },
+            "dots_color" => match parse_color(&value) {
+                Ok(rgb) => self.colors.dots = rgb,
+                Err(e) => log::warn!("{e}"),
+            },
+            "max_password_len" => match value.parse() {
+                Ok(len) => self.max_password_len = len,
+                Err(_) => log::warn!("max_password_len expects a number of bytes"),
+            },
+            "show_failures" => match value.parse() {
+                Ok(show) => self.show_failures = show,
+                Err(_) => log::warn!("show_failures expects true or false"),
+            },
+            "max_dots" => match value.parse() {
+                Ok(max) => self.max_dots = max,
+                Err(_) => log::warn!("max_dots expects a number"),
+            },
+            "image" => self.image = Some(PathBuf::from(value)),
+            "auth" => match value.parse() {
+                Ok(backends) => self.backends = backends,
+                Err(e) => log::warn!("{e}"),
+            },
+            "osk" => match value.parse() {
+                Ok(osk) => self.osk = osk,
+                Err(_) => log::warn!("osk expects true or false"),
+            },
+            "show_user" => match value.parse() {
+                Ok(line) => self.show_user = line,
+                Err(e) => log::warn!("{e}"),
+            },
+            "user_position" => match value.parse() {
+                Ok(position) => self.user_position = position,
+                Err(e) => log::warn!("{e}"),
+            },
+            "status" => match parse_status(&value) {
+                Ok(fields) => self.status = fields,
+                Err(e) => log::warn!("{e}"),
+            },
+            "status_position" => match value.parse() {
+                Ok(position) => self.status_position = position,
+                Err(e) => log::warn!("{e}"),
+            },
+            "image_mode" => match value.parse() {
+                Ok(mode) => self.image_mode = mode,
+                Err(e) => log::warn!("{e}"),
+            },
+            "dpms" => match value.parse() {
+                Ok(secs) => self.dpms = Some(Duration::from_secs(secs)),
+                Err(_) => log::warn!("dpms expects a number of seconds"),
+            },
+            "inhibit_screensaver" => match value.parse() {
+                Ok(inhibit) => self.inhibit_screensaver = inhibit,
+                Err(_) => log::warn!("inhibit_screensaver expects true or false"),
+            },
+            "suspend_after" => match value.parse() {
+                Ok(secs) => self.suspend_after = Some(Duration::from_secs(secs)),
+                Err(_) => log::warn!("suspend_after expects a number of seconds"),
+            },
+            "input_timeout" => match value.parse() {
+                Ok(secs) => self.input_timeout = Some(Duration::from_secs(secs)),
+                Err(_) => log::warn!("input_timeout expects a number of seconds"),
+            },
+            "breathe_ms" => match value.parse() {
+                Ok(ms) => self.breathe = Some(Duration::from_millis(ms)),
+                Err(_) => log::warn!("breathe_ms expects a number of milliseconds"),
+            },
+            "fade_in_ms" => match value.parse() {
+                Ok(ms) => self.fade_in = Some(Duration::from_millis(ms)),
+                Err(_) => log::warn!("fade_in_ms expects a number of milliseconds"),
+            },
+            "fade_out_ms" => match value.parse() {
+                Ok(ms) => self.fade_out = Some(Duration::from_millis(ms)),
+                Err(_) => log::warn!("fade_out_ms expects a number of milliseconds"),
+            },
+            "flash_ms" => match value.parse() {
+                Ok(ms) => self.flash = Some(Duration::from_millis(ms)),
+                Err(_) => log::warn!("flash_ms expects a number of milliseconds"),
+            },
+            "backspace_hold_ms" => match value.parse() {
+                Ok(ms) => self.backspace_hold = Some(Duration::from_millis(ms)),
+                Err(_) => log::warn!("backspace_hold_ms expects a number of milliseconds"),
+            },
+            "auto_submit_len" => match value.parse() {
+                Ok(0) | Err(_) => log::warn!("auto_submit_len expects a number of characters"),
+                Ok(len) => self.auto_submit_len = Some(len),
+            },
+            "wrong_password" => match value.parse() {
+                Ok(policy) => self.wrong_password = policy,
+                Err(e) => log::warn!("{e}"),
+            },
+            "escape" => match value.parse() {
+                Ok(escape) => self.escape = escape,
+                Err(e) => log::warn!("{e}"),
+            },
+            "indicator_ms" => match value.parse() {
+                Ok(ms) => self.indicator_timeout = Some(Duration::from_millis(ms)),
+                Err(_) => log::warn!("indicator_ms expects a number of milliseconds"),
+            },
+            // `background.DP-1`, `image.HDMI-A-0`
+            other => match other.split_once('.') {
+                Some(("background", output)) => match value.parse() {
+                    Ok(background) => self
+                        .output_backgrounds
+                        .push((output.to_owned(), background)),
+                    Err(e) => log::warn!("{e}"),
+                },
+                Some(("image", output)) => self
+                    .output_images
+                    .push((output.to_owned(), PathBuf::from(value))),
+                _ => log::warn!("ignoring unknown config key: {other}"),
+            },
+        }
+    }
+}
+
+// keys that pick a command to run, a file to open or the user to check. Whoever started a
+// setuid zlock chose the config file, so these are only taken from it once the privileges
+// are dropped, as `zlock` itself does before loading
+fn runs_or_opens(key: &str) -> bool {
+    matches!(
+        key,
+        "user"
+            | "duress_cmd"
+            | "lockout_cmd"
+            | "pre_lock"
+            | "post_unlock"
+            | "bell_cmd"
+            | "attempt_log"
+            | "totp_secret_file"
+            | "image"
+    ) || key.starts_with("image.")
+}
+
+// the environment minus anything that isn't UTF-8, which no setting could use anyway.
+// Nothing at all when setuid or setgid, as glibc's secure mode does: whoever started zlock
+// picked it, and it could choose the user whose password is checked
+fn env_vars() -> Vec<(String, String)> {
+    let vars = std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+        .filter(|(name, _)| name.starts_with("ZLOCK_"));
+    if privilege::setuid() {
+        if vars.count() > 0 {
+            log::warn!("running setuid, ignoring the ZLOCK_* environment variables");
+        }
+        return Vec::new();
+    }
+    vars.collect()
+}
+
+// a "quoted" value is taken as is, minus a trailing comment; bare values are kept
+// whole since a color like #1d2021 would otherwise read as a comment
+fn unquote(value: &str) -> Option<String> {
+    let Some(quoted) = value.strip_prefix('"') else {
+        return Some(value.to_owned());
+    };
+    let (inner, rest) = quoted.split_once('"')?;
+    let rest = rest.trim_start();
+    (rest.is_empty() || rest.starts_with('#')).then(|| inner.to_owned())
+}
+
+/// A volume from -100 to 100, or `off`
+pub fn parse_bell(s: &str) -> Result<Option<i8>, String> {
+    if s.eq_ignore_ascii_case("off") {
+        return Ok(None);
+    }
+    s.parse::<i8>()
+        .ok()
+        .filter(|percent| (-100..=100).contains(percent))
+        .map(Some)
+        .ok_or_else(|| format!("invalid bell `{s}`, expected -100 to 100 or off"))
+}
+
+/// Key names separated by spaces or commas
+pub fn parse_keys(s: &str) -> Vec<String> {
+    s.split([' ', ','])
+        .filter(|name| !name.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Status line fields separated by spaces or commas, in the order they're shown
+pub fn parse_status(s: &str) -> Result<Vec<StatusField>, String> {
+    s.split([' ', ','])
+        .filter(|name| !name.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// A `#RRGGBB` color as `0xRRGGBB`
+pub fn parse_color(s: &str) -> Result<u32, String> {
+    parse_rgb(s).ok_or_else(|| format!("invalid color `{s}`, expected #RRGGBB"))
+}
+
+/// A single Latin-1 character, the only ones the core X font is guaranteed to have
+pub fn parse_glyph(s: &str) -> Result<char, String> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if u8::try_from(c).is_ok() => Ok(c),
+        _ => Err(format!(
+            "invalid glyph `{s}`, expected a single Latin-1 character"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AuthBackend;
+
+    #[test]
+    fn parses_quoted_and_bare_values() {
+        let mut config = Config::default();
+        config.parse(
+            r##"
+# comments and blank lines are skipped
+background = "#1d2021"
+clock = "%H:%M" # a trailing comment after a string
+dots_color = #dddddd
+show_failures = true
+max_dots = 8
+dpms = 60
+pre_lock = "playerctl pause"
+indicator_ms = 500
+retry_limit = 5
+backoff_cap = 60
+"##,
+        );
+        assert_eq!(config.background, Some(Background::Rgb(0x1d2021)));
+        assert_eq!(config.clock.as_deref(), Some("%H:%M"));
+        assert_eq!(config.colors.dots, 0xdddddd);
+        assert!(config.show_failures);
+        assert_eq!(config.max_dots, 8);
+        assert_eq!(config.dpms, Some(Duration::from_secs(60)));
+        assert_eq!(config.pre_lock.as_deref(), Some("playerctl pause"));
+        assert_eq!(config.indicator_timeout, Some(Duration::from_millis(500)));
+        assert_eq!(config.retry_limit, Some(5));
+        assert_eq!(config.backoff_cap, Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn the_environment_overrides_the_file() {
+        let mut config = Config::default();
+        config.parse("background = \"#1d2021\"\nclock = \"%H:%M\"\nmax_dots = 8\n");
+        config.apply_env([
+            ("ZLOCK_BG".to_owned(), "#282828".to_owned()),
+            ("ZLOCK_MAX_DOTS".to_owned(), "12".to_owned()),
+            ("ZLOCK_AUTH".to_owned(), "shadow".to_owned()),
+            ("HOME".to_owned(), "/home/someone".to_owned()),
+        ]);
+        assert_eq!(config.background, Some(Background::Rgb(0x282828)));
+        assert_eq!(config.max_dots, 12);
+        assert_eq!(config.backends, AuthBackends::from(AuthBackend::Shadow));
+        // untouched by the environment, so the file's value stands
+        assert_eq!(config.clock.as_deref(), Some("%H:%M"));
+    }
+
+    #[test]
+    fn a_bad_environment_value_keeps_the_file_value() {
+        let mut config = Config::default();
+        config.parse("max_dots = 8\n");
+        config.apply_env([("ZLOCK_MAX_DOTS".to_owned(), "many".to_owned())]);
+        assert_eq!(config.max_dots, 8);
+    }
+
+    #[test]
+    fn status_fields_keep_their_order() {
+        let mut config = Config::default();
+        config.parse("status = time, battery\nstatus_position = above\n");
+        assert_eq!(config.status, [StatusField::Time, StatusField::Battery]);
+        assert_eq!(config.status_position, TextPosition::Above);
+        config.parse("status = time, moon phase\n");
+        assert_eq!(config.status, [StatusField::Time, StatusField::Battery]);
+    }
+
+    #[test]
+    fn cursor_colors_default_to_the_decorative_circle() {
+        let mut config = Config::default();
+        assert_eq!(config.colors.cursor, 0xdddddd);
+        config.parse("cursor_color = #ff8800\ncursor_outline_color = black\n");
+        assert_eq!(config.colors.cursor, 0xff8800);
+        assert_eq!(config.colors.cursor_outline, 0x333333);
+    }
+
+    #[test]
+    fn wrong_passwords_are_cleared_unless_kept() {
+        let mut config = Config::default();
+        assert_eq!(config.wrong_password, WrongPassword::Clear);
+        config.parse("wrong_password = keep\n");
+        assert_eq!(config.wrong_password, WrongPassword::Keep);
+        config.parse("wrong_password = sometimes\n");
+        assert_eq!(config.wrong_password, WrongPassword::Keep);
+    }
+
+    #[test]
+    fn bad_lines_leave_defaults() {
+        let mut config = Config::default();
+        config.parse("max_dots = many\nclock = \"%H:%M\" trailing\nnot a setting\n");
+        assert_eq!(config.max_dots, MAX_DOTS);
+        assert_eq!(config.clock, None);
+    }
+
+    #[test]
+    fn auth_backends_are_parsed_in_order_without_duplicates() {
+        let mut config = Config::default();
+        config.parse("auth = shadow, shadow\n");
+        assert_eq!(config.backends, AuthBackends::from(AuthBackend::Shadow));
+        config.parse("auth = shadow,kerberos\n");
+        assert_eq!(config.backends, AuthBackends::from(AuthBackend::Shadow));
+    }
+
+    #[test]
+    fn the_old_hide_cursor_key_still_sets_the_pointer() {
+        let mut config = Config::default();
+        config.parse("hide_cursor = false\n");
+        assert_eq!(config.pointer, PointerMode::Visible);
+        config.parse("pointer = frozen\npointer = sideways\n");
+        assert_eq!(config.pointer, PointerMode::Frozen);
+    }
+
+    #[test]
+    fn per_output_keys_are_collected_by_name() {
+        let mut config = Config::default();
+        config.parse(
+            "background.DP-1 = #112233\nimage.HDMI-A-0 = /tmp/side.png\nbackground.DP-2 = pink\nborder.DP-1 = 1\n",
+        );
+        assert_eq!(
+            config.output_backgrounds,
+            [("DP-1".to_owned(), Background::Rgb(0x112233))]
+        );
+        assert_eq!(
+            config.output_images,
+            [("HDMI-A-0".to_owned(), PathBuf::from("/tmp/side.png"))]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd},
+        unix::{
+            fs::PermissionsExt,
+            net::{UnixListener, UnixStream},
+        },
+    },
+    path::PathBuf,
+    time::Duration,
+};
+use zeroize::Zeroize;
+
+// a client gets this long to send its line, the lock loop waits meanwhile
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+const MAX_LINE: usize = 256;
+
+// `$XDG_RUNTIME_DIR/zlock.sock`, where a process of the same user (or root) unlocks by
+// sending `unlock` or `unlock <token>`. It's a way around the password, so every client is
+// checked: the socket is 0600 in a directory only the user can enter, the peer's uid must
+// match, and with a token configured the line must carry it
+pub(crate) struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+    token: Option<String>,
+}
+
+impl ControlSocket {
+    pub(crate) fn bind(token: Option<String>) -> Option<Self> {
+        let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) else {
+            log::warn!("the control socket needs $XDG_RUNTIME_DIR, not opening it");
+            return None;
+        };
+        let path = PathBuf::from(dir).join("zlock.sock");
+        // a socket nobody answers on is left over from a lock that didn't clean up
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                log::warn!(
+                    "{} is in use, not opening the control socket",
+                    path.display()
+                );
+                return None;
+            }
+            let _ = std::fs::remove_file(&path);
+        }
+        let listener = UnixListener::bind(&path)
+            .and_then(|listener| {
+                std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+                listener.set_nonblocking(true)?;
+                Ok(listener)
+            })
+            .inspect_err(|e| log::warn!("failed to open {}: {e}", path.display()))
+            .ok()?;
+        log::debug!("listening on {}", path.display());
+        Some(Self {
+            listener,
+            path,
+            token,
+        })
+    }
+
+    // readable when a client is waiting to be accepted
+    pub(crate) fn wake_fd(&self) -> BorrowedFd<'_> {
+        self.listener.as_fd()
+    }
+
+    // serves every waiting client, true once one of them was allowed to unlock
+    pub(crate) fn unlock_requested(&self) -> bool {
+        let mut unlock = false;
+        loop {
+            match self.listener.accept() {
+                Ok((mut stream, _)) => {
+                    let allowed = self.allowed(&mut stream);
+                    let reply: &[u8] = if allowed { b"ok\n" } else { b"denied\n" };
+                    let _ = stream.write_all(reply);
+                    unlock |= allowed;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return unlock,
+                Err(e) => {
+                    log::warn!("control socket: {e}");
+                    return unlock;
+                }
+            }
+        }
+    }
+
+    fn allowed(&self, stream: &mut UnixStream) -> bool {
+        let uid = unsafe { libc::getuid() };
+        match peer_uid(stream) {
+            Some(peer) if peer == uid || peer == 0 => {}
+            peer => {
+                log::warn!("control socket: refusing a client with uid {peer:?}");
+                return false;
+            }
+        }
+        let mut line = read_line(stream);
+        let allowed = match (
+            line.as_deref().and_then(|line| line.strip_prefix("unlock")),
+            &self.token,
+        ) {
+            (Some(""), None) => true,
+            (Some(rest), Some(token)) => rest
+                .strip_prefix(' ')
+                .is_some_and(|given| same_secret(given, token)),
+            _ => false,
+        };
+        if let Some(line) = &mut line {
+            line.zeroize();
+        }
+        if allowed {
+            log::info!("unlock requested over the control socket");
+        } else {
+            log::warn!("control socket: rejected a request");
+        }
+        allowed
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// one line without its newline, None if it doesn't arrive in time or isn't UTF-8
+fn read_line(stream: &mut UnixStream) -> Option<String> {
+    stream.set_nonblocking(false).ok()?;
+    stream.set_read_timeout(Some(READ_TIMEOUT)).ok()?;
+    let mut buf = [0; MAX_LINE];
+    let mut len = 0;
+    while len < MAX_LINE && !buf[..len].contains(&b'\n') {
+        match stream.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(_) => {
+                buf.zeroize();
+                return None;
+            }
+        }
+    }
+    let line = std::str::from_utf8(&buf[..len])
+        .ok()
+        .map(|text| text.lines().next().unwrap_or("").to_owned());
+    buf.zeroize();
+    line
+}
+
+pub(crate) fn peer_uid(stream: &UnixStream) -> Option<libc::uid_t> {
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    (res == 0).then_some(cred.uid)
+}
+
+// compares every byte whatever the first mismatch, so timing doesn't give the token away
+fn same_secret(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
@@ -0,0 +1,129 @@
+use crate::{lock::connect, Config, Lock, LockError, Locker};
+use std::time::Duration;
+use xcb::{screensaver, sync, x, Connection};
+
+/// Locks whenever the user has been idle for `idle`. An XSync alarm on the server's idle
+/// counter wakes zlock right when that happens; without XSync the screensaver extension is
+/// polled every `poll` instead
+pub fn run_daemon(config: &Config, idle: Duration, poll: Duration) -> Result<(), LockError> {
+    let (conn, scr_no) = connect(&[xcb::Extension::ScreenSaver], &[xcb::Extension::Sync])?;
+    if let Some(alarm) = IdleAlarm::new(&conn, idle) {
+        log::debug!("waiting for the XSync idle alarm");
+        return alarm.run(&conn, config, poll);
+    }
+    log::debug!("no XSync idle counter, polling every {poll:?}");
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(scr_no as usize)
+        .expect("unexpected failure while getting screen")
+        .root();
+    loop {
+        let cookie = conn.send_request(&screensaver::QueryInfo {
+            drawable: x::Drawable::Window(root),
+        });
+        let info = conn.wait_for_reply(cookie).map_err(LockError::Connection)?;
+        if Duration::from_millis(info.ms_since_user_input().into()) >= idle {
+            log::debug!("idle for {}ms, locking", info.ms_since_user_input());
+            lock_once(config)?;
+        }
+        std::thread::sleep(poll);
+    }
+}
+
+// false when another lock was up already, nothing to do until that one is gone
+fn lock_once(config: &Config) -> Result<bool, LockError> {
+    match Lock::lock_screen(config) {
+        Ok(mut lock) => lock.authenticate().map(|()| true),
+        Err(LockError::AlreadyLocked) => {
+            log::debug!("already locked");
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// an alarm on XSync's IDLETIME counter, the milliseconds since the last input. It fires once
+// when the counter reaches the idle time and then goes inactive until armed again
+struct IdleAlarm {
+    id: sync::Alarm,
+    idle: Duration,
+}
+
+impl IdleAlarm {
+    // None without XSync or without an IDLETIME counter on the server
+    fn new(conn: &Connection, idle: Duration) -> Option<Self> {
+        if !conn
+            .active_extensions()
+            .any(|ext| ext == xcb::Extension::Sync)
+        {
+            return None;
+        }
+        // the protocol wants the version negotiated before anything else
+        let cookie = conn.send_request(&sync::Initialize {
+            desired_major_version: 3,
+            desired_minor_version: 1,
+        });
+        conn.wait_for_reply(cookie).ok()?;
+        let cookie = conn.send_request(&sync::ListSystemCounters {});
+        let counter = conn
+            .wait_for_reply(cookie)
+            .ok()?
+            .counters()
+            .find(|counter| counter.name().as_bytes() == b"IDLETIME")?
+            .counter();
+        let id = conn.generate_id();
+        // a delta of 0 is what makes the alarm fire once instead of every millisecond after
+        conn.send_and_check_request(&sync::CreateAlarm {
+            id,
+            value_list: &[
+                sync::Ca::Counter(counter),
+                sync::Ca::ValueType(sync::Valuetype::Absolute),
+                sync::Ca::Value(int64(idle)),
+                sync::Ca::TestType(sync::Testtype::PositiveComparison),
+                sync::Ca::Delta(int64(Duration::ZERO)),
+                sync::Ca::Events(1),
+            ],
+        })
+        .inspect_err(|e| log::warn!("failed to create the XSync idle alarm: {e}"))
+        .ok()?;
+        Some(Self { id, idle })
+    }
+
+    fn run(&self, conn: &Connection, config: &Config, poll: Duration) -> Result<(), LockError> {
+        loop {
+            let event = conn.wait_for_event().map_err(LockError::Connection)?;
+            let xcb::Event::Sync(sync::Event::AlarmNotify(notify)) = event else {
+                continue;
+            };
+            if notify.alarm() != self.id {
+                continue;
+            }
+            log::debug!("idle for {}ms, locking", self.idle.as_millis());
+            // the other lock keeps the counter past the idle time, an alarm armed right away
+            // would fire again at once
+            if !lock_once(config)? {
+                std::thread::sleep(poll);
+            }
+            self.arm(conn)?;
+        }
+    }
+
+    // changing the alarm makes it active again, to fire the next time the counter gets there
+    fn arm(&self, conn: &Connection) -> Result<(), LockError> {
+        conn.send_request(&sync::ChangeAlarm {
+            id: self.id,
+            value_list: &[sync::Ca::Value(int64(self.idle))],
+        });
+        conn.flush()
+            .map_err(|e| LockError::Connection(xcb::Error::Connection(e)))
+    }
+}
+
+fn int64(duration: Duration) -> sync::Int64 {
+    let ms = duration.as_millis().min(i64::MAX as u128) as u64;
+    sync::Int64 {
+        hi: (ms >> 32) as i32,
+        lo: ms as u32,
+    }
+}
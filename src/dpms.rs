@@ -0,0 +1,79 @@
+use crate::LockError;
+use std::time::Duration;
+use xcb::{dpms, Connection};
+
+// powers the monitors off once the lock has sat idle for `delay`, and back on at the next key
+pub(crate) struct Dpms {
+    delay: Duration,
+    // whether DPMS was enabled before we first turned the monitors off
+    was_enabled: Option<bool>,
+    off: bool,
+}
+
+impl Dpms {
+    // None if the server or its monitors can't do DPMS
+    pub(crate) fn new(conn: &Connection, delay: Duration) -> Result<Option<Self>, LockError> {
+        if !conn
+            .active_extensions()
+            .any(|ext| ext == xcb::Extension::Dpms)
+        {
+            log::warn!("the X server has no DPMS extension, monitors stay on");
+            return Ok(None);
+        }
+        let capable = conn
+            .wait_for_reply(conn.send_request(&dpms::Capable {}))
+            .map_err(LockError::Connection)?;
+        if !capable.capable() {
+            log::warn!("the monitors aren't DPMS capable, they stay on");
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            delay,
+            was_enabled: None,
+            off: false,
+        }))
+    }
+
+    pub(crate) fn tick(&mut self, conn: &Connection, idle: Duration) -> Result<(), LockError> {
+        if self.off || idle < self.delay {
+            return Ok(());
+        }
+        let enabled = match self.was_enabled {
+            Some(enabled) => enabled,
+            None => {
+                let info = conn
+                    .wait_for_reply(conn.send_request(&dpms::Info {}))
+                    .map_err(LockError::Connection)?;
+                *self.was_enabled.insert(info.state())
+            }
+        };
+        // ForceLevel is refused while DPMS is disabled
+        if !enabled {
+            conn.send_request(&dpms::Enable {});
+        }
+        conn.send_request(&dpms::ForceLevel {
+            power_level: dpms::DpmsMode::Off,
+        });
+        log::debug!("idle for {idle:?}, monitors off");
+        self.off = true;
+        Ok(())
+    }
+
+    pub(crate) fn wake(&mut self, conn: &Connection) {
+        if !self.off {
+            return;
+        }
+        conn.send_request(&dpms::ForceLevel {
+            power_level: dpms::DpmsMode::On,
+        });
+        self.off = false;
+    }
+
+    // monitors on, and DPMS disabled again if it was before
+    pub(crate) fn restore(&mut self, conn: &Connection) {
+        self.wake(conn);
+        if self.was_enabled == Some(false) {
+            conn.send_request(&dpms::Disable {});
+        }
+    }
+}
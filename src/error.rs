@@ -0,0 +1,121 @@
+use std::{error::Error, fmt};
+use xcb::x;
+
+/// Everything that can go wrong while locking or unlocking
+#[derive(Debug)]
+pub enum LockError {
+    Connect(xcb::ConnError),
+    /// `$DISPLAY` is unset, there's no X server to lock
+    NoDisplay,
+    /// Another zlock already holds the display
+    AlreadyLocked,
+    Connection(xcb::Error),
+    /// The X server went away or dropped the connection while locked
+    ConnectionLost(xcb::ConnError),
+    CreateWindow(xcb::ProtocolError),
+    Cursor(xcb::ProtocolError),
+    Font(xcb::ProtocolError),
+    GrabKeyboard(x::GrabStatus),
+    GrabPointer(x::GrabStatus),
+    HashUnavailable(&'static str),
+    /// The auth backend can't check passwords here, found before anything was grabbed
+    AuthUnavailable(String),
+    Image(String),
+    InvalidScreen(i32),
+    /// No active RandR output has the name, the ones that do are listed
+    InvalidOutput(String, Vec<String>),
+    Keymap,
+    /// Setting the effective ids back to the real ones failed
+    Privileges(std::io::Error),
+    #[cfg(feature = "logind")]
+    Logind(zbus::Error),
+    Signal(std::io::Error),
+    Stdin(std::io::Error),
+    Terminated(i32),
+    /// The second factor was asked for but its secret can't be used
+    Totp(String),
+    Tty(std::io::Error),
+    #[cfg(feature = "wayland")]
+    Wayland(String),
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::Connect(e) => write!(
+                f,
+                "failed to connect to the X server: {e} (check $DISPLAY and $XAUTHORITY)"
+            ),
+            LockError::AlreadyLocked => write!(f, "another zlock is already locking this display"),
+            LockError::NoDisplay => write!(
+                f,
+                "no X display, $DISPLAY is not set (--tty locks the console instead)"
+            ),
+            LockError::Connection(e) => write!(f, "X connection error: {e}"),
+            LockError::ConnectionLost(e) => write!(
+                f,
+                "lost the connection to the X server ({e}), the screen is no longer locked"
+            ),
+            LockError::CreateWindow(e) => write!(f, "failed to create the lock window: {e}"),
+            LockError::Cursor(e) => write!(f, "failed to create the cursor: {e}"),
+            LockError::Font(e) => write!(f, "failed to open the font: {e}"),
+            LockError::GrabKeyboard(status) => write!(f, "failed to grab the keyboard: {status:?}"),
+            LockError::GrabPointer(status) => write!(f, "failed to grab the pointer: {status:?}"),
+            LockError::HashUnavailable(reason) => {
+                write!(f, "failed to acquire password hash: {reason}")
+            }
+            LockError::AuthUnavailable(reason) => write!(f, "cannot check passwords: {reason}"),
+            LockError::Image(reason) => write!(f, "failed to load the background image: {reason}"),
+            LockError::InvalidScreen(n) => write!(f, "no X screen numbered {n}"),
+            LockError::InvalidOutput(name, outputs) if outputs.is_empty() => {
+                write!(f, "no active output named {name}, RandR names none")
+            }
+            LockError::InvalidOutput(name, outputs) => write!(
+                f,
+                "no active output named {name}, expected one of {}",
+                outputs.join(", ")
+            ),
+            LockError::Keymap => write!(f, "failed to acquire keyboard state"),
+            LockError::Privileges(e) => write!(f, "failed to drop privileges: {e}"),
+            #[cfg(feature = "logind")]
+            LockError::Logind(e) => write!(f, "logind error: {e}"),
+            LockError::Signal(e) => write!(f, "failed to install signal handlers: {e}"),
+            LockError::Stdin(e) => write!(f, "failed to read the password from stdin: {e}"),
+            LockError::Terminated(signal) => write!(f, "terminated by signal {signal}"),
+            LockError::Totp(reason) => write!(f, "failed to load the TOTP secret: {reason}"),
+            LockError::Tty(e) => write!(f, "terminal error: {e}"),
+            #[cfg(feature = "wayland")]
+            LockError::Wayland(reason) => write!(f, "Wayland error: {reason}"),
+        }
+    }
+}
+
+impl Error for LockError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LockError::Connect(e) | LockError::ConnectionLost(e) => Some(e),
+            LockError::Connection(e) => Some(e),
+            LockError::CreateWindow(e) | LockError::Cursor(e) | LockError::Font(e) => Some(e),
+            LockError::GrabKeyboard(_)
+            | LockError::GrabPointer(_)
+            | LockError::HashUnavailable(_)
+            | LockError::AuthUnavailable(_)
+            | LockError::Image(_)
+            | LockError::InvalidScreen(_)
+            | LockError::InvalidOutput(..)
+            | LockError::Keymap
+            | LockError::NoDisplay
+            | LockError::AlreadyLocked
+            | LockError::Terminated(_)
+            | LockError::Totp(_) => None,
+            #[cfg(feature = "logind")]
+            LockError::Logind(e) => Some(e),
+            LockError::Signal(e)
+            | LockError::Stdin(e)
+            | LockError::Tty(e)
+            | LockError::Privileges(e) => Some(e),
+            #[cfg(feature = "wayland")]
+            LockError::Wayland(_) => None,
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+    sync::mpsc::{self, Receiver},
+};
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::OwnedObjectPath,
+};
+
+const DESTINATION: &str = "net.reactivated.Fprint";
+const MANAGER_PATH: &str = "/net/reactivated/Fprint/Manager";
+const MANAGER_IFACE: &str = "net.reactivated.Fprint.Manager";
+const DEVICE_IFACE: &str = "net.reactivated.Fprint.Device";
+
+// a verification running on fprintd's default reader while the screen is locked. Results
+// arrive on a thread reading the bus and are handed over through a channel; a byte written
+// to a pipe wakes the event loop, which polls the pipe next to the X connection
+pub(crate) struct Fingerprint {
+    device: Proxy<'static>,
+    results: Receiver<bool>,
+    wake: File,
+}
+
+impl Fingerprint {
+    // claims the reader for `user` and starts verifying any enrolled finger
+    pub(crate) fn start(user: &str) -> zbus::Result<Self> {
+        let conn = Connection::system()?;
+        let manager = Proxy::new(&conn, DESTINATION, MANAGER_PATH, MANAGER_IFACE)?;
+        let path: OwnedObjectPath = manager.call("GetDefaultDevice", &())?;
+        let device = Proxy::new(&conn, DESTINATION, path, DEVICE_IFACE)?;
+        device.call_method("Claim", &(user))?;
+        let statuses = device.receive_signal("VerifyStatus")?;
+        if let Err(e) = device.call_method("VerifyStart", &("any")) {
+            let _ = device.call_method("Release", &());
+            return Err(e);
+        }
+        let (wake, mut notify) = pipe()?;
+        let (tx, results) = mpsc::channel();
+        let restart = device.clone();
+        // blocks on the bus until the device goes quiet; once the lock is gone nothing
+        // reads the results and the thread ends on the next status it sees
+        std::thread::spawn(move || {
+            for msg in statuses {
+                let Ok((status, done)) = msg.body().deserialize::<(String, bool)>() else {
+                    continue;
+                };
+                log::debug!("fprintd: {status}");
+                // retry statuses (too short, not centered) only ask for another swipe
+                if !done {
+                    continue;
+                }
+                let matched = status == "verify-match";
+                if tx.send(matched).is_err() || notify.write_all(&[1]).is_err() {
+                    break;
+                }
+                if matched {
+                    break;
+                }
+                // a finished verification has to be restarted for the next try
+                let restarted = restart
+                    .call_method("VerifyStop", &())
+                    .and_then(|_| restart.call_method("VerifyStart", &("any")));
+                if let Err(e) = restarted {
+                    log::warn!("fprintd: failed to restart verification: {e}");
+                    break;
+                }
+            }
+        });
+        log::debug!("fprintd: verifying");
+        Ok(Self {
+            device,
+            results,
+            wake,
+        })
+    }
+
+    // readable whenever a result is waiting
+    pub(crate) fn wake_fd(&self) -> BorrowedFd<'_> {
+        self.wake.as_fd()
+    }
+
+    // the latest finished scan, true for a match
+    pub(crate) fn result(&mut self) -> Option<bool> {
+        let mut drained = [0; 16];
+        while matches!(self.wake.read(&mut drained), Ok(n) if n > 0) {}
+        self.results.try_iter().last()
+    }
+}
+
+impl Drop for Fingerprint {
+    fn drop(&mut self) {
+        let _ = self.device.call_method("VerifyStop", &());
+        let _ = self.device.call_method("Release", &());
+    }
+}
+
+// the read end is non-blocking so draining it never stalls the event loop
+fn pipe() -> std::io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    let flags = unsafe { libc::fcntl(read.as_raw_fd(), libc::F_GETFL) };
+    if unsafe { libc::fcntl(read.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok((File::from(read), File::from(write)))
+}
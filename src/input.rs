@@ -0,0 +1,1062 @@
+use crate::LockError;
+use std::{
+    ffi::OsString,
+    os::fd::{AsRawFd, BorrowedFd},
+    str::{FromStr, Utf8Error},
+    time::{Duration, Instant},
+};
+use xcb::{x, xinput, xkb as xxkb, Connection};
+use xkbcommon::xkb::{self, compose};
+use zeroize::Zeroize;
+
+// default cap on the password length, in bytes
+pub(crate) const MAX_BUF_SIZE: usize = 500;
+
+pub(crate) enum InputEvent {
+    // the buffer was edited, no attempt should be made yet
+    Edit,
+    // Escape with `EscapeKey::Reset`: the buffer is empty, the screen should look fresh
+    Reset,
+    // the buffer is ready to be checked
+    Submit,
+    // anything that isn't keyboard input, left for the lock to handle
+    Other(xcb::Event),
+    // nothing arrived in time, a chance to run timers
+    Timeout,
+    // a volume key, never part of the password
+    Volume(Volume),
+    // a pass-through key, to be sent on instead of typed
+    Forward(x::Keycode),
+    // one of the extra fds passed to `get_input` became readable
+    Wake,
+}
+
+// what waiting on the connection turned up
+enum Wait {
+    Event(xcb::Event),
+    Timeout,
+    Wake,
+}
+
+/// What Escape does while locked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EscapeKey {
+    /// Throws away what was typed
+    #[default]
+    Clear,
+    /// Throws away what was typed and brings the lock screen back to how it looked when
+    /// locked: the indicator goes idle and the failure count is hidden
+    Reset,
+    /// Nothing, so a stray press never loses a half-typed password
+    Ignore,
+}
+
+impl FromStr for EscapeKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clear" => Ok(EscapeKey::Clear),
+            "reset" => Ok(EscapeKey::Reset),
+            "ignore" => Ok(EscapeKey::Ignore),
+            other => Err(format!(
+                "invalid escape action `{other}`, expected clear, reset or ignore"
+            )),
+        }
+    }
+}
+
+/// What happens to the typed text after a wrong password
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WrongPassword {
+    /// It's thrown away, nothing of it stays in memory
+    #[default]
+    Clear,
+    /// It stays, to fix a typo instead of typing it all again. It's kept until it's
+    /// submitted, cleared or the input timeout runs out, so leave it off where someone could
+    /// walk up to the machine afterwards
+    Keep,
+}
+
+impl FromStr for WrongPassword {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clear" => Ok(WrongPassword::Clear),
+            "keep" => Ok(WrongPassword::Keep),
+            other => Err(format!(
+                "invalid wrong password policy `{other}`, expected clear or keep"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Volume {
+    Up,
+    Down,
+    Mute,
+}
+
+// the typed password; never holds more than `max` bytes so a stuck key or a paste can't
+// grow it without bound, the cap is far above any real password.
+// All `max` bytes are allocated up front and the buffer never grows: a Vec that outgrows its
+// capacity copies itself and frees the old block without wiping it, which would leave the
+// start of the password lying in freed memory. Every byte is zeroed again on clear and drop
+pub(crate) struct Password {
+    buf: Vec<u8>,
+    len: usize,
+    max: usize,
+}
+
+impl Password {
+    pub(crate) fn new(max: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(max),
+            len: 0,
+            max,
+        }
+    }
+
+    // wipes the whole allocation, not just the initialized part
+    pub(crate) fn clear(&mut self) {
+        self.buf.zeroize();
+        self.len = 0;
+    }
+
+    // past the cap further chars are dropped, what was typed so far stays
+    pub(crate) fn push_char(&mut self, c: char) {
+        if self.len + c.len_utf8() > self.max {
+            return;
+        }
+        let mut bytes = [0; 4];
+        let encoded = c.encode_utf8(&mut bytes).as_bytes();
+        // within the capacity allocated in `new`, this never reallocates
+        debug_assert!(self.buf.len() + encoded.len() <= self.buf.capacity());
+        self.buf.extend_from_slice(encoded);
+        bytes.zeroize();
+        self.len += c.len_utf8();
+    }
+
+    // removes the whole last char, which may span several bytes
+    pub(crate) fn pop_char(&mut self) {
+        let Some(last) = self.as_str().ok().and_then(|s| s.chars().next_back()) else {
+            return;
+        };
+        self.len -= last.len_utf8();
+        self.buf[self.len..].zeroize();
+        self.buf.truncate(self.len);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // counts chars rather than bytes, i.e. everything but UTF-8 continuation bytes
+    pub(crate) fn char_count(&self) -> usize {
+        self.buf[..self.len]
+            .iter()
+            .filter(|&&b| b & 0xc0 != 0x80)
+            .count()
+    }
+
+    pub(crate) fn as_str(&self) -> Result<&str, Utf8Error> {
+        std::str::from_utf8(&self.buf[..self.len])
+    }
+}
+
+impl Drop for Password {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+pub(crate) struct InputHandler {
+    pass: Password,
+    keyb: Keyb,
+    // dead keys and Multi_key sequences, from the locale's Compose file if there is one
+    compose: Option<compose::State>,
+    // the server speaks XKB, the keymap can be read from it
+    xkb: bool,
+    passthrough: Passthrough,
+    // what came up while draining a burst of edits, handed out on the next call
+    pending: Option<InputEvent>,
+    // holding BackSpace this long clears everything, off if unset
+    clear_on_hold: Option<Duration>,
+    escape: EscapeKey,
+    bindings: Bindings,
+    // submit as soon as this many chars are typed
+    auto_submit: Option<usize>,
+    // since when BackSpace has been held down, repeats don't move it
+    backspace_since: Option<Instant>,
+}
+
+// keys that go on to other clients instead of into the password, by keysym so they follow
+// the layout
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Passthrough(Vec<xkb::Keysym>);
+
+impl Passthrough {
+    // keysym names as in xev, `media` for the usual playback and volume keys; unknown names
+    // are reported and skipped
+    pub(crate) fn new(names: &[String]) -> Self {
+        let mut keysyms = Vec::new();
+        for name in names {
+            if name == "media" {
+                keysyms.extend_from_slice(&MEDIA_KEYS);
+                continue;
+            }
+            match xkb::keysym_from_name(name, xkb::KEYSYM_NO_FLAGS) {
+                keysym if keysym.raw() == xkb::keysyms::KEY_NoSymbol => {
+                    log::warn!("ignoring unknown pass-through key {name}");
+                }
+                keysym => keysyms.push(keysym),
+            }
+        }
+        Self(keysyms)
+    }
+
+    fn contains(&self, keysym: xkb::Keysym) -> bool {
+        self.0.contains(&keysym)
+    }
+}
+
+/// The editing keys that can be rebound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    /// Checks what was typed, Return or keypad Enter by default
+    Submit,
+    /// Does what [`EscapeKey`] says, Escape by default
+    Clear,
+    /// Deletes the last character, BackSpace, Delete or keypad Delete by default
+    Delete,
+}
+
+// the keypad's keys count too: KP_Enter would otherwise type a carriage return into the
+// password, and KP_Delete is what the keypad's period sends without Num Lock
+const DEFAULT_BINDINGS: [(KeyAction, xkb::Keysym); 6] = [
+    (KeyAction::Submit, xkb::Keysym::Return),
+    (KeyAction::Submit, xkb::Keysym::KP_Enter),
+    (KeyAction::Clear, xkb::Keysym::Escape),
+    (KeyAction::Delete, xkb::Keysym::BackSpace),
+    (KeyAction::Delete, xkb::Keysym::Delete),
+    (KeyAction::Delete, xkb::Keysym::KP_Delete),
+];
+
+// keysyms for each editing action, the defaults above unless rebound
+#[derive(Debug, Clone)]
+pub(crate) struct Bindings(Vec<(KeyAction, xkb::Keysym)>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self(DEFAULT_BINDINGS.to_vec())
+    }
+}
+
+impl Bindings {
+    // keysym names as in xev, per action; an action left without a known key keeps its
+    // default, so there's always a way to submit. A key bound twice does the first action
+    pub(crate) fn new(keys: &[(KeyAction, Vec<String>)]) -> Self {
+        let mut bindings = Vec::new();
+        for action in [KeyAction::Submit, KeyAction::Clear, KeyAction::Delete] {
+            let names = keys
+                .iter()
+                .filter(|(bound, _)| *bound == action)
+                .flat_map(|(_, names)| names);
+            let before = bindings.len();
+            for name in names {
+                match xkb::keysym_from_name(name, xkb::KEYSYM_NO_FLAGS) {
+                    keysym if keysym.raw() == xkb::keysyms::KEY_NoSymbol => {
+                        log::warn!("ignoring unknown key {name} bound to {action:?}");
+                    }
+                    keysym => bindings.push((action, keysym)),
+                }
+            }
+            if bindings.len() == before {
+                bindings.extend(
+                    DEFAULT_BINDINGS
+                        .iter()
+                        .filter(|(bound, _)| *bound == action),
+                );
+            }
+        }
+        Self(bindings)
+    }
+
+    fn action(&self, keysym: xkb::Keysym) -> Option<KeyAction> {
+        self.0
+            .iter()
+            .find(|(_, key)| *key == keysym)
+            .map(|(action, _)| *action)
+    }
+}
+
+const MEDIA_KEYS: [xkb::Keysym; 8] = [
+    xkb::Keysym::XF86_AudioPlay,
+    xkb::Keysym::XF86_AudioPause,
+    xkb::Keysym::XF86_AudioStop,
+    xkb::Keysym::XF86_AudioNext,
+    xkb::Keysym::XF86_AudioPrev,
+    xkb::Keysym::XF86_AudioRaiseVolume,
+    xkb::Keysym::XF86_AudioLowerVolume,
+    xkb::Keysym::XF86_AudioMute,
+];
+
+// what a key press did to a pending compose sequence
+enum Composed {
+    // no sequence involved, the key stands for itself
+    Key,
+    // a sequence was started or continued, nothing to type yet
+    Pending,
+    // a sequence completed into a single keysym
+    Done(xkb::Keysym),
+}
+
+impl InputHandler {
+    // `max_len` caps the password, in bytes
+    pub(crate) fn new(conn: &Connection, max_len: usize) -> Result<Self, LockError> {
+        let xkb = setup_xkb(conn)?;
+        Ok(Self {
+            pass: Password::new(max_len),
+            keyb: Keyb::new(conn, xkb).ok_or(LockError::Keymap)?,
+            compose: compose_state(),
+            xkb,
+            passthrough: Passthrough::default(),
+            pending: None,
+            clear_on_hold: None,
+            escape: EscapeKey::default(),
+            bindings: Bindings::default(),
+            auto_submit: None,
+            backspace_since: None,
+        })
+    }
+
+    // for keyboards whose keymap doesn't come from an X server, i.e. a Wayland seat
+    #[cfg(feature = "wayland")]
+    pub(crate) fn from_keymap(keymap: &xkb::Keymap, max_len: usize) -> Self {
+        Self {
+            pass: Password::new(max_len),
+            keyb: Keyb(xkb::State::new(keymap)),
+            compose: compose_state(),
+            xkb: false,
+            passthrough: Passthrough::default(),
+            pending: None,
+            clear_on_hold: None,
+            escape: EscapeKey::default(),
+            bindings: Bindings::default(),
+            auto_submit: None,
+            backspace_since: None,
+        }
+    }
+
+    // a key reported by something other than the X connection, with X keycodes (evdev + 8)
+    #[cfg(feature = "wayland")]
+    pub(crate) fn key(&mut self, code: x::Keycode, down: bool) -> Option<InputEvent> {
+        if down {
+            self.press(code)
+        } else {
+            self.release(code);
+            None
+        }
+    }
+
+    // the compositor is the authority on modifiers, e.g. a Caps Lock turned on before locking
+    #[cfg(feature = "wayland")]
+    pub(crate) fn set_modifiers(&mut self, depressed: u32, latched: u32, locked: u32, group: u32) {
+        self.keyb
+            .0
+            .update_mask(depressed, latched, locked, 0, 0, group);
+    }
+
+    pub(crate) fn clear_on_hold(&mut self, hold: Option<Duration>) {
+        self.clear_on_hold = hold;
+    }
+
+    pub(crate) fn escape_key(&mut self, escape: EscapeKey) {
+        self.escape = escape;
+    }
+
+    pub(crate) fn auto_submit(&mut self, len: Option<usize>) {
+        self.auto_submit = len;
+    }
+
+    pub(crate) fn bind_keys(&mut self, bindings: Bindings) {
+        self.bindings = bindings;
+    }
+
+    pub(crate) fn pass_through(&mut self, passthrough: Passthrough) {
+        self.passthrough = passthrough;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.pass.clear();
+        if let Some(compose) = &mut self.compose {
+            compose.reset();
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pass.is_empty()
+    }
+
+    pub(crate) fn char_count(&self) -> usize {
+        self.pass.char_count()
+    }
+
+    pub(crate) fn build_str(&self) -> Result<&str, Utf8Error> {
+        self.pass.as_str()
+    }
+
+    pub(crate) fn caps_lock(&self) -> bool {
+        self.keyb.caps_lock()
+    }
+
+    // keys held right now are forgotten, the next release of one is harmless
+    fn reload_keymap(&mut self, conn: &Connection) -> Result<(), LockError> {
+        log::debug!("keyboard mapping changed, reloading the keymap");
+        self.keyb = Keyb::new(conn, self.xkb).ok_or(LockError::Keymap)?;
+        Ok(())
+    }
+
+    // `wake` is watched alongside the X connection, for input that doesn't come from X.
+    // Edits already queued (a burst of key repeats) are applied together and reported as one,
+    // so the lock redraws once per burst instead of once per key
+    pub(crate) fn get_input(
+        &mut self,
+        conn: &Connection,
+        timeout: Duration,
+        wake: &[BorrowedFd],
+    ) -> Result<InputEvent, LockError> {
+        if let Some(event) = self.pending.take() {
+            return Ok(event);
+        }
+        let deadline = Instant::now() + timeout;
+        let mut edited = false;
+        loop {
+            let event = if edited {
+                match conn.poll_for_event().map_err(LockError::Connection)? {
+                    Some(event) => event,
+                    None => return Ok(InputEvent::Edit),
+                }
+            } else {
+                match wait_for_event_until(conn, deadline, wake)? {
+                    Wait::Event(event) => event,
+                    Wait::Timeout => return Ok(InputEvent::Timeout),
+                    Wait::Wake => return Ok(InputEvent::Wake),
+                }
+            };
+            let code = match event {
+                xcb::Event::X(x::Event::KeyPress(key_press)) => key_press.detail(),
+                xcb::Event::X(x::Event::KeyRelease(key_release)) => {
+                    self.release(key_release.detail());
+                    continue;
+                }
+                // keyboards grabbed through XInput2 report their keys here instead
+                xcb::Event::Input(xinput::Event::KeyPress(key_press)) => {
+                    key_press.detail() as x::Keycode
+                }
+                xcb::Event::Input(xinput::Event::KeyRelease(key_release)) => {
+                    self.release(key_release.detail() as x::Keycode);
+                    continue;
+                }
+                // the layout changed (setxkbmap, a new keyboard), translations must follow
+                xcb::Event::X(x::Event::MappingNotify(notify))
+                    if notify.request() == x::Mapping::Keyboard =>
+                {
+                    self.reload_keymap(conn)?;
+                    continue;
+                }
+                xcb::Event::Xkb(xxkb::Event::NewKeyboardNotify(_) | xxkb::Event::MapNotify(_)) => {
+                    self.reload_keymap(conn)?;
+                    continue;
+                }
+                other => return Ok(self.after_edits(edited, InputEvent::Other(other))),
+            };
+            match self.press(code) {
+                Some(InputEvent::Edit) => edited = true,
+                Some(event) => return Ok(self.after_edits(edited, event)),
+                None => {}
+            }
+        }
+    }
+
+    // anything but an edit ends a burst; the edits are reported first so the lock redraws
+    // before e.g. checking the password
+    fn after_edits(&mut self, edited: bool, event: InputEvent) -> InputEvent {
+        if !edited {
+            return event;
+        }
+        self.pending = Some(event);
+        InputEvent::Edit
+    }
+
+    // translated before the press itself is applied, so modifiers held so far still count;
+    // a modifier on its own only changes the xkb state
+    fn press(&mut self, code: x::Keycode) -> Option<InputEvent> {
+        let keysym = self.keyb.keycode_to_keysym(code);
+        // never a modifier or part of a sequence, the xkb and compose state stay as they are
+        if self.passthrough.contains(keysym) {
+            return Some(InputEvent::Forward(code));
+        }
+        let ch = self.keyb.keycode_to_char(code);
+        self.keyb.update_key(code, xkb::KeyDirection::Down);
+        match self.compose(keysym) {
+            Composed::Key => self.apply(keysym, ch),
+            Composed::Pending => Some(InputEvent::Edit),
+            Composed::Done(keysym) => self.apply(keysym, keysym.key_char()),
+        }
+    }
+
+    // bound keys do their action; Return, Escape and BackSpace bound to nothing type nothing
+    fn apply(&mut self, keysym: xkb::Keysym, ch: Option<char>) -> Option<InputEvent> {
+        match self.bindings.action(keysym) {
+            Some(KeyAction::Submit) => Some(InputEvent::Submit),
+            Some(KeyAction::Clear) => self.escape(),
+            Some(KeyAction::Delete) if self.backspace_held() => {
+                self.pass.clear();
+                Some(InputEvent::Edit)
+            }
+            Some(KeyAction::Delete) => {
+                self.pass.pop_char();
+                Some(InputEvent::Edit)
+            }
+            None if DEFAULT_BINDINGS.iter().any(|(_, key)| *key == keysym) => None,
+            None => {
+                let typed = self.pass.char_count();
+                let event = apply_key(&mut self.pass, keysym, ch);
+                self.auto_submitted(typed).or(event)
+            }
+        }
+    }
+
+    // a fixed-length PIN goes off on its last digit, without waiting for Enter
+    fn auto_submitted(&self, typed: usize) -> Option<InputEvent> {
+        let count = self.pass.char_count();
+        (count > typed && self.auto_submit.is_some_and(|len| count >= len))
+            .then_some(InputEvent::Submit)
+    }
+
+    // a char from somewhere other than the keyboard, i.e. the on-screen keyboard
+    pub(crate) fn type_char(&mut self, ch: char) -> InputEvent {
+        let typed = self.pass.char_count();
+        self.pass.push_char(ch);
+        self.auto_submitted(typed).unwrap_or(InputEvent::Edit)
+    }
+
+    pub(crate) fn delete_char(&mut self) -> InputEvent {
+        self.pass.pop_char();
+        InputEvent::Edit
+    }
+
+    // modifiers are ignored by the compose state, so Shift inside a sequence is fine. A key
+    // that breaks a sequence is still applied, Enter right after a dead key submits
+    fn compose(&mut self, keysym: xkb::Keysym) -> Composed {
+        let Some(compose) = &mut self.compose else {
+            return Composed::Key;
+        };
+        if compose.feed(keysym) == compose::FeedResult::Ignored {
+            return Composed::Key;
+        }
+        match compose.status() {
+            compose::Status::Composing => Composed::Pending,
+            compose::Status::Composed => {
+                let result = compose.keysym();
+                compose.reset();
+                // sequences producing several chars have no single keysym, they're dropped
+                result.map_or(Composed::Pending, Composed::Done)
+            }
+            compose::Status::Cancelled => {
+                compose.reset();
+                Composed::Key
+            }
+            compose::Status::Nothing => Composed::Key,
+        }
+    }
+
+    fn escape(&mut self) -> Option<InputEvent> {
+        match self.escape {
+            EscapeKey::Clear => {
+                self.pass.clear();
+                Some(InputEvent::Edit)
+            }
+            EscapeKey::Reset => {
+                self.pass.clear();
+                Some(InputEvent::Reset)
+            }
+            EscapeKey::Ignore => None,
+        }
+    }
+
+    // auto-repeat sends presses without releases in between (detectable auto-repeat is asked
+    // for in `setup_xkb`), each deletes a char until the hold runs past `clear_on_hold`
+    fn backspace_held(&mut self) -> bool {
+        let Some(hold) = self.clear_on_hold else {
+            return false;
+        };
+        match self.backspace_since {
+            Some(since) => since.elapsed() >= hold,
+            None => {
+                self.backspace_since = Some(Instant::now());
+                false
+            }
+        }
+    }
+
+    fn release(&mut self, code: x::Keycode) {
+        let keysym = self.keyb.keycode_to_keysym(code);
+        if self.bindings.action(keysym) == Some(KeyAction::Delete) {
+            self.backspace_since = None;
+        }
+        self.keyb.update_key(code, xkb::KeyDirection::Up);
+    }
+}
+
+// what a translated key press that isn't bound to an action does to the password, None if
+// it's ignored outright
+fn apply_key(pass: &mut Password, keysym: xkb::Keysym, ch: Option<char>) -> Option<InputEvent> {
+    match keysym {
+        xkb::Keysym::XF86_AudioRaiseVolume => return Some(InputEvent::Volume(Volume::Up)),
+        xkb::Keysym::XF86_AudioLowerVolume => return Some(InputEvent::Volume(Volume::Down)),
+        xkb::Keysym::XF86_AudioMute => return Some(InputEvent::Volume(Volume::Mute)),
+        // media, brightness and other vendor keys map to odd codepoints, if any
+        media if is_vendor_key(media) => return None,
+        // only the xkb state changes, the caller redraws the caps warning
+        modifier if modifier.is_modifier_key() => {}
+        // arrows, function keys and the like type nothing and leave the entry alone
+        _ => pass.push_char(ch?),
+    }
+    Some(InputEvent::Edit)
+}
+
+// the same locale lookup as Xlib: LC_ALL, then LC_CTYPE, then LANG
+fn compose_state() -> Option<compose::State> {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .find_map(|var| std::env::var_os(var).filter(|value| !value.is_empty()))
+        .unwrap_or_else(|| OsString::from("C"));
+    let context = xkb::Context::new(0);
+    match compose::Table::new_from_locale(&context, &locale, compose::COMPILE_NO_FLAGS) {
+        Ok(table) => Some(compose::State::new(&table, compose::STATE_NO_FLAGS)),
+        Err(()) => {
+            log::debug!("no compose table for {locale:?}, dead keys type nothing");
+            None
+        }
+    }
+}
+
+// the XF86 keysyms, everything from play/pause to brightness
+fn is_vendor_key(keysym: xkb::Keysym) -> bool {
+    (0x1008_ff00..=0x1008_ffff).contains(&keysym.raw())
+}
+
+// enables XKB on the connection and selects its keymap notifications, false if the server
+// doesn't have it; core MappingNotify reaches every client unasked either way
+fn setup_xkb(conn: &Connection) -> Result<bool, LockError> {
+    if !conn
+        .active_extensions()
+        .any(|ext| ext == xcb::Extension::Xkb)
+    {
+        return Ok(false);
+    }
+    let reply = conn
+        .wait_for_reply(conn.send_request(&xxkb::UseExtension {
+            wanted_major: 1,
+            wanted_minor: 0,
+        }))
+        .map_err(LockError::Connection)?;
+    if !reply.supported() {
+        return Ok(false);
+    }
+    // held keys repeat as presses alone, without the release core X fakes before each
+    let repeat = conn.wait_for_reply(conn.send_request(&xxkb::PerClientFlags {
+        device_spec: xxkb::Id::UseCoreKbd as xxkb::DeviceSpec,
+        change: xxkb::PerClientFlag::DETECTABLE_AUTO_REPEAT,
+        value: xxkb::PerClientFlag::DETECTABLE_AUTO_REPEAT,
+        ctrls_to_change: xxkb::BoolCtrl::empty(),
+        auto_ctrls: xxkb::BoolCtrl::empty(),
+        auto_ctrls_values: xxkb::BoolCtrl::empty(),
+    }));
+    if let Err(e) = repeat {
+        log::debug!("detectable auto-repeat unavailable: {e}");
+    }
+    let events = xxkb::EventType::NEW_KEYBOARD_NOTIFY | xxkb::EventType::MAP_NOTIFY;
+    conn.send_request(&xxkb::SelectEvents {
+        device_spec: xxkb::Id::UseCoreKbd as xxkb::DeviceSpec,
+        affect_which: events,
+        clear: xxkb::EventType::empty(),
+        select_all: events,
+        affect_map: xxkb::MapPart::all(),
+        map: xxkb::MapPart::all(),
+        details: &[],
+    });
+    Ok(true)
+}
+
+// poll(2)s the connection instead of blocking in xcb, so callers wake up even without input
+fn wait_for_event_until(
+    conn: &Connection,
+    deadline: Instant,
+    wake: &[BorrowedFd],
+) -> Result<Wait, LockError> {
+    loop {
+        if let Some(event) = conn.poll_for_event().map_err(LockError::Connection)? {
+            return Ok(Wait::Event(event));
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(Wait::Timeout);
+        }
+        let mut fds: Vec<_> = std::iter::once(conn.as_raw_fd())
+            .chain(wake.iter().map(|fd| fd.as_raw_fd()))
+            .map(|fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        let ms = left.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32;
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, ms) } < 0 {
+            // a signal arrived, let the caller look at it right away
+            if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                return Ok(Wait::Timeout);
+            }
+            return Err(LockError::Connection(xcb::Error::Connection(
+                xcb::ConnError::Connection,
+            )));
+        }
+        if fds[1..].iter().any(|fd| fd.revents != 0) {
+            return Ok(Wait::Wake);
+        }
+    }
+}
+
+struct Keyb(xkb::State);
+
+impl Keyb {
+    // the layout the user actually has configured, or the default rules' layout without XKB
+    fn new(conn: &Connection, xkb: bool) -> Option<Self> {
+        let context = xkb::Context::new(0);
+        if xkb {
+            if let Some(keyb) = Keyb::from_device(&context, conn) {
+                return Some(keyb);
+            }
+            log::warn!("failed to read the keymap from the X server, using the default layout");
+        }
+        xkb::Keymap::new_from_names(&context, "", "", "", "", None, 0)
+            .map(|kmap| Keyb(xkb::State::new(&kmap)))
+    }
+
+    // the state starts out with the server's current modifiers, e.g. an active Caps Lock
+    fn from_device(context: &xkb::Context, conn: &Connection) -> Option<Self> {
+        let device = xkb::x11::get_core_keyboard_device_id(conn);
+        if device < 0 {
+            return None;
+        }
+        let kmap = xkb::x11::keymap_new_from_device(context, conn, device, 0);
+        if kmap.get_raw_ptr().is_null() {
+            return None;
+        }
+        let state = xkb::x11::state_new_from_device(&kmap, conn, device);
+        if state.get_raw_ptr().is_null() {
+            return None;
+        }
+        Some(Keyb(state))
+    }
+
+    fn keycode_to_keysym(&self, code: x::Keycode) -> xkb::Keysym {
+        self.0.key_get_one_sym(xkb::Keycode::new(code as u32))
+    }
+
+    fn update_key(&mut self, code: x::Keycode, direction: xkb::KeyDirection) {
+        self.0.update_key(xkb::Keycode::new(code as u32), direction);
+    }
+
+    fn caps_lock(&self) -> bool {
+        self.0
+            .mod_name_is_active(xkb::MOD_NAME_CAPS, xkb::STATE_MODS_EFFECTIVE)
+    }
+
+    fn keycode_to_char(&self, code: x::Keycode) -> Option<char> {
+        char::from_u32(self.0.key_get_utf32(xkb::Keycode::new(code as u32)))
+            .filter(|ch| !ch.is_control())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Bindings, EscapeKey, InputEvent, InputHandler, KeyAction, Keyb, Passthrough, Password,
+        MAX_BUF_SIZE,
+    };
+    use std::{ffi::OsStr, time::Duration};
+    use xkbcommon::xkb::{self, compose};
+
+    // evdev keycodes, as the X server numbers them
+    const SHIFT_L: u8 = 50;
+    const KEY_A: u8 = 38;
+    const KEY_B: u8 = 56;
+    const KEY_E: u8 = 26;
+    const BACKSPACE: u8 = 22;
+    const ESCAPE: u8 = 9;
+    const RETURN: u8 = 36;
+    const KP_ENTER: u8 = 104;
+    const DELETE: u8 = 119;
+    const TAB: u8 = 23;
+    const LEFT: u8 = 113;
+    const F5: u8 = 71;
+    // dead_acute in us(intl)
+    const APOSTROPHE: u8 = 48;
+
+    fn handler(variant: &str) -> InputHandler {
+        let context = xkb::Context::new(0);
+        let kmap = xkb::Keymap::new_from_names(&context, "evdev", "pc105", "us", variant, None, 0)
+            .expect("the us layout should be installed");
+        let table = compose::Table::new_from_locale(
+            &context,
+            OsStr::new("en_US.UTF-8"),
+            compose::COMPILE_NO_FLAGS,
+        )
+        .expect("the en_US.UTF-8 compose table should be installed");
+        InputHandler {
+            pass: Password::new(MAX_BUF_SIZE),
+            keyb: Keyb(xkb::State::new(&kmap)),
+            compose: Some(compose::State::new(&table, compose::STATE_NO_FLAGS)),
+            xkb: false,
+            passthrough: Passthrough::default(),
+            pending: None,
+            clear_on_hold: None,
+            escape: EscapeKey::default(),
+            bindings: Bindings::default(),
+            auto_submit: None,
+            backspace_since: None,
+        }
+    }
+
+    #[test]
+    fn push_past_cap_keeps_buffer() {
+        let mut pass = Password::new(4);
+        for c in "abcdef".chars() {
+            pass.push_char(c);
+        }
+        assert_eq!(pass.as_str(), Ok("abcd"));
+        // a multi-byte char that would straddle the cap is dropped whole
+        let mut pass = Password::new(4);
+        for c in "abcé".chars() {
+            pass.push_char(c);
+        }
+        assert_eq!(pass.as_str(), Ok("abc"));
+    }
+
+    #[test]
+    fn backspace_on_empty_is_a_noop() {
+        let mut pass = Password::new(MAX_BUF_SIZE);
+        pass.pop_char();
+        assert!(pass.is_empty());
+        assert_eq!(pass.as_str(), Ok(""));
+        pass.push_char('a');
+        pass.pop_char();
+        pass.pop_char();
+        assert!(pass.is_empty());
+    }
+
+    #[test]
+    fn typing_up_to_the_cap_never_reallocates() {
+        let mut pass = Password::new(MAX_BUF_SIZE);
+        let start = pass.buf.as_ptr();
+        let capacity = pass.buf.capacity();
+        for _ in 0..MAX_BUF_SIZE {
+            pass.push_char('\u{e9}');
+        }
+        assert_eq!(pass.buf.len(), MAX_BUF_SIZE);
+        assert_eq!(pass.buf.as_ptr(), start);
+        assert_eq!(pass.buf.capacity(), capacity);
+    }
+
+    #[test]
+    fn backspace_removes_whole_utf8_chars() {
+        let mut pass = Password::new(MAX_BUF_SIZE);
+        for c in "aé€😀".chars() {
+            pass.push_char(c);
+        }
+        assert_eq!(pass.char_count(), 4);
+        assert_eq!(pass.as_str(), Ok("aé€😀"));
+        pass.pop_char();
+        assert_eq!(pass.as_str(), Ok("aé€"));
+        pass.pop_char();
+        assert_eq!(pass.as_str(), Ok("aé"));
+        pass.pop_char();
+        assert_eq!(pass.as_str(), Ok("a"));
+        assert_eq!(pass.char_count(), 1);
+    }
+
+    #[test]
+    fn clear_empties_and_allows_reuse() {
+        let mut pass = Password::new(MAX_BUF_SIZE);
+        for c in "secret".chars() {
+            pass.push_char(c);
+        }
+        pass.clear();
+        assert!(pass.is_empty());
+        assert!(pass.buf.is_empty());
+        assert_eq!(pass.char_count(), 0);
+        pass.push_char('x');
+        assert_eq!(pass.as_str(), Ok("x"));
+    }
+
+    #[test]
+    fn escape_and_backspace_keys_edit_the_buffer() {
+        let mut handler = handler("");
+        for key in [KEY_A, KEY_B, KEY_A] {
+            handler.press(key);
+            handler.release(key);
+        }
+        assert!(matches!(handler.press(BACKSPACE), Some(InputEvent::Edit)));
+        handler.release(BACKSPACE);
+        assert_eq!(handler.build_str(), Ok("ab"));
+        assert!(matches!(handler.press(ESCAPE), Some(InputEvent::Edit)));
+        handler.release(ESCAPE);
+        assert!(handler.is_empty());
+    }
+
+    #[test]
+    fn navigation_key_mid_entry_is_skipped() {
+        let mut handler = handler("");
+        for key in [KEY_A, LEFT, F5, KEY_B] {
+            handler.press(key);
+            handler.release(key);
+        }
+        assert!(matches!(handler.press(RETURN), Some(InputEvent::Submit)));
+        assert_eq!(handler.build_str(), Ok("ab"));
+    }
+
+    #[test]
+    fn shift_only_changes_state() {
+        let mut handler = handler("");
+        assert!(matches!(handler.press(SHIFT_L), Some(InputEvent::Edit)));
+        assert!(handler.is_empty());
+        handler.press(KEY_A);
+        handler.release(KEY_A);
+        handler.release(SHIFT_L);
+        handler.press(KEY_B);
+        assert_eq!(handler.build_str(), Ok("Ab"));
+    }
+
+    #[test]
+    fn holding_backspace_clears_past_the_threshold() {
+        let mut handler = handler("");
+        handler.clear_on_hold(Some(Duration::ZERO));
+        for key in [KEY_A, KEY_B, KEY_A] {
+            handler.press(key);
+            handler.release(key);
+        }
+        // released in between, one char at a time
+        handler.press(BACKSPACE);
+        handler.release(BACKSPACE);
+        assert_eq!(handler.build_str(), Ok("ab"));
+        // a repeat of the held key, already held for the (zero) threshold
+        handler.press(BACKSPACE);
+        assert_eq!(handler.build_str(), Ok("a"));
+        assert!(matches!(handler.press(BACKSPACE), Some(InputEvent::Edit)));
+        assert!(handler.is_empty());
+    }
+
+    #[test]
+    fn held_backspace_deletes_one_char_per_repeat_by_default() {
+        let mut handler = handler("");
+        for key in [KEY_A, KEY_B, KEY_A] {
+            handler.press(key);
+            handler.release(key);
+        }
+        handler.press(BACKSPACE);
+        handler.press(BACKSPACE);
+        assert_eq!(handler.build_str(), Ok("a"));
+    }
+
+    #[test]
+    fn escape_follows_the_configured_action() {
+        let mut handler = handler("");
+        handler.escape_key(EscapeKey::Ignore);
+        handler.press(KEY_A);
+        handler.release(KEY_A);
+        assert!(handler.press(ESCAPE).is_none());
+        handler.release(ESCAPE);
+        assert_eq!(handler.build_str(), Ok("a"));
+        handler.escape_key(EscapeKey::Reset);
+        assert!(matches!(handler.press(ESCAPE), Some(InputEvent::Reset)));
+        assert!(handler.is_empty());
+    }
+
+    #[test]
+    fn rebound_keys_replace_the_defaults() {
+        let mut handler = handler("");
+        handler.bind_keys(Bindings::new(&[
+            (KeyAction::Submit, vec!["Tab".to_owned()]),
+            (KeyAction::Delete, vec!["NoSuchKey".to_owned()]),
+        ]));
+        handler.press(KEY_A);
+        handler.release(KEY_A);
+        // Return is no longer bound, it does nothing
+        assert!(handler.press(RETURN).is_none());
+        handler.release(RETURN);
+        assert!(matches!(handler.press(TAB), Some(InputEvent::Submit)));
+        handler.release(TAB);
+        // the unknown delete key leaves BackSpace in place
+        handler.press(BACKSPACE);
+        assert!(handler.is_empty());
+    }
+
+    #[test]
+    fn keypad_enter_submits_and_delete_deletes() {
+        let mut handler = handler("");
+        handler.press(KEY_A);
+        handler.release(KEY_A);
+        handler.press(KEY_B);
+        handler.release(KEY_B);
+        assert!(matches!(handler.press(DELETE), Some(InputEvent::Edit)));
+        handler.release(DELETE);
+        assert_eq!(handler.build_str(), Ok("a"));
+        // no carriage return is typed into the password
+        assert!(matches!(handler.press(KP_ENTER), Some(InputEvent::Submit)));
+        assert_eq!(handler.build_str(), Ok("a"));
+    }
+
+    #[test]
+    fn rebinding_submit_drops_keypad_enter_too() {
+        let mut handler = handler("");
+        handler.bind_keys(Bindings::new(&[(
+            KeyAction::Submit,
+            vec!["Tab".to_owned()],
+        )]));
+        assert!(handler.press(KP_ENTER).is_none());
+        handler.release(KP_ENTER);
+        assert!(handler.is_empty());
+    }
+
+    #[test]
+    fn reaching_the_auto_submit_length_submits() {
+        let mut handler = handler("");
+        handler.auto_submit(Some(2));
+        assert!(matches!(handler.press(KEY_A), Some(InputEvent::Edit)));
+        handler.release(KEY_A);
+        assert!(matches!(handler.press(KEY_B), Some(InputEvent::Submit)));
+        handler.release(KEY_B);
+        handler.press(KEY_A);
+        handler.release(KEY_A);
+        // deleting never submits, even back down to the length
+        assert!(matches!(handler.press(BACKSPACE), Some(InputEvent::Edit)));
+        assert_eq!(handler.build_str(), Ok("ab"));
+    }
+
+    #[test]
+    fn dead_acute_composes() {
+        let mut handler = handler("intl");
+        assert!(matches!(handler.press(APOSTROPHE), Some(InputEvent::Edit)));
+        handler.release(APOSTROPHE);
+        assert!(handler.is_empty());
+        handler.press(KEY_E);
+        handler.release(KEY_E);
+        handler.press(KEY_E);
+        assert_eq!(handler.build_str(), Ok("\u{e9}e"));
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{control::peer_uid, LockError};
+use std::{
+    io,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixListener, UnixStream},
+    },
+};
+
+// an abstract socket named after the user and the display, bound for as long as a lock is up.
+// The kernel drops it with the process however that ends, so a crash or SIGKILL never leaves
+// a stale guard. Abstract names are open to every user on the host, so a name taken by
+// someone else's process says nothing about this user's lock
+pub(crate) struct Instance {
+    _listener: UnixListener,
+}
+
+impl Instance {
+    // AlreadyLocked when another zlock of the same user holds `display`; any other failure
+    // only costs the guard, the grabs still keep a second lock out
+    pub(crate) fn acquire(display: &str) -> Result<Option<Self>, LockError> {
+        let uid = unsafe { libc::getuid() };
+        let name = format!("zlock-{uid}-{}", display_key(display));
+        let addr = match SocketAddr::from_abstract_name(name.as_bytes()) {
+            Ok(addr) => addr,
+            Err(e) => {
+                log::warn!("failed to bind the @{name} instance guard: {e}");
+                return Ok(None);
+            }
+        };
+        match UnixListener::bind_addr(&addr) {
+            Ok(listener) => {
+                log::debug!("holding the @{name} instance guard");
+                Ok(Some(Instance {
+                    _listener: listener,
+                }))
+            }
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+                let holder = UnixStream::connect_addr(&addr)
+                    .ok()
+                    .and_then(|stream| peer_uid(&stream));
+                if holder == Some(uid) {
+                    return Err(LockError::AlreadyLocked);
+                }
+                log::warn!("@{name} is held by uid {holder:?}, locking without the instance guard");
+                Ok(None)
+            }
+            Err(e) => {
+                log::warn!("failed to bind the @{name} instance guard: {e}");
+                Ok(None)
+            }
+        }
+    }
+}
+
+// `:0`, `:0.1` and `unix:0` are the same server, the screen number doesn't matter
+fn display_key(display: &str) -> &str {
+    let display = display.strip_prefix("unix").unwrap_or(display);
+    match display.rfind(':') {
+        Some(colon) => match display[colon..].find('.') {
+            Some(dot) => &display[..colon + dot],
+            None => display,
+        },
+        None => display,
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    io,
+    os::fd::{AsFd, BorrowedFd, FromRawFd, OwnedFd},
+    process::{Child, ExitStatus},
+};
+
+// a command the lock waits on: it ends the lock when it exits successfully. Watched through
+// a pidfd, which becomes readable once the process is gone
+pub(crate) struct Job {
+    child: Option<Child>,
+    pidfd: OwnedFd,
+}
+
+impl Job {
+    pub(crate) fn spawn(cmd: &str) -> Option<Self> {
+        log::debug!("running `{cmd}` while locked");
+        let mut child = match crate::privilege::shell(cmd).spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                log::warn!("failed to run `{cmd}`: {e}");
+                return None;
+            }
+        };
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, child.id(), 0) };
+        if fd < 0 {
+            // without a way to notice the exit the lock would never end by itself
+            log::warn!(
+                "failed to watch `{cmd}`: {}, only the password unlocks",
+                io::Error::last_os_error()
+            );
+            std::thread::spawn(move || child.wait());
+            return None;
+        }
+        Some(Self {
+            child: Some(child),
+            pidfd: unsafe { OwnedFd::from_raw_fd(fd as i32) },
+        })
+    }
+
+    pub(crate) fn wake_fd(&self) -> BorrowedFd<'_> {
+        self.pidfd.as_fd()
+    }
+
+    // the exit status once the command is done, None while it's still running
+    pub(crate) fn finished(&mut self) -> Option<ExitStatus> {
+        let status = self.child.as_mut()?.try_wait().ok()??;
+        self.child = None;
+        Some(status)
+    }
+}
+
+// unlocked early: the command carries on, reaped in the background
+impl Drop for Job {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            std::thread::spawn(move || child.wait());
+        }
+    }
+}
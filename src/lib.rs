@@ -0,0 +1,113 @@
+//! An `slock` clone: a minimal X11 lock screen that can also be embedded in other programs.
+//!
+//! ```no_run
+//! use zlock::{Config, Lock, Locker};
+//!
+//! let mut lock = Lock::lock_screen(&Config::default())?;
+//! lock.authenticate()?;
+//! # Ok::<(), zlock::LockError>(())
+//! ```
+//!
+//! [`Lock::builder`] gives finer control:
+//!
+//! ```no_run
+//! use zlock::{Lock, Locker};
+//!
+//! let mut lock = Lock::builder().background(0x1d2021).hide_cursor(false).build()?;
+//! lock.authenticate()?;
+//! # Ok::<(), zlock::LockError>(())
+//! ```
+//!
+//! Passwords go through the configured [`AuthBackends`], unless the builder is given an
+//! [`Authenticator`] that checks them some other way.
+//!
+//! A session manager embedding the lock follows it through [`Lock::on_event`], which is
+//! called from inside [`Locker::authenticate`], and asks [`Lock::is_locked`] and
+//! [`Lock::failed_attempts`] once it returns:
+//!
+//! ```no_run
+//! use std::sync::mpsc;
+//! use zlock::{Lock, LockEvent, Locker};
+//!
+//! let mut lock = Lock::builder().build()?;
+//! let (tx, events) = mpsc::channel();
+//! lock.on_event(move |event| {
+//!     let _ = tx.send(event);
+//! });
+//! // e.g. on a thread that reports to the rest of the session
+//! std::thread::spawn(move || {
+//!     for event in events {
+//!         match event {
+//!             LockEvent::Failed(n) => eprintln!("{n} wrong password(s)"),
+//!             LockEvent::GrabsLost => eprintln!("input may be escaping the lock"),
+//!             LockEvent::GrabsRestored => eprintln!("input is held again"),
+//!             LockEvent::Unlocked => eprintln!("unlocked"),
+//!         }
+//!     }
+//! });
+//! lock.authenticate()?;
+//! assert!(!lock.is_locked());
+//! println!("unlocked after {} failed attempt(s)", lock.failed_attempts());
+//! # Ok::<(), zlock::LockError>(())
+//! ```
+
+mod attempts;
+mod auth;
+mod clock;
+mod color;
+mod config;
+mod control;
+mod daemon;
+mod dpms;
+mod error;
+#[cfg(feature = "fprint")]
+mod fprint;
+mod input;
+mod instance;
+mod job;
+mod lock;
+#[cfg(feature = "logind")]
+mod logind;
+mod monitor;
+mod osk;
+mod privilege;
+mod screensaver;
+mod signal;
+mod state;
+mod status;
+mod suspend;
+mod text;
+#[cfg(feature = "totp")]
+mod totp;
+mod tty;
+mod wallpaper;
+#[cfg(feature = "wayland")]
+mod wayland;
+
+pub use auth::{Auth, AuthBackend, AuthBackends, Authenticator};
+pub use color::Background;
+pub use config::{parse_bell, parse_color, parse_glyph, parse_keys, parse_status, Config};
+pub use daemon::run_daemon;
+pub use error::LockError;
+pub use input::{EscapeKey, KeyAction, WrongPassword};
+pub use lock::{Colors, Lock, LockBuilder, LockEvent, PointerMode};
+#[cfg(feature = "logind")]
+pub use logind::run_logind;
+pub use privilege::drop_privileges;
+pub use status::StatusField;
+pub use text::{TextPosition, UserLine};
+pub use tty::{check_stdin, TtyLock};
+pub use wallpaper::ScaleMode;
+#[cfg(feature = "wayland")]
+pub use wayland::WaylandLock;
+
+/// Something that keeps the session locked until the user proves who they are
+pub trait Locker {
+    /// Blocks until the user has authenticated
+    fn authenticate(&mut self) -> Result<(), LockError>;
+}
+
+/// Locks every screen and returns once the user has authenticated
+pub fn run(config: &Config) -> Result<(), LockError> {
+    Lock::lock_screen(config)?.authenticate()
+}
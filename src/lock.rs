@@ -0,0 +1,3011 @@
+use crate::{
+    attempts::AttemptLog,
+    auth::{resolve_user, Auth, Authenticator, Duress, SystemAuth},
+    clock::Clock,
+    color::{argb_visual, blend, pixel_converter, rgb_to_pixel, Background},
+    control::ControlSocket,
+    dpms::Dpms,
+    input::{
+        Bindings, EscapeKey, InputEvent, InputHandler, KeyAction, Passthrough, Volume,
+        WrongPassword, MAX_BUF_SIZE,
+    },
+    instance::Instance,
+    job::Job,
+    monitor::{outputs, Output},
+    osk::{Osk, OskKey},
+    privilege,
+    screensaver::ScreenSaver,
+    signal::SignalGuard,
+    state::StateFile,
+    status::{Status, StatusField},
+    suspend::Suspend,
+    text::{TextFont, TextPosition, UserLine, FONT, TEXT_MARGIN},
+    wallpaper::ScaleMode,
+    AuthBackend, AuthBackends, Config, LockError, Locker,
+};
+use std::{
+    os::fd::BorrowedFd,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use xcb::{
+    randr,
+    x::{self, EventMask},
+    xinput, Connection,
+};
+
+pub(crate) const RETRY_LIMIT: u32 = 3;
+pub(crate) const BACKOFF_CAP: Duration = Duration::from_secs(30);
+const GRAB_ATTEMPTS: u32 = 20;
+pub(crate) const INDICATOR_SIZE: u16 = 64;
+const GRAB_RETRY_DELAY: Duration = Duration::from_millis(50);
+pub(crate) const INPUT_TIMEOUT: Duration = Duration::from_secs(10);
+pub(crate) const INDICATOR_TIMEOUT: Duration = Duration::from_secs(2);
+// one full fade from the idle color to the breathe color and back
+const BREATHE_PERIOD: Duration = Duration::from_secs(3);
+const MIN_FRAME: Duration = Duration::from_millis(20);
+// an unlock that takes longer than this to get out of the way gets in the way
+const MAX_FADE_OUT: Duration = Duration::from_secs(1);
+// a test lock ends by itself after this long
+const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+// how often the event loop wakes up without input
+const TICK: Duration = Duration::from_secs(1);
+const DOT_GLYPH: u8 = b'*';
+// XC_circle in the cursor font
+const CIRCLE_GLYPH: u16 = 24;
+
+/// A locked X session covering every screen
+pub struct Lock {
+    screens: Vec<ScreenLock>,
+    conn: Connection,
+    authenticator: Box<dyn Authenticator>,
+    user: String,
+    // the hash is the authenticator's, only the command is run from here
+    duress: Option<Duress>,
+    retry_limit: u32,
+    backoff_cap: Duration,
+    input_timeout: Duration,
+    max_password_len: usize,
+    clear_on_hold: Option<Duration>,
+    escape: EscapeKey,
+    wrong_password: WrongPassword,
+    bindings: Bindings,
+    auto_submit: Option<usize>,
+    colors: Colors,
+    pointer: PointerMode,
+    // the one output locked, None for the whole desktop
+    output: Option<String>,
+    // whether the keyboard is grabbed; always while locking the whole desktop, only while
+    // the pointer is over it when locking one output
+    keyboard_held: bool,
+    dpms: Option<Dpms>,
+    // the server's screensaver settings from before the lock, to restore
+    screensaver: Option<ScreenSaver>,
+    suspend: Option<Suspend>,
+    indicator: Indicator,
+    // when the indicator was last drawn, input and error flashes fade back to idle
+    indicator_since: Instant,
+    indicator_timeout: Duration,
+    breathe: Option<Breathe>,
+    fade: Option<Fade>,
+    fade_out: Option<Fade>,
+    flash: Option<Flash>,
+    clock: Option<Clock>,
+    // glyph and how many of them are drawn at most, one per typed char
+    dots: Option<(u8, usize)>,
+    typed: usize,
+    show_failures: bool,
+    failures: u32,
+    attempt_log: Option<AttemptLog>,
+    #[cfg(feature = "totp")]
+    totp: Option<crate::totp::Totp>,
+    // the password was right, the one-time code is typed next
+    code_pending: bool,
+    // wrong passwords logged before this lock, shown until Escape resets the screen
+    earlier_failures: u32,
+    // `user` or `user@host` and where it goes, drawn once with every repaint
+    user_line: Option<(String, TextRow)>,
+    // refreshed on the tick like the clock
+    status: Option<(Status, TextRow)>,
+    osk: Option<Osk>,
+    font: Option<TextFont>,
+    #[cfg(feature = "image")]
+    wallpaper: Option<crate::wallpaper::Wallpaper>,
+    // by RandR output name, as 0xRRGGBB
+    output_backgrounds: Vec<(String, u32)>,
+    #[cfg(feature = "image")]
+    output_images: Vec<(String, crate::wallpaper::Wallpaper)>,
+    post_unlock: Option<String>,
+    bell: Option<i8>,
+    bell_cmd: Option<String>,
+    volume_keys: bool,
+    passthrough: Passthrough,
+    lockout: Option<Lockout>,
+    // the lockout fired without a command, no password is checked anymore
+    locked_out: bool,
+    // the grabs were taken away and couldn't be taken back yet
+    grab_lost: bool,
+    // wrong passwords since the last unlock, what the backoff and the lockout count
+    attempts: u32,
+    // every wrong password since the screen was locked, Escape only hides the count
+    total_attempts: u32,
+    unlocked: bool,
+    on_event: Option<Box<dyn FnMut(LockEvent) + Send>>,
+    test: bool,
+    #[cfg(feature = "fprint")]
+    fingerprint: Option<crate::fprint::Fingerprint>,
+    #[cfg(feature = "logind")]
+    session_unlock: Option<crate::logind::SessionUnlock>,
+    control: Option<ControlSocket>,
+    job: Option<Job>,
+    // physical devices grabbed through XInput2, released before the core grabs
+    devices: Vec<xinput::Device>,
+    // keeps a second zlock off the display until this one is gone
+    _instance: Option<Instance>,
+    // dropped after the grabs are released, so `unlocked` is only written once it's true
+    state: Option<StateFile>,
+    signals: SignalGuard,
+}
+
+// the lock window, cursor and indicator gc created on a single X screen
+struct ScreenLock {
+    index: usize,
+    root: x::Window,
+    cursor: x::Cursor,
+    lock: x::Window,
+    gc: x::Gcontext,
+    text_gc: x::Gcontext,
+    background: u32,
+    // where the window sits on the root, 0,0 unless a single output is locked
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+    depth: u8,
+    visual: x::Visualid,
+    // only created for an ARGB visual, the root visual uses the default colormap
+    colormap: Option<x::Colormap>,
+    // or'ed into every pixel so an ARGB window stays fully opaque, 0 otherwise
+    alpha: u32,
+    created: Created,
+}
+
+// which of a screen's ids exist on the server yet; a lock that failed half way through
+// only frees those
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Created {
+    colormap: bool,
+    window: bool,
+    gc: bool,
+    text_gc: bool,
+    cursor: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resource {
+    Gc(x::Gcontext),
+    Cursor(x::Cursor),
+    Window(x::Window),
+    Colormap(x::Colormap),
+}
+
+impl ScreenLock {
+    // everything created so far, gcs and cursor before the window they were made for and
+    // the colormap last; handed out only once
+    fn take_created(&mut self) -> Vec<Resource> {
+        let created = std::mem::take(&mut self.created);
+        let mut resources = Vec::new();
+        if created.gc {
+            resources.push(Resource::Gc(self.gc));
+        }
+        if created.text_gc {
+            resources.push(Resource::Gc(self.text_gc));
+        }
+        if created.cursor {
+            resources.push(Resource::Cursor(self.cursor));
+        }
+        if created.window {
+            resources.push(Resource::Window(self.lock));
+        }
+        if let (true, Some(colormap)) = (created.colormap, self.colormap) {
+            resources.push(Resource::Colormap(colormap));
+        }
+        resources
+    }
+}
+
+// one step of setting up a screen, in the order create_in_order takes them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Part {
+    Colormap,
+    Window,
+    // painted and mapped, nothing new to free
+    Map,
+    Gc,
+    TextGc,
+}
+
+// runs `create` for each part a screen needs and records what exists as it goes, so a part
+// failing half way through leaves `created` naming exactly what has to be freed
+fn create_in_order(
+    created: &mut Created,
+    colormap: bool,
+    font: bool,
+    mut create: impl FnMut(Part) -> Result<(), LockError>,
+) -> Result<(), LockError> {
+    if colormap {
+        create(Part::Colormap)?;
+        created.colormap = true;
+    }
+    create(Part::Window)?;
+    created.window = true;
+    create(Part::Map)?;
+    create(Part::Gc)?;
+    created.gc = true;
+    if font {
+        create(Part::TextGc)?;
+        created.text_gc = true;
+    }
+    Ok(())
+}
+
+/// What happened to a [`Lock`] while it waits for the password, see [`Lock::on_event`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockEvent {
+    /// A wrong password, with the number of wrong ones since the screen was locked
+    Failed(u32),
+    /// Another client took the keyboard or pointer away; input may reach other windows
+    /// until [`LockEvent::GrabsRestored`]
+    GrabsLost,
+    GrabsRestored,
+    /// The user authenticated, [`Locker::authenticate`] returns right after
+    Unlocked,
+}
+
+/// Indicator pixel values, as `0xRRGGBB` for TrueColor visuals
+#[derive(Debug, Clone, Copy)]
+pub struct Colors {
+    pub idle: u32,
+    pub input: u32,
+    pub error: u32,
+    pub caps: u32,
+    pub text: u32,
+    pub dots: u32,
+    /// The idle indicator fades towards this and back when breathing
+    pub breathe: u32,
+    /// The whole screen after a wrong password, see [`LockBuilder::flash`]
+    pub flash: u32,
+    /// The indicator while the one-time code is asked for, see [`LockBuilder::totp_secret_file`]
+    pub code: u32,
+    /// The glyph and its outline with [`PointerMode::Decorative`]
+    pub cursor: u32,
+    pub cursor_outline: u32,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            idle: 0x333333,
+            input: 0x005577,
+            error: 0xcc3333,
+            caps: 0xcc8800,
+            text: 0xdddddd,
+            dots: 0xdddddd,
+            breathe: 0x111111,
+            flash: 0xcc3333,
+            code: 0x775599,
+            cursor: 0xdddddd,
+            cursor_outline: 0x333333,
+        }
+    }
+}
+
+/// What the pointer looks like and does while locked; it never leaves the lock windows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PointerMode {
+    /// A fully transparent cursor
+    #[default]
+    Hidden,
+    /// Hidden and frozen in place: the server queues its motion until the lock is gone.
+    /// With the on-screen keyboard it keeps moving, taps need it
+    Frozen,
+    /// The normal cursor, to see that the pointer is held
+    Visible,
+    /// A circle from the cursor font in the text and idle colors
+    Decorative,
+    /// Not grabbed at all, only the keyboard is, for remote control and accessibility
+    /// tools that drive the pointer themselves. The pointer can then click any window that
+    /// shows up above the lock, a notification for one, and other clients see it move
+    Ungrabbed,
+}
+
+impl FromStr for PointerMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hidden" => Ok(PointerMode::Hidden),
+            "frozen" => Ok(PointerMode::Frozen),
+            "visible" => Ok(PointerMode::Visible),
+            "decorative" => Ok(PointerMode::Decorative),
+            "ungrabbed" => Ok(PointerMode::Ungrabbed),
+            other => Err(format!(
+                "invalid pointer mode `{other}`, expected hidden, frozen, visible, decorative or ungrabbed"
+            )),
+        }
+    }
+}
+
+/// Configures and locks the screen, see [`Lock::builder`]
+#[derive(Debug)]
+pub struct LockBuilder {
+    backends: AuthBackends,
+    authenticator: Option<CustomAuth>,
+    user: Option<String>,
+    pin: Option<String>,
+    duress: Option<Duress>,
+    colors: Colors,
+    background: Option<Background>,
+    screen: Option<i32>,
+    output: Option<String>,
+    pointer: PointerMode,
+    dpms: Option<Duration>,
+    inhibit_screensaver: bool,
+    suspend: Option<Duration>,
+    input_timeout: Duration,
+    indicator_timeout: Duration,
+    breathe: Option<Duration>,
+    fade_in: Option<Duration>,
+    fade_out: Option<Duration>,
+    flash: Option<Duration>,
+    max_password_len: usize,
+    clear_on_hold: Option<Duration>,
+    escape: EscapeKey,
+    wrong_password: WrongPassword,
+    bindings: Vec<(KeyAction, Vec<String>)>,
+    auto_submit: Option<usize>,
+    clock: Option<String>,
+    dots: Option<(char, usize)>,
+    show_failures: bool,
+    user_line: (UserLine, TextPosition),
+    status: (Vec<StatusField>, TextPosition),
+    osk: bool,
+    image: Option<(PathBuf, ScaleMode)>,
+    output_backgrounds: Vec<(String, Background)>,
+    output_images: Vec<(String, PathBuf)>,
+    pre_lock: Option<String>,
+    post_unlock: Option<String>,
+    bell: Option<i8>,
+    bell_cmd: Option<String>,
+    volume_keys: bool,
+    passthrough: Vec<String>,
+    lockout: Option<Lockout>,
+    retry_limit: u32,
+    backoff_cap: Duration,
+    font: Option<String>,
+    argb: bool,
+    xi2_grab: bool,
+    state_file: bool,
+    attempt_log: Option<PathBuf>,
+    totp_secret: Option<PathBuf>,
+    fingerprint: bool,
+    // Some when enabled, with the token clients must send, if any
+    control: Option<Option<String>>,
+    run: Option<String>,
+    test: bool,
+}
+
+impl Default for LockBuilder {
+    fn default() -> Self {
+        Self {
+            backends: AuthBackends::default(),
+            authenticator: None,
+            user: None,
+            pin: None,
+            duress: None,
+            colors: Colors::default(),
+            background: None,
+            screen: None,
+            output: None,
+            pointer: PointerMode::Hidden,
+            dpms: None,
+            inhibit_screensaver: false,
+            suspend: None,
+            input_timeout: INPUT_TIMEOUT,
+            indicator_timeout: INDICATOR_TIMEOUT,
+            breathe: None,
+            fade_in: None,
+            fade_out: None,
+            flash: None,
+            max_password_len: MAX_BUF_SIZE,
+            clear_on_hold: None,
+            escape: EscapeKey::default(),
+            wrong_password: WrongPassword::default(),
+            bindings: Vec::new(),
+            auto_submit: None,
+            clock: None,
+            dots: None,
+            show_failures: false,
+            user_line: (UserLine::Off, TextPosition::Above),
+            status: (Vec::new(), TextPosition::Below),
+            osk: false,
+            image: None,
+            output_backgrounds: Vec::new(),
+            output_images: Vec::new(),
+            pre_lock: None,
+            post_unlock: None,
+            bell: None,
+            bell_cmd: None,
+            volume_keys: false,
+            passthrough: Vec::new(),
+            lockout: None,
+            retry_limit: RETRY_LIMIT,
+            backoff_cap: BACKOFF_CAP,
+            font: None,
+            argb: false,
+            xi2_grab: false,
+            state_file: false,
+            attempt_log: None,
+            totp_secret: None,
+            fingerprint: false,
+            control: None,
+            run: None,
+            test: false,
+        }
+    }
+}
+
+impl From<&Config> for LockBuilder {
+    fn from(config: &Config) -> Self {
+        Self {
+            backends: config.backends.clone(),
+            user: config.user.clone(),
+            pin: config.pin_hash.clone(),
+            duress: config.duress(),
+            colors: config.colors,
+            background: config.background,
+            output: config.lock_output.clone(),
+            dpms: config.dpms,
+            inhibit_screensaver: config.inhibit_screensaver,
+            suspend: config.suspend_after,
+            input_timeout: config.input_timeout.unwrap_or(INPUT_TIMEOUT),
+            indicator_timeout: config.indicator_timeout.unwrap_or(INDICATOR_TIMEOUT),
+            breathe: config.breathe,
+            fade_in: config.fade_in,
+            fade_out: config.fade_out,
+            flash: config.flash,
+            max_password_len: config.max_password_len,
+            clear_on_hold: config.backspace_hold,
+            escape: config.escape,
+            wrong_password: config.wrong_password,
+            bindings: config.key_bindings(),
+            auto_submit: config.auto_submit_len,
+            clock: config.clock.clone(),
+            dots: config.dots.map(|glyph| (glyph, config.max_dots)),
+            show_failures: config.show_failures,
+            user_line: (config.show_user, config.user_position),
+            status: (config.status.clone(), config.status_position),
+            osk: config.osk,
+            image: config.image.clone().map(|path| (path, config.image_mode)),
+            output_backgrounds: config.output_backgrounds.clone(),
+            output_images: config.output_images.clone(),
+            pre_lock: config.pre_lock.clone(),
+            post_unlock: config.post_unlock.clone(),
+            bell: config.bell,
+            bell_cmd: config.bell_cmd.clone(),
+            pointer: config.pointer,
+            volume_keys: config.volume_keys,
+            passthrough: config.passthrough_keys.clone(),
+            lockout: config.lockout(),
+            retry_limit: config.retry_limit.unwrap_or(RETRY_LIMIT),
+            backoff_cap: config.backoff_cap.unwrap_or(BACKOFF_CAP),
+            font: config.font.clone(),
+            argb: config.argb,
+            xi2_grab: config.xi2_grab,
+            state_file: config.state_file,
+            attempt_log: config.attempt_log.clone(),
+            totp_secret: config.totp_secret_file.clone(),
+            fingerprint: config.fingerprint,
+            control: config.control_socket.then(|| config.control_token.clone()),
+            ..Self::default()
+        }
+    }
+}
+
+impl LockBuilder {
+    /// Background of the lock window, e.g. `0x1d2021` or [`Background::Random`];
+    /// the screen's black pixel if unset
+    pub fn background(mut self, background: impl Into<Background>) -> Self {
+        self.background = Some(background.into());
+        self
+    }
+
+    /// How passwords are checked. PAM doesn't need zlock to run as root, the shadow
+    /// backend does on most systems
+    pub fn auth_backend(mut self, backend: AuthBackend) -> Self {
+        self.backends = backend.into();
+        self
+    }
+
+    /// Several password backends, tried in order until one accepts. A backend that can't
+    /// work here (shadow without root, PAM without its service) is skipped with a warning
+    pub fn auth_backends(mut self, backends: impl IntoIterator<Item = AuthBackend>) -> Self {
+        self.backends = AuthBackends::new(backends);
+        self
+    }
+
+    /// Checks passwords with `authenticator` instead of the backends, PIN and duress
+    /// password, none of which are then looked at or need to work here
+    pub fn authenticator(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.authenticator = Some(CustomAuth(Box::new(authenticator)));
+        self
+    }
+
+    /// The account whose password unlocks; by default the owner of the terminal zlock was
+    /// started from, then `$USER`
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    /// Also unlocks with a short PIN, given as a crypt(3) hash (e.g. from `mkpasswd`);
+    /// the account's password keeps working
+    pub fn pin_hash(mut self, hash: impl Into<String>) -> Self {
+        self.pin = Some(hash.into());
+        self
+    }
+
+    /// A duress password, as a crypt(3) hash: entering it runs `cmd` and is otherwise
+    /// treated exactly like a wrong password, the screen stays locked
+    pub fn duress(mut self, hash: impl Into<String>, cmd: impl Into<String>) -> Self {
+        self.duress = Some(Duress {
+            hash: hash.into(),
+            cmd: cmd.into(),
+        });
+        self
+    }
+
+    pub fn colors(mut self, colors: Colors) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    /// Locks only the given X screen, or all of them with `None` (the default)
+    pub fn screen(mut self, screen: Option<i32>) -> Self {
+        self.screen = screen;
+        self
+    }
+
+    /// Locks only the RandR output `name` (`DP-1`, `HDMI-A-0`, as xrandr lists them), the
+    /// rest of the desktop stays usable. The window covers just that output, the pointer is
+    /// never grabbed whatever [`LockBuilder::pointer`] says, and the keyboard is grabbed only
+    /// while the pointer is over the output. Fails with [`LockError::InvalidOutput`] if no
+    /// active output has that name
+    pub fn output(mut self, name: impl Into<String>) -> Self {
+        self.output = Some(name.into());
+        self
+    }
+
+    /// What the pointer looks like and whether it moves, [`PointerMode::Hidden`] by default
+    pub fn pointer(mut self, mode: PointerMode) -> Self {
+        self.pointer = mode;
+        self
+    }
+
+    /// [`PointerMode::Hidden`] or [`PointerMode::Visible`]
+    pub fn hide_cursor(self, hide: bool) -> Self {
+        self.pointer(match hide {
+            true => PointerMode::Hidden,
+            false => PointerMode::Visible,
+        })
+    }
+
+    /// Turns the monitors off through DPMS once nothing was typed for `delay`,
+    /// the next key turns them back on
+    pub fn dpms(mut self, delay: Duration) -> Self {
+        self.dpms = Some(delay);
+        self
+    }
+
+    /// Switches the X screensaver and the server's DPMS timeouts off while locked, so
+    /// another program's settings can't blank the screen; [`LockBuilder::dpms`] still works.
+    /// The earlier settings are restored when the lock is dropped
+    pub fn inhibit_screensaver(mut self, inhibit: bool) -> Self {
+        self.inhibit_screensaver = inhibit;
+        self
+    }
+
+    /// Suspends the machine through logind once nothing was typed for `delay`, needs the
+    /// `logind` feature. The screen stays locked and the grabs are taken again on resume
+    pub fn suspend_after(mut self, delay: Duration) -> Self {
+        self.suspend = Some(delay);
+        self
+    }
+
+    /// How long a half-typed password is kept without further keystrokes before it's wiped
+    pub fn input_timeout(mut self, timeout: Duration) -> Self {
+        self.input_timeout = timeout;
+        self
+    }
+
+    /// How long the indicator shows the input or error color before going back to idle
+    pub fn indicator_timeout(mut self, timeout: Duration) -> Self {
+        self.indicator_timeout = timeout;
+        self
+    }
+
+    /// Slowly fades the idle indicator to [`Colors::breathe`] and back, redrawing every
+    /// `frame`. It stands still while anything is typed
+    pub fn breathe(mut self, frame: Duration) -> Self {
+        self.breathe = Some(frame);
+        self
+    }
+
+    /// Fades the lock windows in from fully transparent over `duration` once everything is
+    /// grabbed, through the compositor's `_NET_WM_WINDOW_OPACITY`. Without a compositor
+    /// the windows simply show up at once. Typing works from the start
+    pub fn fade_in(mut self, duration: Duration) -> Self {
+        self.fade_in = Some(duration);
+        self
+    }
+
+    /// Fades the lock windows out over `duration` (at most a second) once the password is
+    /// accepted, before [`Locker::authenticate`] returns. The grabs are held until the lock
+    /// is dropped, so keys typed meanwhile still go nowhere. Needs a compositor like
+    /// [`LockBuilder::fade_in`]
+    pub fn fade_out(mut self, duration: Duration) -> Self {
+        self.fade_out = Some(duration);
+        self
+    }
+
+    /// Fills every lock window with [`Colors::flash`] for `duration` after a wrong
+    /// password, on top of the indicator's error color
+    pub fn flash(mut self, duration: Duration) -> Self {
+        self.flash = Some(duration);
+        self
+    }
+
+    /// Longest password accepted, in bytes (500 by default). Keys typed past it are ignored;
+    /// the cap only keeps a stuck key or runaway paste from growing the buffer without bound.
+    /// The buffer is allocated at this size up front, so it's never copied by growing
+    pub fn max_password_len(mut self, len: usize) -> Self {
+        self.max_password_len = len;
+        self
+    }
+
+    /// Holding BackSpace for this long clears the whole password instead of deleting one
+    /// character per repeat
+    pub fn clear_on_hold(mut self, hold: Duration) -> Self {
+        self.clear_on_hold = Some(hold);
+        self
+    }
+
+    /// Keys doing `action` instead of its default key, by keysym name (`KP_Enter`). Unknown
+    /// names are skipped, and an action left without a known key keeps its default
+    pub fn bind_keys(
+        mut self,
+        action: KeyAction,
+        keys: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.bindings
+            .push((action, keys.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    /// Checks the password as soon as `len` characters are typed, for fixed-length PINs.
+    /// Enter still works for anything shorter
+    pub fn auto_submit_len(mut self, len: usize) -> Self {
+        self.auto_submit = Some(len);
+        self
+    }
+
+    /// What Escape does, [`EscapeKey::Clear`] by default
+    pub fn escape(mut self, escape: EscapeKey) -> Self {
+        self.escape = escape;
+        self
+    }
+
+    /// Whether a wrong password clears what was typed, [`WrongPassword::Clear`] by default.
+    /// A wrong one-time code is always cleared
+    pub fn wrong_password(mut self, policy: WrongPassword) -> Self {
+        self.wrong_password = policy;
+        self
+    }
+
+    /// Shows the time above the indicator, `format` is passed to strftime(3)
+    pub fn clock(mut self, format: impl Into<String>) -> Self {
+        self.clock = Some(format.into());
+        self
+    }
+
+    /// Core X font for the clock and other text, as listed by xlsfonts (e.g.
+    /// `-misc-fixed-bold-r-normal--18-*-*-*-*-*-iso8859-1`); `fixed` if it can't be opened.
+    /// The color is [`Colors::text`]
+    pub fn font(mut self, name: impl Into<String>) -> Self {
+        self.font = Some(name.into());
+        self
+    }
+
+    /// Draws one `glyph` per typed character below the indicator, but never more than `max`
+    /// so long passwords don't give away their length. The font only covers Latin-1,
+    /// anything else falls back to `*`
+    pub fn dots(mut self, glyph: char, max: usize) -> Self {
+        self.dots = Some((glyph, max));
+        self
+    }
+
+    /// Shows how many wrong passwords were entered since the screen was locked,
+    /// below the indicator
+    pub fn show_failures(mut self, show: bool) -> Self {
+        self.show_failures = show;
+        self
+    }
+
+    /// Shows whose password is asked for, the user that's authenticated against (and the
+    /// hostname with [`UserLine::UserHost`]), above or below the indicator
+    pub fn show_user(mut self, line: UserLine, position: TextPosition) -> Self {
+        self.user_line = (line, position);
+        self
+    }
+
+    /// Shows a line of battery charge, time and network state, in the order of `fields`,
+    /// above or below the indicator. It's read from sysfs every few seconds; no fields, the
+    /// default, means no line
+    pub fn status_line(
+        mut self,
+        fields: impl IntoIterator<Item = StatusField>,
+        position: TextPosition,
+    ) -> Self {
+        self.status = (fields.into_iter().collect(), position);
+        self
+    }
+
+    /// Draws a QWERTY keyboard along the bottom of the screen that types on a tap, for
+    /// touchscreens without a physical keyboard. Taps and clicks reach the lock then, and
+    /// mice and touchscreens are left out of [`LockBuilder::xi2_grab`]
+    pub fn on_screen_keyboard(mut self, osk: bool) -> Self {
+        self.osk = osk;
+        self
+    }
+
+    /// Shows a PNG or JPEG on every monitor, needs the `image` feature
+    pub fn image(mut self, path: impl Into<PathBuf>, mode: ScaleMode) -> Self {
+        self.image = Some((path.into(), mode));
+        self
+    }
+
+    /// Background for one output by its RandR name (`DP-1`, as listed by `xrandr`), in
+    /// place of [`LockBuilder::background`]. Outputs that aren't named keep the default
+    pub fn output_background(
+        mut self,
+        output: impl Into<String>,
+        background: impl Into<Background>,
+    ) -> Self {
+        self.output_backgrounds
+            .push((output.into(), background.into()));
+        self
+    }
+
+    /// Image for one output by its RandR name, in place of [`LockBuilder::image`] and
+    /// fitted the same way (stretched without one). Needs the `image` feature
+    pub fn output_image(mut self, output: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        self.output_images.push((output.into(), path.into()));
+        self
+    }
+
+    pub fn pre_lock(mut self, cmd: impl Into<String>) -> Self {
+        self.pre_lock = Some(cmd.into());
+        self
+    }
+
+    pub fn post_unlock(mut self, cmd: impl Into<String>) -> Self {
+        self.post_unlock = Some(cmd.into());
+        self
+    }
+
+    /// Rings the X bell on every rejected password, `percent` (-100 to 100) is relative to
+    /// the keyboard's base volume
+    pub fn bell(mut self, percent: i8) -> Self {
+        self.bell = Some(percent.clamp(-100, 100));
+        self
+    }
+
+    /// Shell command spawned on every rejected password, e.g. to play a sound
+    pub fn bell_cmd(mut self, cmd: impl Into<String>) -> Self {
+        self.bell_cmd = Some(cmd.into());
+        self
+    }
+
+    /// Lets the volume keys adjust the default PulseAudio/PipeWire sink through `pactl`
+    /// while locked; by default they, like every other media key, are ignored
+    pub fn volume_keys(mut self, enable: bool) -> Self {
+        self.volume_keys = enable;
+        self
+    }
+
+    /// Keys sent on to the root window instead of typed, by keysym name (`XF86AudioPlay`)
+    /// or `media` for the playback and volume keys. They arrive as synthetic events, which
+    /// clients that grab the keys themselves (most hotkey daemons) ignore
+    pub fn passthrough_keys(mut self, keys: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.passthrough = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Opt-in protection against guessing: every `attempts` wrong passwords in one lock
+    /// session, `cmd` is spawned (e.g. to log out or suspend). Without a command the lock
+    /// stops checking passwords at all once the limit is hit, and only killing zlock
+    /// (from another console or over ssh) ends it — don't use that without another way in
+    pub fn lockout(mut self, attempts: u32, cmd: Option<String>) -> Self {
+        self.lockout = Some(Lockout::new(attempts, cmd));
+        self
+    }
+
+    /// Wrong passwords in a row that are answered right away; after that every further one
+    /// waits 1s, 2s, 4s... before the next can be typed
+    pub fn retry_limit(mut self, attempts: u32) -> Self {
+        self.retry_limit = attempts;
+        self
+    }
+
+    /// The longest wait after a wrong password, however many came before
+    pub fn backoff_cap(mut self, cap: Duration) -> Self {
+        self.backoff_cap = cap;
+        self
+    }
+
+    /// Creates the lock windows with a 32-bit ARGB visual, painted fully opaque, for
+    /// compositors that let a plain window show through. Screens without such a visual
+    /// keep the root visual
+    pub fn argb(mut self, argb: bool) -> Self {
+        self.argb = argb;
+        self
+    }
+
+    /// Also grabs every physical keyboard and pointer through XInput2, so clients that
+    /// listen on the devices themselves rather than the core ones see nothing either.
+    /// Without XInput2 on the server only the core grabs are held
+    pub fn xi2_grab(mut self, enable: bool) -> Self {
+        self.xi2_grab = enable;
+        self
+    }
+
+    /// Keeps `$XDG_RUNTIME_DIR/zlock.state` up to date, `locked` once every screen is
+    /// covered and grabbed and `unlocked` when the lock is gone
+    pub fn state_file(mut self, enable: bool) -> Self {
+        self.state_file = enable;
+        self
+    }
+
+    /// Appends a line with the time of every wrong password, and of every unlock, to `path`,
+    /// which only its owner may read. Failures logged since the last unlock are shown on the
+    /// next lock, even without [`LockBuilder::show_failures`]
+    pub fn attempt_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.attempt_log = Some(path.into());
+        self
+    }
+
+    /// Asks for a six-digit code from an authenticator app once the password is accepted,
+    /// needs the `totp` feature. `path` holds the app's base32 secret and nothing else, and
+    /// must be readable by its owner only; the lock refuses to start otherwise. A wrong code
+    /// counts as a failed attempt and starts over at the password. Fingerprint, control
+    /// socket and [`LockBuilder::run`] unlocks don't ask for a code
+    pub fn totp_secret_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.totp_secret = Some(path.into());
+        self
+    }
+
+    /// Also unlocks with a fingerprint through fprintd's default reader, needs the `fprint`
+    /// feature. Typing keeps working alongside; without a reader only typing does
+    pub fn fingerprint(mut self, enable: bool) -> Self {
+        self.fingerprint = enable;
+        self
+    }
+
+    /// Listens on `$XDG_RUNTIME_DIR/zlock.sock` for `unlock` (or `unlock <token>` when a
+    /// token is given) from processes of the same user or root, and unlocks on it. This is a
+    /// way around the password for anything running as that user, only enable it for
+    /// automation that needs it
+    pub fn control_socket(mut self, token: Option<String>) -> Self {
+        self.control = Some(token);
+        self
+    }
+
+    /// Runs `cmd` through `sh -c` once locked and unlocks when it exits successfully; the
+    /// password still unlocks earlier, leaving the command running. If it fails the lock
+    /// stays until the password is entered
+    pub fn run(mut self, cmd: impl Into<String>) -> Self {
+        self.run = Some(cmd.into());
+        self
+    }
+
+    /// Checks no password at all: submitting anything unlocks, and the lock ends by itself
+    /// after 10 seconds. Meant for trying out colors and layout, never for a real lock
+    pub fn test_mode(mut self) -> Self {
+        self.test = true;
+        self
+    }
+
+    /// Covers the selected screens and grabs the keyboard and pointer
+    pub fn build(mut self) -> Result<Lock, LockError> {
+        let mut lock = Lock::new(&self)?;
+        match self.authenticator.take() {
+            _ if self.test => {
+                log::warn!("test mode: any input unlocks, or wait {TEST_TIMEOUT:?}");
+            }
+            Some(CustomAuth(authenticator)) => {
+                log::debug!("locking with a custom authenticator");
+                lock.authenticator = authenticator;
+            }
+            None => {
+                log::debug!("locking with {:?}", self.backends);
+                lock.authenticator = Box::new(SystemAuth {
+                    backends: self.backends.preflight(&lock.user)?,
+                    user: lock.user.clone(),
+                    pin: self.pin.clone(),
+                    duress: self.duress.clone(),
+                });
+            }
+        }
+        if let Some(cmd) = &self.pre_lock {
+            spawn_hook(cmd);
+        }
+        lock.draw_win()?;
+        lock.init_cursor()?;
+        lock.grab_cursor()?;
+        if lock.output.is_none() {
+            lock.grab_keyboard()?;
+            lock.keyboard_held = true;
+            if self.xi2_grab {
+                lock.grab_devices();
+            }
+            lock.focus();
+        } else {
+            if self.xi2_grab {
+                log::warn!("xi2_grab would hold every keyboard, it's off when locking one output");
+            }
+            if lock.pointer_over_lock()? {
+                lock.hold_keyboard()?;
+            }
+        }
+        if self.inhibit_screensaver {
+            lock.screensaver = Some(ScreenSaver::inhibit(&lock.conn)?);
+        }
+        if let Some(fade) = &mut lock.fade {
+            fade.started = Instant::now();
+        }
+        lock.flush()?;
+        log::info!("locked {} screen(s)", lock.screens.len());
+        if self.state_file {
+            lock.state = StateFile::locked();
+        }
+        #[cfg(feature = "fprint")]
+        if self.fingerprint {
+            lock.fingerprint = crate::fprint::Fingerprint::start(&lock.user)
+                .inspect_err(|e| log::warn!("fprintd: fingerprint unlock unavailable: {e}"))
+                .ok();
+        }
+        #[cfg(not(feature = "fprint"))]
+        if self.fingerprint {
+            log::warn!("zlock was built without the fprint feature, unlock by typing");
+        }
+        if let Some(token) = &self.control {
+            lock.control = ControlSocket::bind(token.clone());
+        }
+        if let Some(cmd) = &self.run {
+            lock.job = Job::spawn(cmd);
+        }
+        Ok(lock)
+    }
+}
+
+// a caller's Authenticator, wrapped for the builder's Debug
+struct CustomAuth(Box<dyn Authenticator>);
+
+impl std::fmt::Debug for CustomAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CustomAuth(..)")
+    }
+}
+
+// the lock windows' opacity going from 0 to opaque, or back once unlocked, one step per
+// frame
+struct Fade {
+    duration: Duration,
+    started: Instant,
+    atom: x::Atom,
+}
+
+// the whole screen in the flash color after a wrong password, since when if it's showing
+struct Flash {
+    duration: Duration,
+    since: Option<Instant>,
+}
+
+// the idle animation: how often a frame is drawn and the color last drawn, so frames that
+// round to the same color send nothing
+#[derive(Debug, Clone, Copy)]
+struct Breathe {
+    frame: Duration,
+    shown: u32,
+}
+
+// where a line of text sits, relative to the indicator in the middle of each screen
+#[derive(Debug, Clone, Copy)]
+enum TextRow {
+    // counted upwards from the line right above the indicator
+    Above(i16),
+    // counted from the first line under the indicator
+    Below(i16),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Indicator {
+    Idle,
+    Input,
+    Error,
+    CapsLock,
+}
+
+impl Indicator {
+    pub(crate) fn for_input(handler: &InputHandler) -> Self {
+        if handler.caps_lock() {
+            Indicator::CapsLock
+        } else if handler.is_empty() {
+            Indicator::Idle
+        } else {
+            Indicator::Input
+        }
+    }
+}
+
+impl Lock {
+    pub fn builder() -> LockBuilder {
+        LockBuilder::default()
+    }
+
+    #[inline]
+    fn new(builder: &LockBuilder) -> Result<Self, LockError> {
+        let signals = SignalGuard::new()?;
+        let (conn, _) = connect(
+            &[xcb::Extension::RandR],
+            &[
+                xcb::Extension::Dpms,
+                xcb::Extension::Input,
+                xcb::Extension::Xkb,
+            ],
+        )?;
+        let instance = Instance::acquire(&std::env::var("DISPLAY").unwrap_or_default())?;
+        // resolved once so a random color is shared by every screen
+        let background = builder.background.map(Background::resolve);
+        let screens = conn
+            .get_setup()
+            .roots()
+            .enumerate()
+            .filter(|(index, _)| builder.screen.is_none_or(|n| n as usize == *index))
+            .map(|(index, screen)| {
+                let argb = builder.argb.then(|| argb_visual(screen)).flatten();
+                if builder.argb && argb.is_none() {
+                    log::warn!("screen {index} has no 32-bit visual, using the root visual");
+                }
+                let alpha = if argb.is_some() { 0xff00_0000 } else { 0 };
+                ScreenLock {
+                    index,
+                    root: screen.root(),
+                    cursor: conn.generate_id(),
+                    lock: conn.generate_id(),
+                    gc: conn.generate_id(),
+                    text_gc: conn.generate_id(),
+                    background: background
+                        .map(|rgb| rgb_to_pixel(screen, rgb))
+                        .unwrap_or(screen.black_pixel())
+                        | alpha,
+                    x: 0,
+                    y: 0,
+                    width: screen.width_in_pixels(),
+                    height: screen.height_in_pixels(),
+                    depth: if argb.is_some() {
+                        32
+                    } else {
+                        screen.root_depth()
+                    },
+                    visual: argb.unwrap_or(screen.root_visual()),
+                    colormap: argb.map(|_| conn.generate_id()),
+                    alpha,
+                    created: Created::default(),
+                }
+            })
+            .collect::<Vec<_>>();
+        if screens.is_empty() {
+            return Err(LockError::InvalidScreen(builder.screen.unwrap_or_default()));
+        }
+        let screens = match &builder.output {
+            Some(name) => only_output(&conn, screens, name)?,
+            None => screens,
+        };
+        let clock = builder.clock.clone().map(Clock::new);
+        let dots = builder.dots.map(|(glyph, max)| {
+            let glyph = u8::try_from(glyph).unwrap_or(DOT_GLYPH);
+            (glyph, max)
+        });
+        let font = if clock.is_some()
+            || dots.is_some()
+            || builder.show_failures
+            || builder.user_line.0 != UserLine::Off
+            || !builder.status.0.is_empty()
+            || builder.osk
+        {
+            Some(TextFont::open(
+                &conn,
+                builder.font.as_deref().unwrap_or(FONT),
+            )?)
+        } else {
+            None
+        };
+        let user = resolve_user(builder.user.as_deref())?;
+        log::debug!("authenticating as {user}");
+        let user_line = match builder.user_line {
+            (UserLine::Off, _) => None,
+            (line, position) => {
+                let text = match (line, hostname()) {
+                    (UserLine::UserHost, Some(host)) => format!("{user}@{host}"),
+                    _ => user.clone(),
+                };
+                let row = match position {
+                    // above the clock if there is one
+                    TextPosition::Above => TextRow::Above(i16::from(clock.is_some())),
+                    // under the dots and the failure count
+                    TextPosition::Below => TextRow::Below(2),
+                };
+                Some((text, row))
+            }
+        };
+        let status = match &builder.status {
+            (fields, _) if fields.is_empty() => None,
+            (fields, position) => {
+                let user_here =
+                    builder.user_line.0 != UserLine::Off && builder.user_line.1 == *position;
+                let row = match position {
+                    // above the clock and the user line
+                    TextPosition::Above => {
+                        TextRow::Above(i16::from(clock.is_some()) + i16::from(user_here))
+                    }
+                    // under the dots, the failure count and the user line
+                    TextPosition::Below => TextRow::Below(2 + i16::from(user_here)),
+                };
+                Some((Status::new(fields.clone()), row))
+            }
+        };
+        let dpms = builder
+            .dpms
+            .map(|delay| Dpms::new(&conn, delay))
+            .transpose()?
+            .flatten();
+        #[cfg(feature = "image")]
+        let wallpaper = builder
+            .image
+            .as_ref()
+            .map(|(path, mode)| crate::wallpaper::Wallpaper::load(path, *mode))
+            .transpose()?;
+        #[cfg(feature = "image")]
+        let output_images = builder
+            .output_images
+            .iter()
+            .map(|(output, path)| {
+                let mode = builder.image.as_ref().map(|(_, mode)| *mode);
+                crate::wallpaper::Wallpaper::load(path, mode.unwrap_or_default())
+                    .map(|wallpaper| (output.clone(), wallpaper))
+            })
+            .collect::<Result<_, _>>()?;
+        #[cfg(not(feature = "image"))]
+        if builder.image.is_some() || !builder.output_images.is_empty() {
+            return Err(LockError::Image(
+                "zlock was built without the image feature".to_owned(),
+            ));
+        }
+        let attempt_log = builder.attempt_log.as_deref().map(AttemptLog::open);
+        #[cfg(feature = "totp")]
+        let totp = builder
+            .totp_secret
+            .as_deref()
+            .map(crate::totp::Totp::load)
+            .transpose()?;
+        #[cfg(not(feature = "totp"))]
+        if builder.totp_secret.is_some() {
+            return Err(LockError::Totp(
+                "zlock was built without the totp feature".to_owned(),
+            ));
+        }
+        let fade_in = builder.fade_in.filter(|duration| !duration.is_zero());
+        let fade_out = builder
+            .fade_out
+            .filter(|duration| !duration.is_zero())
+            .map(|duration| duration.min(MAX_FADE_OUT));
+        let atom = match fade_in.or(fade_out) {
+            Some(_) => {
+                let cookie = conn.send_request(&x::InternAtom {
+                    only_if_exists: false,
+                    name: b"_NET_WM_WINDOW_OPACITY",
+                });
+                conn.wait_for_reply(cookie)
+                    .map_err(LockError::Connection)?
+                    .atom()
+            }
+            None => x::ATOM_NONE,
+        };
+        let fade = |duration| Fade {
+            duration,
+            started: Instant::now(),
+            atom,
+        };
+        let (retry_limit, backoff_cap) = guess_brake(builder.retry_limit, builder.backoff_cap);
+        Ok(Self {
+            screens,
+            conn,
+            // not preflighted yet, the builder replaces it unless in test mode
+            authenticator: Box::new(SystemAuth {
+                backends: builder.backends.clone(),
+                user: user.clone(),
+                pin: builder.pin.clone(),
+                duress: builder.duress.clone(),
+            }),
+            user,
+            duress: builder.duress.clone(),
+            retry_limit,
+            backoff_cap,
+            input_timeout: builder.input_timeout,
+            max_password_len: builder.max_password_len,
+            clear_on_hold: builder.clear_on_hold,
+            escape: builder.escape,
+            wrong_password: builder.wrong_password,
+            bindings: Bindings::new(&builder.bindings),
+            auto_submit: builder.auto_submit,
+            colors: builder.colors,
+            // the pointer moves freely between the locked output and the rest
+            pointer: match builder.output {
+                Some(_) => PointerMode::Ungrabbed,
+                None => builder.pointer,
+            },
+            output: builder.output.clone(),
+            keyboard_held: false,
+            dpms,
+            screensaver: None,
+            suspend: builder.suspend.and_then(Suspend::new),
+            indicator: Indicator::Idle,
+            indicator_since: Instant::now(),
+            indicator_timeout: builder.indicator_timeout,
+            fade: fade_in.map(fade),
+            fade_out: fade_out.map(fade),
+            flash: builder
+                .flash
+                .filter(|duration| !duration.is_zero())
+                .map(|duration| Flash {
+                    duration,
+                    since: None,
+                }),
+            breathe: builder.breathe.map(|frame| Breathe {
+                // anything faster is invisible and only burns CPU
+                frame: frame.max(MIN_FRAME),
+                shown: builder.colors.idle,
+            }),
+            clock,
+            dots,
+            typed: 0,
+            show_failures: builder.show_failures,
+            user_line,
+            status,
+            osk: builder.osk.then(Osk::default),
+            failures: 0,
+            earlier_failures: attempt_log.as_ref().map_or(0, AttemptLog::since_unlock),
+            attempt_log,
+            #[cfg(feature = "totp")]
+            totp,
+            code_pending: false,
+            font,
+            #[cfg(feature = "image")]
+            wallpaper,
+            output_backgrounds: builder
+                .output_backgrounds
+                .iter()
+                .map(|(output, background)| (output.clone(), background.resolve()))
+                .collect(),
+            #[cfg(feature = "image")]
+            output_images,
+            post_unlock: builder.post_unlock.clone(),
+            bell: builder.bell,
+            bell_cmd: builder.bell_cmd.clone(),
+            volume_keys: builder.volume_keys,
+            passthrough: Passthrough::new(&builder.passthrough),
+            lockout: builder.lockout.clone(),
+            locked_out: false,
+            grab_lost: false,
+            attempts: 0,
+            total_attempts: 0,
+            unlocked: false,
+            on_event: None,
+            test: builder.test,
+            #[cfg(feature = "fprint")]
+            fingerprint: None,
+            #[cfg(feature = "logind")]
+            session_unlock: None,
+            control: None,
+            job: None,
+            devices: Vec::new(),
+            _instance: instance,
+            state: None,
+            signals,
+        })
+    }
+
+    #[inline]
+    fn draw_win(&mut self) -> Result<(), LockError> {
+        for index in 0..self.screens.len() {
+            if let Err(e) = self.create_window(index) {
+                // the screens that did work are cleaned up too, nothing stays behind
+                self.release_screens();
+                return Err(e);
+            }
+        }
+        self.draw_indicator(Indicator::Idle);
+        self.draw_dots(0)?;
+        self.draw_failures(0)?;
+        self.draw_user()?;
+        self.draw_osk()?;
+        self.draw_status(true)?;
+        self.draw_clock(true)
+    }
+
+    // the lock window and its gcs on one screen, each recorded as soon as it exists
+    fn create_window(&mut self, index: usize) -> Result<(), LockError> {
+        let scr = &self.screens[index];
+        let mut created = scr.created;
+        let (root, colormap) = (scr.root, scr.colormap.is_some());
+        let result = create_in_order(&mut created, colormap, self.font.is_some(), |part| {
+            self.create_part(index, part)
+        });
+        self.screens[index].created = created;
+        result?;
+        // outputs may be plugged in or resized while locked
+        self.conn.send_request(&randr::SelectInput {
+            window: root,
+            enable: randr::NotifyMask::SCREEN_CHANGE,
+        });
+        Ok(())
+    }
+
+    fn create_part(&self, index: usize, part: Part) -> Result<(), LockError> {
+        let scr = &self.screens[index];
+        let screen = self
+            .conn
+            .get_setup()
+            .roots()
+            .nth(scr.index)
+            .expect("unexpected failure while getting screen");
+        match part {
+            Part::Colormap => self.conn.send_and_check_request(&x::CreateColormap {
+                alloc: x::ColormapAlloc::None,
+                mid: scr.colormap.expect("only created with an ARGB visual"),
+                window: scr.root,
+                visual: scr.visual,
+            }),
+            Part::Window => {
+                // without a pointer grab the on-screen keyboard's taps arrive at the window itself
+                let taps = match (self.pointer, &self.osk) {
+                    (PointerMode::Ungrabbed, Some(_)) => x::EventMask::BUTTON_PRESS,
+                    _ => x::EventMask::empty(),
+                };
+                // a single locked output holds the keyboard while the pointer is over it
+                let crossing = match self.output {
+                    Some(_) => x::EventMask::ENTER_WINDOW | x::EventMask::LEAVE_WINDOW,
+                    None => x::EventMask::empty(),
+                };
+                self.conn.send_and_check_request(&x::CreateWindow {
+                    depth: scr.depth,
+                    wid: scr.lock,
+                    parent: screen.root(),
+                    x: scr.x,
+                    y: scr.y,
+                    width: scr.width,
+                    height: scr.height,
+                    border_width: 0,
+                    class: x::WindowClass::CopyFromParent,
+                    visual: scr.visual,
+                    // a visual other than the parent's needs its own colormap and border pixel
+                    value_list: &[
+                        x::Cw::BackPixel(scr.background),
+                        x::Cw::BorderPixel(scr.background),
+                        x::Cw::OverrideRedirect(true),
+                        // releases are needed too, otherwise xkb never sees modifiers (Caps
+                        // Lock) go up. Focus and unmap events tell when the grabs were taken
+                        // away
+                        x::Cw::EventMask(
+                            x::EventMask::KEY_PRESS
+                                | x::EventMask::KEY_RELEASE
+                                | x::EventMask::EXPOSURE
+                                | x::EventMask::FOCUS_CHANGE
+                                | x::EventMask::STRUCTURE_NOTIFY
+                                | taps
+                                | crossing,
+                        ),
+                        x::Cw::Colormap(scr.colormap.unwrap_or(x::COLORMAP_NONE)),
+                    ],
+                })
+            }
+            Part::Map => {
+                self.paint_background(scr, screen)?;
+                // transparent from the first frame on, not just from the first step of the fade
+                if let Some(fade) = &self.fade {
+                    set_opacity(&self.conn, scr.lock, fade.atom, 0);
+                }
+                self.conn
+                    .send_and_check_request(&x::MapWindow { window: scr.lock })
+            }
+            Part::Gc => self.conn.send_and_check_request(&x::CreateGc {
+                cid: scr.gc,
+                drawable: x::Drawable::Window(scr.lock),
+                value_list: &[x::Gc::Foreground(self.colors.idle | scr.alpha)],
+            }),
+            Part::TextGc => {
+                let font = self.font.as_ref().expect("only created with a font");
+                self.conn.send_and_check_request(&x::CreateGc {
+                    cid: scr.text_gc,
+                    drawable: x::Drawable::Window(scr.lock),
+                    value_list: &[
+                        x::Gc::Foreground(self.colors.text | scr.alpha),
+                        x::Gc::Background(scr.background),
+                        x::Gc::Font(font.id()),
+                    ],
+                })
+            }
+        }
+        .map_err(LockError::CreateWindow)
+    }
+
+    // per-output colors and images go into a pixmap behind the window; without any the
+    // window's background pixel already covers everything
+    fn paint_background(&self, scr: &ScreenLock, screen: &x::Screen) -> Result<(), LockError> {
+        #[cfg(feature = "image")]
+        let images = self.wallpaper.is_some() || !self.output_images.is_empty();
+        #[cfg(not(feature = "image"))]
+        let images = false;
+        if !images && self.output_backgrounds.is_empty() {
+            return Ok(());
+        }
+        let convert = pixel_converter(screen);
+        // a single locked output is all there is, at the window's origin
+        let outputs: Vec<_> = outputs(&self.conn, scr.root, scr.width, scr.height)
+            .into_iter()
+            .filter(|output| self.output.is_none() || output.name == self.output)
+            .map(|mut output| {
+                output.area.x -= scr.x;
+                output.area.y -= scr.y;
+                output
+            })
+            .collect();
+        let fills = outputs.iter().map(|output| {
+            let pixel = by_output(&self.output_backgrounds, output)
+                .map_or(scr.background, |rgb| convert(*rgb) | scr.alpha);
+            (output, pixel)
+        });
+        #[cfg(feature = "image")]
+        if images {
+            let areas: Vec<_> = fills
+                .map(|(output, pixel)| {
+                    let wallpaper =
+                        by_output(&self.output_images, output).or(self.wallpaper.as_ref());
+                    (output.area, pixel, wallpaper)
+                })
+                .collect();
+            return crate::wallpaper::apply(
+                &self.conn,
+                screen,
+                scr.lock,
+                scr.depth,
+                scr.background,
+                scr.alpha,
+                &areas,
+            );
+        }
+        let fills: Vec<_> = fills.map(|(output, pixel)| (output.area, pixel)).collect();
+        crate::wallpaper::fill_outputs(
+            &self.conn,
+            scr.lock,
+            scr.depth,
+            scr.width,
+            scr.height,
+            scr.background,
+            &fills,
+        )
+    }
+
+    // every message goes through here: centered on each screen, in the configured font
+    fn draw_text(&self, row: TextRow, color: u32, text: &[u8]) -> Result<(), LockError> {
+        let Some(font) = &self.font else {
+            return Ok(());
+        };
+        for scr in &self.screens {
+            let top = (scr.height.saturating_sub(INDICATOR_SIZE) / 2) as i16;
+            let baseline = match row {
+                TextRow::Above(line) => {
+                    top - TEXT_MARGIN - line * (font.line_height() + TEXT_MARGIN / 2)
+                }
+                TextRow::Below(line) => {
+                    top + INDICATOR_SIZE as i16
+                        + TEXT_MARGIN
+                        + font.ascent()
+                        + line * (font.line_height() + TEXT_MARGIN / 2)
+                }
+            };
+            font.draw_centered(
+                &self.conn,
+                scr.lock,
+                scr.text_gc,
+                color | scr.alpha,
+                scr.width,
+                baseline,
+                text,
+            )?;
+        }
+        Ok(())
+    }
+
+    // redraws only when the formatted time changed, unless `force`d after a repaint
+    fn draw_clock(&mut self, force: bool) -> Result<(), LockError> {
+        let Some(clock) = &mut self.clock else {
+            return Ok(());
+        };
+        if !clock.tick() && !force {
+            return Ok(());
+        }
+        let Some(clock) = &self.clock else {
+            return Ok(());
+        };
+        self.draw_text(TextRow::Above(0), self.colors.text, clock.text().as_bytes())
+    }
+
+    // one glyph per typed char, centered just below the indicator
+    fn draw_dots(&mut self, typed: usize) -> Result<(), LockError> {
+        self.typed = typed;
+        let Some((glyph, max)) = self.dots else {
+            return Ok(());
+        };
+        let text = vec![glyph; typed.min(max)];
+        self.draw_text(TextRow::Below(0), self.colors.dots, &text)
+    }
+
+    // a line below the dots, left empty until the first wrong password; failures the attempt
+    // log kept from earlier locks show there first
+    fn draw_failures(&mut self, failures: u32) -> Result<(), LockError> {
+        self.failures = failures;
+        if !self.show_failures && self.earlier_failures == 0 && !self.code_pending {
+            return Ok(());
+        }
+        let text = match (failures, self.earlier_failures) {
+            _ if self.code_pending => "enter the code from your authenticator".to_owned(),
+            (0, 0) => String::new(),
+            (0, 1) => "1 failed attempt since the last unlock".to_owned(),
+            (0, n) => format!("{n} failed attempts since the last unlock"),
+            _ if !self.show_failures => String::new(),
+            (1, _) => "1 failed attempt".to_owned(),
+            (n, _) => format!("{n} failed attempts"),
+        };
+        self.draw_text(TextRow::Below(1), self.colors.text, text.as_bytes())
+    }
+
+    fn draw_user(&self) -> Result<(), LockError> {
+        let Some((text, row)) = &self.user_line else {
+            return Ok(());
+        };
+        self.draw_text(*row, self.colors.text, text.as_bytes())
+    }
+
+    // like the clock, redrawn only when the text changed unless `force`d
+    fn draw_status(&mut self, force: bool) -> Result<(), LockError> {
+        let Some((status, row)) = &mut self.status else {
+            return Ok(());
+        };
+        if !status.tick() && !force {
+            return Ok(());
+        }
+        let row = *row;
+        let Some((status, _)) = &self.status else {
+            return Ok(());
+        };
+        self.draw_text(row, self.colors.text, status.text().as_bytes())
+    }
+
+    fn draw_osk(&self) -> Result<(), LockError> {
+        let (Some(osk), Some(font)) = (&self.osk, &self.font) else {
+            return Ok(());
+        };
+        for scr in &self.screens {
+            osk.draw(
+                &self.conn,
+                scr.lock,
+                scr.text_gc,
+                font,
+                self.colors.text | scr.alpha,
+                scr.width,
+                scr.height,
+            )?;
+        }
+        Ok(())
+    }
+
+    // a tap on the on-screen keyboard as the input it stands for, None if it missed or only
+    // toggled Shift
+    fn osk_tap(
+        &mut self,
+        press: &x::ButtonPressEvent,
+        handler: &mut InputHandler,
+    ) -> Result<Option<InputEvent>, LockError> {
+        let Some(osk) = &mut self.osk else {
+            return Ok(None);
+        };
+        let Some(scr) = self.screens.iter().find(|scr| scr.lock == press.event()) else {
+            return Ok(None);
+        };
+        let event = match osk.hit(scr.width, scr.height, press.event_x(), press.event_y()) {
+            Some(OskKey::Char(ch)) => handler.type_char(ch),
+            Some(OskKey::Delete) => handler.delete_char(),
+            Some(OskKey::Submit) => InputEvent::Submit,
+            Some(OskKey::Shift) => {
+                osk.toggle_shift();
+                self.draw_osk()?;
+                self.flush()?;
+                return Ok(None);
+            }
+            None => return Ok(None),
+        };
+        Ok(Some(event))
+    }
+
+    // input arriving outside of X, polled along with the connection
+    fn wake_fds(&self) -> Vec<BorrowedFd<'_>> {
+        let mut fds = Vec::new();
+        #[cfg(feature = "fprint")]
+        fds.extend(
+            self.fingerprint
+                .as_ref()
+                .map(crate::fprint::Fingerprint::wake_fd),
+        );
+        #[cfg(feature = "logind")]
+        fds.extend(
+            self.session_unlock
+                .as_ref()
+                .map(crate::logind::SessionUnlock::wake_fd),
+        );
+        fds.extend(self.control.as_ref().map(ControlSocket::wake_fd));
+        fds.extend(self.job.as_ref().map(Job::wake_fd));
+        fds
+    }
+
+    // ends the lock on logind's Unlock for the session, for `zlock logind`
+    #[cfg(feature = "logind")]
+    pub(crate) fn unlock_on_session_unlock(&mut self, unlock: crate::logind::SessionUnlock) {
+        self.session_unlock = Some(unlock);
+    }
+
+    // like the control socket, logind's Unlock comes from someone allowed to unlock the
+    // session (root or its owner, through polkit) and also works after a lockout
+    fn session_unlocked(&self) -> bool {
+        #[cfg(feature = "logind")]
+        if self
+            .session_unlock
+            .as_ref()
+            .is_some_and(crate::logind::SessionUnlock::requested)
+        {
+            log::info!("logind unlocked the session");
+            return true;
+        }
+        false
+    }
+
+    // the job is forgotten once it has exited, its pidfd would otherwise wake every poll
+    fn job_done(&mut self) -> bool {
+        let Some(status) = self.job.as_mut().and_then(Job::finished) else {
+            return false;
+        };
+        self.job = None;
+        if status.success() {
+            log::info!("the command finished, unlocking");
+            true
+        } else {
+            log::warn!("the command failed ({status}), staying locked");
+            false
+        }
+    }
+
+    // the socket is for automation that authenticated some other way, so unlike the
+    // password it also works after a lockout
+    fn control_unlocked(&self) -> bool {
+        self.control
+            .as_ref()
+            .is_some_and(ControlSocket::unlock_requested)
+    }
+
+    // a scan that didn't match flashes the indicator but leaves the typed password alone
+    fn fingerprint_matched(&mut self) -> Result<bool, LockError> {
+        #[cfg(feature = "fprint")]
+        if let Some(matched) = self.fingerprint.as_mut().and_then(|f| f.result()) {
+            if matched && !self.locked_out {
+                log::info!("fingerprint matched");
+                return Ok(true);
+            }
+            self.draw_indicator(Indicator::Error);
+            self.flush()?;
+        }
+        Ok(false)
+    }
+
+    fn wants_code(&self) -> bool {
+        #[cfg(feature = "totp")]
+        return self.totp.is_some();
+        #[cfg(not(feature = "totp"))]
+        false
+    }
+
+    #[cfg_attr(not(feature = "totp"), allow(unused_variables))]
+    fn check_code(&self, code: &str) -> Auth {
+        #[cfg(feature = "totp")]
+        if self.totp.as_ref().is_some_and(|totp| totp.check(code)) {
+            return Auth::Correct;
+        }
+        Auth::Incorrect
+    }
+
+    // an input or error flash that has been on screen long enough
+    fn flash_expired(&self) -> bool {
+        matches!(self.indicator, Indicator::Input | Indicator::Error)
+            && self.indicator_since.elapsed() >= self.indicator_timeout
+    }
+
+    // wakes up early when a flash is due to end before the next regular tick
+    fn next_tick(&self) -> Duration {
+        if self.fade.is_some() {
+            return MIN_FRAME;
+        }
+        let tick = match self.indicator {
+            Indicator::Input | Indicator::Error => self
+                .indicator_timeout
+                .saturating_sub(self.indicator_since.elapsed())
+                .min(TICK),
+            Indicator::Idle => self.breathe.map_or(TICK, |breathe| breathe.frame.min(TICK)),
+            Indicator::CapsLock => TICK,
+        };
+        match self
+            .flash
+            .as_ref()
+            .and_then(|flash| Some((flash.duration, flash.since?)))
+        {
+            Some((duration, since)) => tick.min(duration.saturating_sub(since.elapsed())),
+            None => tick,
+        }
+    }
+
+    // covers every window in the flash color, indicator and text included; only what's
+    // drawn while it shows ends up on top
+    fn start_flash(&mut self) {
+        let Some(flash) = &mut self.flash else {
+            return;
+        };
+        flash.since = Some(Instant::now());
+        for scr in &self.screens {
+            self.conn.send_request(&x::ChangeGc {
+                gc: scr.gc,
+                value_list: &[x::Gc::Foreground(self.colors.flash | scr.alpha)],
+            });
+            self.conn.send_request(&x::PolyFillRectangle {
+                drawable: x::Drawable::Window(scr.lock),
+                gc: scr.gc,
+                rectangles: &[x::Rectangle {
+                    x: 0,
+                    y: 0,
+                    width: scr.width,
+                    height: scr.height,
+                }],
+            });
+        }
+    }
+
+    // the server puts the background back, and the exposes it sends redraw everything on it
+    fn end_flash(&mut self) {
+        let Some(flash) = &mut self.flash else {
+            return;
+        };
+        if flash
+            .since
+            .is_none_or(|since| since.elapsed() < flash.duration)
+        {
+            return;
+        }
+        flash.since = None;
+        for scr in &self.screens {
+            self.conn.send_request(&x::ClearArea {
+                exposures: true,
+                window: scr.lock,
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            });
+        }
+    }
+
+    // the next opacity step; the property is removed at the end, a window without one is
+    // opaque and nothing is left behind for the compositor to blend
+    fn step_fade(&mut self) {
+        let Some(fade) = &self.fade else {
+            return;
+        };
+        let elapsed = fade.started.elapsed();
+        if elapsed >= fade.duration {
+            for scr in &self.screens {
+                self.conn.send_request(&x::DeleteProperty {
+                    window: scr.lock,
+                    property: fade.atom,
+                });
+            }
+            self.fade = None;
+            return;
+        }
+        let opacity =
+            (u32::MAX as f64 * elapsed.as_secs_f64() / fade.duration.as_secs_f64()) as u32;
+        for scr in &self.screens {
+            set_opacity(&self.conn, scr.lock, fade.atom, opacity);
+        }
+    }
+
+    // blocks until the windows are transparent; a connection that fails meanwhile only
+    // ends it early, the password was accepted either way
+    fn run_fade_out(&mut self) {
+        let Some(fade) = self.fade_out.take() else {
+            return;
+        };
+        let started = Instant::now();
+        while started.elapsed() < fade.duration {
+            let left = 1.0 - started.elapsed().as_secs_f64() / fade.duration.as_secs_f64();
+            let opacity = (u32::MAX as f64 * left) as u32;
+            for scr in &self.screens {
+                set_opacity(&self.conn, scr.lock, fade.atom, opacity);
+            }
+            if self.flush().is_err() {
+                return;
+            }
+            std::thread::sleep(MIN_FRAME);
+        }
+    }
+
+    // the next frame of the idle animation, if it changed since the last one
+    fn draw_breathe(&mut self) {
+        let Some(breathe) = &mut self.breathe else {
+            return;
+        };
+        if self.code_pending {
+            return;
+        }
+        let period = BREATHE_PERIOD.as_millis();
+        let t = self.indicator_since.elapsed().as_millis() % period;
+        let level = (t.min(period - t) * 255 / (period / 2)) as u8;
+        let color = blend(self.colors.idle, self.colors.breathe, level);
+        if color == breathe.shown {
+            return;
+        }
+        breathe.shown = color;
+        self.fill_indicator(color);
+    }
+
+    fn draw_indicator(&mut self, state: Indicator) {
+        self.indicator = state;
+        self.indicator_since = Instant::now();
+        let color = match state {
+            // the code prompt keeps its color until the code is in or given up on
+            Indicator::Idle | Indicator::Input if self.code_pending => self.colors.code,
+            Indicator::Idle => self.colors.idle,
+            Indicator::Input => self.colors.input,
+            Indicator::Error => self.colors.error,
+            Indicator::CapsLock => self.colors.caps,
+        };
+        // every redraw restarts the animation from the idle color
+        if let Some(breathe) = &mut self.breathe {
+            breathe.shown = color;
+        }
+        self.fill_indicator(color);
+    }
+
+    fn fill_indicator(&self, color: u32) {
+        for scr in &self.screens {
+            self.conn.send_request(&x::ChangeGc {
+                gc: scr.gc,
+                value_list: &[x::Gc::Foreground(color | scr.alpha)],
+            });
+            self.conn.send_request(&x::PolyFillRectangle {
+                drawable: x::Drawable::Window(scr.lock),
+                gc: scr.gc,
+                rectangles: &[x::Rectangle {
+                    x: (scr.width.saturating_sub(INDICATOR_SIZE) / 2) as i16,
+                    y: (scr.height.saturating_sub(INDICATOR_SIZE) / 2) as i16,
+                    width: INDICATOR_SIZE,
+                    height: INDICATOR_SIZE,
+                }],
+            });
+        }
+    }
+
+    #[inline]
+    fn init_cursor(&mut self) -> Result<(), LockError> {
+        if matches!(self.pointer, PointerMode::Visible | PointerMode::Ungrabbed) {
+            return Ok(());
+        }
+        for index in 0..self.screens.len() {
+            if let Err(e) = self.create_cursor(index) {
+                self.release_screens();
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn create_cursor(&mut self, index: usize) -> Result<(), LockError> {
+        if self.pointer == PointerMode::Decorative {
+            return self.create_glyph_cursor(index);
+        }
+        // a cursor built from a cleared 1x1 bitmap, so its mask lets nothing through; a blank
+        // glyph from the cursor font still shows a faint pointer on some servers
+        let scr = &mut self.screens[index];
+        let pixmap: x::Pixmap = self.conn.generate_id();
+        let gc: x::Gcontext = self.conn.generate_id();
+        self.conn
+            .send_and_check_request(&x::CreatePixmap {
+                depth: 1,
+                pid: pixmap,
+                drawable: x::Drawable::Window(scr.root),
+                width: 1,
+                height: 1,
+            })
+            .map_err(LockError::Cursor)?;
+        // a new pixmap's contents are undefined
+        self.conn.send_request(&x::CreateGc {
+            cid: gc,
+            drawable: x::Drawable::Pixmap(pixmap),
+            value_list: &[x::Gc::Foreground(0)],
+        });
+        self.conn.send_request(&x::PolyFillRectangle {
+            drawable: x::Drawable::Pixmap(pixmap),
+            gc,
+            rectangles: &[x::Rectangle {
+                x: 0,
+                y: 0,
+                width: 1,
+                height: 1,
+            }],
+        });
+        let created = self.conn.send_and_check_request(&x::CreateCursor {
+            cid: scr.cursor,
+            source: pixmap,
+            mask: pixmap,
+            fore_red: 0,
+            fore_green: 0,
+            fore_blue: 0,
+            back_red: 0,
+            back_green: 0,
+            back_blue: 0,
+            x: 0,
+            y: 0,
+        });
+        self.conn.send_request(&x::FreeGc { gc });
+        self.conn.send_request(&x::FreePixmap { pixmap });
+        created.map_err(LockError::Cursor)?;
+        scr.created.cursor = true;
+        Ok(())
+    }
+
+    fn create_glyph_cursor(&mut self, index: usize) -> Result<(), LockError> {
+        let scr = &mut self.screens[index];
+        let font: x::Font = self.conn.generate_id();
+        self.conn
+            .send_and_check_request(&x::OpenFont {
+                fid: font,
+                name: b"cursor",
+            })
+            .map_err(LockError::Cursor)?;
+        let [fore_red, fore_green, fore_blue] = rgb16(self.colors.cursor);
+        let [back_red, back_green, back_blue] = rgb16(self.colors.cursor_outline);
+        // every glyph in the cursor font is followed by its mask
+        let created = self.conn.send_and_check_request(&x::CreateGlyphCursor {
+            cid: scr.cursor,
+            source_font: font,
+            mask_font: font,
+            source_char: CIRCLE_GLYPH,
+            mask_char: CIRCLE_GLYPH + 1,
+            fore_red,
+            fore_green,
+            fore_blue,
+            back_red,
+            back_green,
+            back_blue,
+        });
+        self.conn.send_request(&x::CloseFont { font });
+        created.map_err(LockError::Cursor)?;
+        scr.created.cursor = true;
+        Ok(())
+    }
+
+    // frees whatever exists on every screen; safe to call again, nothing is freed twice
+    fn release_screens(&mut self) {
+        for scr in &mut self.screens {
+            for resource in scr.take_created() {
+                let _ = match resource {
+                    Resource::Gc(gc) => self.conn.send_request(&x::FreeGc { gc }),
+                    Resource::Cursor(cursor) => self.conn.send_request(&x::FreeCursor { cursor }),
+                    Resource::Window(window) => {
+                        self.conn.send_request(&x::DestroyWindow { window })
+                    }
+                    Resource::Colormap(cmap) => self.conn.send_request(&x::FreeColormap { cmap }),
+                };
+            }
+        }
+    }
+
+    // the keyboard grab keeps the key from ever reaching anyone else, so a copy is sent to
+    // whoever listens for keys on the root windows; only the press, that's what media
+    // handlers act on
+    fn forward_key(&self, code: x::Keycode) {
+        log::debug!("passing key {code} through");
+        for scr in &self.screens {
+            let event = x::KeyPressEvent::new(
+                code,
+                x::CURRENT_TIME,
+                scr.root,
+                scr.root,
+                x::WINDOW_NONE,
+                0,
+                0,
+                0,
+                0,
+                x::KeyButMask::empty(),
+                true,
+            );
+            self.conn.send_request(&x::SendEvent {
+                propagate: false,
+                destination: x::SendEventDest::Window(scr.root),
+                event_mask: EventMask::KEY_PRESS,
+                event: &event,
+            });
+        }
+        let _ = self.conn.flush();
+    }
+
+    // another client may briefly hold a grab (menus, notifications), so keep trying for a while
+    fn grab_with_retry(grab: impl Fn() -> xcb::Result<x::GrabStatus>) -> Result<(), x::GrabStatus> {
+        let mut status = x::GrabStatus::AlreadyGrabbed;
+        for attempt in 1..=GRAB_ATTEMPTS {
+            match grab() {
+                Ok(x::GrabStatus::Success) => return Ok(()),
+                Ok(other) => {
+                    log::debug!("grab attempt {attempt}/{GRAB_ATTEMPTS}: {other:?}");
+                    status = other;
+                }
+                Err(e) => log::debug!("grab attempt {attempt}/{GRAB_ATTEMPTS}: {e}"),
+            }
+            std::thread::sleep(GRAB_RETRY_DELAY);
+        }
+        Err(status)
+    }
+
+    // a client holds at most one pointer grab, so grabbing once per screen would only leave
+    // the last one confined. The pointer is held on the screen it's on instead; each lock
+    // window covers its whole root, which spans the bounding box of every RandR output on
+    // that screen, and follows it through resize so the confinement does too
+    #[inline]
+    fn grab_cursor(&self) -> Result<(), LockError> {
+        if self.pointer == PointerMode::Ungrabbed {
+            return Ok(());
+        }
+        let pointer_root = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&x::QueryPointer {
+                window: self.screens[0].root,
+            }))
+            .map(|reply| reply.root())
+            .map_err(LockError::Connection)?;
+        let scr = self
+            .screens
+            .iter()
+            .find(|scr| scr.root == pointer_root)
+            .unwrap_or(&self.screens[0]);
+        log::debug!("confining the pointer to screen {}", scr.index);
+        Self::grab_with_retry(|| {
+            let cookie = self.conn.send_request(&x::GrabPointer {
+                owner_events: false,
+                grab_window: scr.lock,
+                // taps on the on-screen keyboard, nothing else is wanted from the pointer
+                event_mask: match self.osk {
+                    Some(_) => EventMask::BUTTON_PRESS,
+                    None => EventMask::empty(),
+                },
+                // nothing ever allows the events through, the device stays frozen until the
+                // grab goes away
+                pointer_mode: match (self.pointer, &self.osk) {
+                    (PointerMode::Frozen, None) => x::GrabMode::Sync,
+                    _ => x::GrabMode::Async,
+                },
+                keyboard_mode: x::GrabMode::Async,
+                confine_to: scr.lock,
+                cursor: match self.pointer {
+                    PointerMode::Visible => x::CURSOR_NONE,
+                    _ => scr.cursor,
+                },
+                time: x::CURRENT_TIME,
+            });
+            Ok(self.conn.wait_for_reply(cookie)?.status())
+        })
+        .map_err(LockError::GrabPointer)
+    }
+
+    #[inline]
+    fn grab_keyboard(&self) -> Result<(), LockError> {
+        for scr in &self.screens {
+            self.grab_keyboard_on(scr)?;
+        }
+        Ok(())
+    }
+
+    fn grab_keyboard_on(&self, scr: &ScreenLock) -> Result<(), LockError> {
+        Self::grab_with_retry(|| {
+            let cookie = self.conn.send_request(&x::GrabKeyboard {
+                owner_events: true,
+                grab_window: scr.lock,
+                time: x::CURRENT_TIME,
+                pointer_mode: x::GrabMode::Async,
+                keyboard_mode: x::GrabMode::Async,
+            });
+            Ok(self.conn.wait_for_reply(cookie)?.status())
+        })
+        .map_err(LockError::GrabKeyboard)
+    }
+
+    // with a single output locked, whether the pointer is over it right now
+    fn pointer_over_lock(&self) -> Result<bool, LockError> {
+        let scr = &self.screens[0];
+        let pointer = self
+            .conn
+            .wait_for_reply(
+                self.conn
+                    .send_request(&x::QueryPointer { window: scr.lock }),
+            )
+            .map_err(LockError::Connection)?;
+        Ok(pointer.same_screen()
+            && (0..i32::from(scr.width)).contains(&i32::from(pointer.win_x()))
+            && (0..i32::from(scr.height)).contains(&i32::from(pointer.win_y())))
+    }
+
+    fn hold_keyboard(&mut self) -> Result<(), LockError> {
+        self.grab_keyboard()?;
+        self.keyboard_held = true;
+        self.focus();
+        Ok(())
+    }
+
+    // typing goes back to whatever window is under the pointer
+    fn release_keyboard(&mut self) {
+        self.keyboard_held = false;
+        self.conn.send_request(&x::UngrabKeyboard {
+            time: x::CURRENT_TIME,
+        });
+        self.conn.send_request(&x::SetInputFocus {
+            revert_to: x::InputFocus::PointerRoot,
+            focus: x::INPUTFOCUS_POINTER_ROOT,
+            time: x::CURRENT_TIME,
+        });
+    }
+
+    // no other client can take an active grab, but the server drops it when the grab window
+    // stops being viewable or on XF86Ungrab. The keyboard is grabbed again on the last
+    // window only: moving it across screens would report an ungrab of its own. If that
+    // fails the next tick tries again, ending the lock here would unlock the screen
+    fn regrab(&mut self, why: &str) {
+        let Some(scr) = self.screens.last() else {
+            return;
+        };
+        // the pointer is elsewhere, the keyboard isn't the lock's to take back
+        if self.output.is_some() && !self.keyboard_held {
+            return;
+        }
+        match self.grab_cursor().and_then(|()| self.grab_keyboard_on(scr)) {
+            Ok(()) => {
+                log::warn!("{why}, grabbed the keyboard and pointer again");
+                if std::mem::take(&mut self.grab_lost) {
+                    self.emit(LockEvent::GrabsRestored);
+                }
+                self.focus();
+            }
+            Err(e) => {
+                log::error!("{why} and the grabs can't be taken back yet: {e}");
+                if !std::mem::replace(&mut self.grab_lost, true) {
+                    self.emit(LockEvent::GrabsLost);
+                }
+            }
+        }
+    }
+
+    // a grabbed slave device stops feeding its master, so keys arrive as XI2 events
+    // instead of core ones. A device that can't be grabbed is left to the core grabs,
+    // which still hold every key and click that goes through the master devices
+    fn grab_devices(&mut self) {
+        let Some(scr) = self.screens.last() else {
+            return;
+        };
+        if self
+            .conn
+            .active_extensions()
+            .all(|ext| ext != xcb::Extension::Input)
+        {
+            log::warn!("the X server has no XInput extension, holding the core grabs only");
+            return;
+        }
+        let version = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&xinput::XiQueryVersion {
+                major_version: 2,
+                minor_version: 0,
+            }));
+        match version {
+            Ok(reply) if reply.major_version() >= 2 => {}
+            Ok(_) => {
+                log::warn!("the X server doesn't support XInput2, holding the core grabs only");
+                return;
+            }
+            Err(e) => {
+                log::warn!("failed to query the XInput version: {e}");
+                return;
+            }
+        }
+        let devices =
+            match self
+                .conn
+                .wait_for_reply(self.conn.send_request(&xinput::XiQueryDevice {
+                    device: xinput::Device::All,
+                })) {
+                Ok(reply) => reply
+                    .infos()
+                    .filter_map(|info| {
+                        let mask = match info.r#type() {
+                            xinput::DeviceType::SlaveKeyboard => {
+                                xinput::XiEventMask::KEY_PRESS | xinput::XiEventMask::KEY_RELEASE
+                            }
+                            // left to the core grab, whose clicks the on-screen keyboard reads
+                            xinput::DeviceType::SlavePointer
+                                if self.osk.is_some() || self.pointer == PointerMode::Ungrabbed =>
+                            {
+                                return None
+                            }
+                            xinput::DeviceType::SlavePointer
+                            | xinput::DeviceType::FloatingSlave => xinput::XiEventMask::empty(),
+                            _ => return None,
+                        };
+                        Some((info.device(), info.name().to_string(), mask))
+                    })
+                    .collect::<Vec<_>>(),
+                Err(e) => {
+                    log::warn!("failed to list input devices: {e}");
+                    return;
+                }
+            };
+        for (device, name, mask) in devices {
+            let grabbed = self
+                .conn
+                .wait_for_reply(self.conn.send_request(&xinput::XiGrabDevice {
+                    window: scr.lock,
+                    time: x::CURRENT_TIME,
+                    cursor: x::CURSOR_NONE,
+                    device,
+                    mode: x::GrabMode::Async,
+                    paired_device_mode: x::GrabMode::Async,
+                    owner_events: xinput::GrabOwner::NoOwner,
+                    mask: &[mask.bits()],
+                }))
+                .map(|reply| reply.status());
+            match grabbed {
+                Ok(x::GrabStatus::Success) => {
+                    log::debug!("grabbed input device {name}");
+                    self.devices.push(device);
+                }
+                Ok(status) => log::warn!("failed to grab input device {name}: {status:?}"),
+                Err(e) => log::warn!("failed to grab input device {name}: {e}"),
+            }
+        }
+    }
+
+    // the grab already routes every key here, focus is held as well so a window manager
+    // refocusing something underneath never gets to see input before the grab does.
+    // The last screen's window is the one holding the keyboard grab
+    fn focus(&self) {
+        let Some(scr) = self.screens.last() else {
+            return;
+        };
+        let focused = self
+            .conn
+            .send_and_check_request(&x::SetInputFocus {
+                revert_to: x::InputFocus::PointerRoot,
+                focus: scr.lock,
+                time: x::CURRENT_TIME,
+            })
+            .map_err(xcb::Error::from)
+            .and_then(|()| {
+                self.conn
+                    .wait_for_reply(self.conn.send_request(&x::GetInputFocus {}))
+            })
+            .map(|reply| reply.focus());
+        match focused {
+            Ok(window) if window == scr.lock => log::debug!("focused screen {}", scr.index),
+            Ok(window) => log::warn!("failed to focus the lock window, {window:?} has focus"),
+            Err(e) => log::warn!("failed to focus the lock window: {e}"),
+        }
+    }
+
+    #[inline]
+    fn flush(&self) -> Result<(), LockError> {
+        self.conn
+            .flush()
+            .map_err(|e| LockError::Connection(xcb::Error::Connection(e)))
+    }
+
+    /// Covers every screen and grabs the keyboard and pointer
+    #[inline]
+    pub fn lock_screen(config: &Config) -> Result<Lock, LockError> {
+        LockBuilder::from(config).build()
+    }
+
+    /// True until [`Locker::authenticate`] returns successfully. The windows and grabs stay
+    /// until the lock is dropped either way
+    pub fn is_locked(&self) -> bool {
+        !self.unlocked
+    }
+
+    /// Wrong passwords since the screen was locked, including those before the last unlock
+    pub fn failed_attempts(&self) -> u32 {
+        self.total_attempts
+    }
+
+    /// Calls `callback` from inside [`Locker::authenticate`] whenever a [`LockEvent`]
+    /// happens, replacing any earlier one. It runs on the locking thread between two
+    /// events, so it should return quickly; anything slow belongs on a thread of its own
+    pub fn on_event(&mut self, callback: impl FnMut(LockEvent) + Send + 'static) {
+        self.on_event = Some(Box::new(callback));
+    }
+
+    fn emit(&mut self, event: LockEvent) {
+        log::debug!("{event:?}");
+        if let Some(callback) = &mut self.on_event {
+            callback(event);
+        }
+    }
+
+    fn backoff_delay(&self, failures: u32) -> Option<Duration> {
+        backoff_delay(failures, self.retry_limit, self.backoff_cap)
+    }
+
+    // keys pressed while backing off are dropped, the grabs stay in place
+    fn discard_events(&mut self) -> Result<(), LockError> {
+        while let Some(event) = self.conn.poll_for_event().map_err(LockError::Connection)? {
+            if !matches!(
+                event,
+                xcb::Event::X(x::Event::KeyPress(_) | x::Event::KeyRelease(_))
+                    | xcb::Event::Input(xinput::Event::KeyPress(_) | xinput::Event::KeyRelease(_))
+            ) {
+                self.handle_event(event)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: xcb::Event) -> Result<(), LockError> {
+        match event {
+            xcb::Event::RandR(randr::Event::ScreenChangeNotify(change)) => {
+                log::debug!(
+                    "screen {:?} changed to {}x{}",
+                    change.root(),
+                    change.width(),
+                    change.height()
+                );
+                self.resize(change.root(), change.width(), change.height());
+                self.draw_dots(self.typed)?;
+                self.draw_failures(self.failures)?;
+                self.draw_user()?;
+                self.draw_osk()?;
+                self.draw_status(true)?;
+                self.draw_clock(true)?;
+            }
+            // the keyboard grab sits on the last window, an ungrab elsewhere is just it moving
+            xcb::Event::X(x::Event::FocusOut(focus))
+                if focus.mode() == x::NotifyMode::Ungrab
+                    && self
+                        .screens
+                        .last()
+                        .is_some_and(|scr| scr.lock == focus.event())
+                    && (self.output.is_none() || self.keyboard_held) =>
+            {
+                log::warn!("lost the keyboard grab");
+                self.regrab("the grabs were lost");
+            }
+            // unmapped by another client, which released the grabs along with it
+            xcb::Event::X(x::Event::UnmapNotify(unmap))
+                if self.screens.iter().any(|scr| scr.lock == unmap.window()) =>
+            {
+                log::warn!("a lock window was unmapped, mapping it again");
+                self.conn.send_request(&x::MapWindow {
+                    window: unmap.window(),
+                });
+                self.conn.send_request(&x::ConfigureWindow {
+                    window: unmap.window(),
+                    value_list: &[x::ConfigWindow::StackMode(x::StackMode::Above)],
+                });
+                self.regrab("the grabs were lost");
+            }
+            xcb::Event::X(x::Event::EnterNotify(enter))
+                if enter.mode() == x::NotifyMode::Normal && self.output.is_some() =>
+            {
+                log::debug!("the pointer is over the locked output, grabbing the keyboard");
+                if let Err(e) = self.hold_keyboard() {
+                    log::warn!("{e}, typing goes elsewhere until the pointer comes back");
+                }
+            }
+            xcb::Event::X(x::Event::LeaveNotify(leave))
+                if leave.mode() == x::NotifyMode::Normal && self.output.is_some() =>
+            {
+                log::debug!("the pointer left the locked output, releasing the keyboard");
+                self.release_keyboard();
+            }
+            // the server repaints the background itself, only the indicator needs redrawing
+            // and only once the last expose of a series arrives
+            xcb::Event::X(x::Event::Expose(expose)) if expose.count() == 0 => {
+                self.draw_indicator(self.indicator);
+                self.draw_dots(self.typed)?;
+                self.draw_failures(self.failures)?;
+                self.draw_user()?;
+                self.draw_osk()?;
+                self.draw_status(true)?;
+                self.draw_clock(true)?;
+            }
+            _ => return Ok(()),
+        }
+        self.flush()
+    }
+
+    fn ring_bell(&self) {
+        if let Some(percent) = self.bell {
+            self.conn.send_request(&x::Bell { percent });
+        }
+        if let Some(cmd) = &self.bell_cmd {
+            spawn_hook(cmd);
+        }
+    }
+
+    // keeps every lock window covering its whole screen, or its output, above everything else
+    fn resize(&mut self, root: x::Window, width: u16, height: u16) {
+        let Some(index) = self.screens.iter().position(|scr| scr.root == root) else {
+            return;
+        };
+        let area = match &self.output {
+            Some(name) => match outputs(&self.conn, root, width, height)
+                .into_iter()
+                .find(|output| output.name.as_deref() == Some(name))
+            {
+                Some(output) => output.area,
+                None => {
+                    log::warn!("the output {name} went away, the lock window stays where it was");
+                    return;
+                }
+            },
+            None => x::Rectangle {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+        };
+        let scr = &mut self.screens[index];
+        (scr.x, scr.y, scr.width, scr.height) = (area.x, area.y, area.width, area.height);
+        self.conn.send_request(&x::ConfigureWindow {
+            window: scr.lock,
+            value_list: &[
+                x::ConfigWindow::X(area.x.into()),
+                x::ConfigWindow::Y(area.y.into()),
+                x::ConfigWindow::Width(area.width.into()),
+                x::ConfigWindow::Height(area.height.into()),
+                x::ConfigWindow::StackMode(x::StackMode::Above),
+            ],
+        });
+        self.draw_indicator(self.indicator);
+    }
+}
+
+impl Locker for Lock {
+    // a dead connection takes the grabs and windows with it; it's reported as such rather
+    // than as whatever request happened to notice
+    fn authenticate(&mut self) -> Result<(), LockError> {
+        self.wait_for_unlock()
+            .map_err(|e| match self.conn.has_error() {
+                Err(lost) => LockError::ConnectionLost(lost),
+                Ok(()) => e,
+            })
+    }
+}
+
+impl Lock {
+    fn wait_for_unlock(&mut self) -> Result<(), LockError> {
+        let mut handler = InputHandler::new(&self.conn, self.max_password_len)?;
+        handler.pass_through(self.passthrough.clone());
+        handler.clear_on_hold(self.clear_on_hold);
+        handler.escape_key(self.escape);
+        handler.bind_keys(self.bindings.clone());
+        handler.auto_submit(self.auto_submit);
+        self.unlocked = false;
+        let mut last_input = Instant::now();
+        let started = Instant::now();
+        loop {
+            self.signals.check()?;
+            if self.test && started.elapsed() >= TEST_TIMEOUT {
+                log::info!("test mode: timed out");
+                break;
+            }
+            self.draw_clock(false)?;
+            self.draw_status(false)?;
+            self.step_fade();
+            self.end_flash();
+            self.flush()?;
+            let mut event = handler.get_input(&self.conn, self.next_tick(), &self.wake_fds())?;
+            if let InputEvent::Other(xcb::Event::X(x::Event::ButtonPress(press))) = &event {
+                if let Some(tapped) = self.osk_tap(press, &mut handler)? {
+                    event = tapped;
+                }
+            }
+            if matches!(
+                event,
+                InputEvent::Edit | InputEvent::Reset | InputEvent::Submit
+            ) {
+                last_input = Instant::now();
+                if let Some(dpms) = &mut self.dpms {
+                    dpms.wake(&self.conn);
+                }
+                if let Some(suspend) = &mut self.suspend {
+                    suspend.wake();
+                }
+            }
+            match event {
+                InputEvent::Edit => {
+                    self.draw_indicator(Indicator::for_input(&handler));
+                    self.draw_dots(handler.char_count())?;
+                    self.flush()?;
+                    continue;
+                }
+                InputEvent::Reset => {
+                    self.earlier_failures = 0;
+                    self.code_pending = false;
+                    self.draw_indicator(Indicator::Idle);
+                    self.draw_dots(0)?;
+                    self.draw_failures(0)?;
+                    self.flush()?;
+                    continue;
+                }
+                InputEvent::Other(event) => {
+                    self.handle_event(event)?;
+                    continue;
+                }
+                InputEvent::Timeout => {
+                    // a driver or the server may have dropped the grabs on the way down
+                    if self.suspend.as_mut().is_some_and(Suspend::resumed) {
+                        last_input = Instant::now();
+                        self.regrab("resumed from suspend");
+                    } else if self.grab_lost {
+                        self.regrab("the grabs were lost");
+                    }
+                    // don't leave a half-typed password lying around once the user walks away
+                    // a code prompt left alone goes back to the password as well
+                    if (!handler.is_empty() || self.code_pending)
+                        && last_input.elapsed() >= self.input_timeout
+                    {
+                        handler.clear();
+                        self.code_pending = false;
+                        self.draw_indicator(Indicator::for_input(&handler));
+                        self.draw_dots(0)?;
+                        self.draw_failures(self.failures)?;
+                        self.flush()?;
+                    }
+                    if self.flash_expired() {
+                        self.draw_indicator(Indicator::Idle);
+                        self.flush()?;
+                    } else if matches!(self.indicator, Indicator::Idle) && handler.is_empty() {
+                        self.draw_breathe();
+                        self.flush()?;
+                    }
+                    if let Some(dpms) = &mut self.dpms {
+                        dpms.tick(&self.conn, last_input.elapsed())?;
+                    }
+                    if let Some(suspend) = &mut self.suspend {
+                        suspend.tick(last_input.elapsed());
+                    }
+                    continue;
+                }
+                InputEvent::Volume(volume) => {
+                    if self.volume_keys {
+                        spawn_hook(match volume {
+                            Volume::Up => "pactl set-sink-volume @DEFAULT_SINK@ +5%",
+                            Volume::Down => "pactl set-sink-volume @DEFAULT_SINK@ -5%",
+                            Volume::Mute => "pactl set-sink-mute @DEFAULT_SINK@ toggle",
+                        });
+                    }
+                    continue;
+                }
+                InputEvent::Forward(code) => {
+                    self.forward_key(code);
+                    continue;
+                }
+                InputEvent::Wake => {
+                    if self.fingerprint_matched()?
+                        || self.control_unlocked()
+                        || self.session_unlocked()
+                        || self.job_done()
+                    {
+                        break;
+                    }
+                    continue;
+                }
+                InputEvent::Submit if self.test => {
+                    log::info!("test mode: unlocked without a password");
+                    break;
+                }
+                InputEvent::Submit => {}
+            }
+            if self.locked_out {
+                handler.clear();
+                self.draw_indicator(Indicator::Error);
+                self.draw_dots(0)?;
+                self.flush()?;
+                continue;
+            }
+            let Ok(pass) = handler.build_str() else {
+                handler.clear();
+                continue;
+            };
+            // pass borrows the handler's buffer and is only ever lent out, never copied here
+            if pass.is_empty() {
+                self.draw_indicator(Indicator::Idle);
+                self.draw_dots(0)?;
+                self.flush()?;
+            } else {
+                let code = std::mem::take(&mut self.code_pending);
+                let auth = match code {
+                    true => self.check_code(pass),
+                    false => self.authenticator.verify(pass),
+                };
+                match auth {
+                    Auth::Correct if !code && self.wants_code() => {
+                        log::debug!("password accepted, asking for the code");
+                        handler.clear();
+                        self.code_pending = true;
+                        self.draw_indicator(Indicator::Idle);
+                        self.draw_dots(0)?;
+                        self.draw_failures(self.failures)?;
+                        self.flush()?;
+                        continue;
+                    }
+                    Auth::Correct => break,
+                    Auth::Duress => {
+                        if let Some(duress) = &self.duress {
+                            spawn_hook(&duress.cmd);
+                        }
+                    }
+                    Auth::Incorrect => {}
+                }
+                // a wrong code starts over at the password, the code is no use to keep
+                if code || self.wrong_password == WrongPassword::Clear {
+                    handler.clear();
+                }
+                self.draw_indicator(Indicator::Error);
+                self.draw_dots(handler.char_count())?;
+                self.attempts += 1;
+                self.total_attempts += 1;
+                let failures = self.attempts;
+                log::info!("incorrect password, {failures} failed attempt(s)");
+                self.emit(LockEvent::Failed(failures));
+                if let Some(log) = &self.attempt_log {
+                    log.failed();
+                }
+                self.draw_failures(failures)?;
+                self.start_flash();
+                self.ring_bell();
+                self.flush()?;
+                if let Some(lockout) = &self.lockout {
+                    self.locked_out = lockout.on_failure(failures);
+                }
+                if let Some(delay) = self.backoff_delay(failures) {
+                    log::debug!("backing off for {delay:?}");
+                    self.signals.sleep(delay)?;
+                    self.discard_events()?;
+                }
+            }
+        }
+        log::info!("unlocked after {} failed attempt(s)", self.attempts);
+        // a successful unlock resets the counter, the next lock starts without a backoff
+        self.attempts = 0;
+        self.failures = 0;
+        self.unlocked = true;
+        // a test lock opens without a password, it says nothing about who was at the machine
+        if let Some(log) = self.attempt_log.as_ref().filter(|_| !self.test) {
+            log.unlocked();
+        }
+        self.emit(LockEvent::Unlocked);
+        self.run_fade_out();
+        if let Some(cmd) = &self.post_unlock {
+            spawn_hook(cmd);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // the server let go of everything along with the connection
+        if self.conn.has_error().is_err() {
+            return;
+        }
+        if let Some(screensaver) = &self.screensaver {
+            screensaver.restore(&self.conn);
+        }
+        if let Some(dpms) = &mut self.dpms {
+            dpms.restore(&self.conn);
+        }
+        for &device in &self.devices {
+            self.conn.send_request(&xinput::XiUngrabDevice {
+                time: x::CURRENT_TIME,
+                device,
+            });
+        }
+        self.conn.send_request(&x::UngrabKeyboard {
+            time: x::CURRENT_TIME,
+        });
+        if self.pointer != PointerMode::Ungrabbed {
+            self.conn.send_request(&x::UngrabPointer {
+                time: x::CURRENT_TIME,
+            });
+        }
+        self.release_screens();
+        if let Some(font) = &self.font {
+            self.conn.send_request(&x::CloseFont { font: font.id() });
+        }
+        let _ = self.conn.flush();
+    }
+}
+
+// xcb only reports a generic failure without a display, that case gets its own error
+pub(crate) fn connect(
+    mandatory: &[xcb::Extension],
+    optional: &[xcb::Extension],
+) -> Result<(Connection, i32), LockError> {
+    if std::env::var_os("DISPLAY").is_none_or(|display| display.is_empty()) {
+        return Err(LockError::NoDisplay);
+    }
+    Connection::connect_with_extensions(None, mandatory, optional).map_err(LockError::Connect)
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Lockout {
+    attempts: u32,
+    cmd: Option<String>,
+}
+
+impl Lockout {
+    // a lockout after 0 attempts would fire on every failure, it means 1 however it's set
+    pub(crate) fn new(attempts: u32, cmd: Option<String>) -> Self {
+        Self {
+            attempts: attempts.max(1),
+            cmd,
+        }
+    }
+
+    // fires on every `attempts`th failure; true once no password may unlock anymore
+    pub(crate) fn on_failure(&self, failures: u32) -> bool {
+        if !failures.is_multiple_of(self.attempts) {
+            return false;
+        }
+        match &self.cmd {
+            Some(cmd) => {
+                log::warn!("{failures} failed attempts, running the lockout command");
+                spawn_hook(cmd);
+                false
+            }
+            None => {
+                log::warn!("{failures} failed attempts, no longer accepting passwords");
+                true
+            }
+        }
+    }
+}
+
+// the caller of a setuid zlock may be guessing someone's password, so it can only make the
+// backoff stricter than the defaults, never looser
+pub(crate) fn guess_brake(retry_limit: u32, backoff_cap: Duration) -> (u32, Duration) {
+    if !privilege::setuid() {
+        return (retry_limit, backoff_cap);
+    }
+    let clamped = (retry_limit.min(RETRY_LIMIT), backoff_cap.max(BACKOFF_CAP));
+    if clamped != (retry_limit, backoff_cap) {
+        log::warn!("running setuid, keeping retry_limit and backoff_cap at their defaults");
+    }
+    clamped
+}
+
+// nothing for the first `retry_limit` consecutive failures, then 1s, 2s, 4s... capped at `cap`
+pub(crate) fn backoff_delay(failures: u32, retry_limit: u32, cap: Duration) -> Option<Duration> {
+    let over = failures.checked_sub(retry_limit)?.checked_sub(1)?;
+    let delay = Duration::from_secs(1)
+        .checked_mul(1 << over.min(31))
+        .unwrap_or(cap);
+    Some(delay.min(cap))
+}
+
+// 0xRRGGBB as the 16-bit channels cursors are colored with
+fn rgb16(rgb: u32) -> [u16; 3] {
+    [16, 8, 0].map(|shift| ((rgb >> shift) & 0xff) as u16 * 0x101)
+}
+
+// 0 is transparent and u32::MAX opaque, as compositors read _NET_WM_WINDOW_OPACITY
+fn set_opacity(conn: &Connection, window: x::Window, atom: x::Atom, opacity: u32) {
+    conn.send_request(&x::ChangeProperty {
+        mode: x::PropMode::Replace,
+        window,
+        property: atom,
+        r#type: x::ATOM_CARDINAL,
+        data: &[opacity],
+    });
+}
+
+// the screen showing the output `name`, its window shrunk to cover only that output
+fn only_output(
+    conn: &Connection,
+    screens: Vec<ScreenLock>,
+    name: &str,
+) -> Result<Vec<ScreenLock>, LockError> {
+    let mut names = Vec::new();
+    for mut scr in screens {
+        for output in outputs(conn, scr.root, scr.width, scr.height) {
+            if output.name.as_deref() == Some(name) {
+                log::debug!("locking only {name} on screen {}", scr.index);
+                let area = output.area;
+                (scr.x, scr.y, scr.width, scr.height) = (area.x, area.y, area.width, area.height);
+                return Ok(vec![scr]);
+            }
+            names.extend(output.name);
+        }
+    }
+    Err(LockError::InvalidOutput(name.to_owned(), names))
+}
+
+// the setting for `output` by its name, the last one wins; unnamed outputs never match
+fn by_output<'a, T>(settings: &'a [(String, T)], output: &Output) -> Option<&'a T> {
+    let name = output.name.as_deref()?;
+    settings
+        .iter()
+        .rev()
+        .find(|(output, _)| output == name)
+        .map(|(_, setting)| setting)
+}
+
+// None if it can't be read, the line falls back to the user alone
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0)?;
+    String::from_utf8(buf[..len].to_vec()).ok()
+}
+
+// hooks run through the shell in the background, a failing hook never holds up the lock
+pub(crate) fn spawn_hook(cmd: &str) {
+    log::debug!("running hook `{cmd}`");
+    match crate::privilege::shell(cmd).spawn() {
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => log::warn!("failed to run hook `{cmd}`: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backoff_delay, create_in_order, Created, Part, Resource, ScreenLock};
+    use crate::LockError;
+    use std::time::Duration;
+    use xcb::{x, XidNew};
+
+    fn screen(index: u32, created: Created) -> ScreenLock {
+        let id = |n: u32| (index + 1) << 8 | n;
+        unsafe {
+            ScreenLock {
+                index: index as usize,
+                root: x::Window::new(id(1)),
+                cursor: x::Cursor::new(id(2)),
+                lock: x::Window::new(id(3)),
+                gc: x::Gcontext::new(id(4)),
+                text_gc: x::Gcontext::new(id(5)),
+                background: 0,
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+                depth: 32,
+                visual: 0,
+                colormap: Some(x::Colormap::new(id(6))),
+                alpha: 0xff00_0000,
+                created,
+            }
+        }
+    }
+
+    #[test]
+    fn failure_on_second_screen_frees_only_what_exists() {
+        let mut screens = [screen(0, Created::default()), screen(1, Created::default())];
+        let mut attempted = Vec::new();
+        let mut result = Ok(());
+        for (index, scr) in screens.iter_mut().enumerate() {
+            // the second screen fails to map its window, its gcs are never created
+            result = create_in_order(&mut scr.created, true, true, |part| {
+                attempted.push((index, part));
+                match (index, part) {
+                    (1, Part::Map) => Err(LockError::Keymap),
+                    _ => Ok(()),
+                }
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+        assert!(matches!(result, Err(LockError::Keymap)));
+        assert_eq!(attempted.last(), Some(&(1, Part::Map)));
+        let released: Vec<_> = screens
+            .iter_mut()
+            .flat_map(ScreenLock::take_created)
+            .collect();
+        let (first, second) = (&screens[0], &screens[1]);
+        assert_eq!(
+            released,
+            [
+                Resource::Gc(first.gc),
+                Resource::Gc(first.text_gc),
+                Resource::Window(first.lock),
+                Resource::Colormap(first.colormap.unwrap()),
+                Resource::Window(second.lock),
+                Resource::Colormap(second.colormap.unwrap()),
+            ]
+        );
+        // dropping the lock afterwards must not free anything twice
+        assert!(screens.iter_mut().all(|scr| scr.take_created().is_empty()));
+    }
+
+    #[test]
+    fn backoff_doubles_from_the_retry_limit_up_to_the_cap() {
+        let cap = Duration::from_secs(30);
+        let delay = |failures| backoff_delay(failures, 3, cap);
+        assert_eq!(delay(0), None);
+        assert_eq!(delay(2), None);
+        // the limit itself is still answered right away
+        assert_eq!(delay(3), None);
+        assert_eq!(delay(4), Some(Duration::from_secs(1)));
+        assert_eq!(delay(5), Some(Duration::from_secs(2)));
+        assert_eq!(delay(6), Some(Duration::from_secs(4)));
+        assert_eq!(delay(8), Some(Duration::from_secs(16)));
+        assert_eq!(delay(9), Some(cap));
+        assert_eq!(backoff_delay(0, 0, cap), None);
+        assert_eq!(backoff_delay(1, 0, cap), Some(Duration::from_secs(1)));
+        // no overflow however many came before
+        assert_eq!(delay(40), Some(cap));
+        assert_eq!(delay(u32::MAX), Some(cap));
+        assert_eq!(
+            backoff_delay(u32::MAX, 0, Duration::MAX),
+            Some(Duration::from_secs(1 << 31))
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use crate::{Config, Lock, LockError, Locker};
+use std::{
+    io::{Read, Write},
+    os::{
+        fd::{AsFd, BorrowedFd},
+        unix::net::UnixStream,
+    },
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+};
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedFd, OwnedObjectPath},
+};
+
+const DESTINATION: &str = "org.freedesktop.login1";
+const MANAGER_PATH: &str = "/org/freedesktop/login1";
+const MANAGER_IFACE: &str = "org.freedesktop.login1.Manager";
+const SESSION_IFACE: &str = "org.freedesktop.login1.Session";
+
+#[derive(Debug, Clone, Copy)]
+enum LogindEvent {
+    Lock,
+    Suspend,
+    Resume,
+}
+
+// the session's Unlock signal, `loginctl unlock-session`. It has to reach a lock that is
+// blocked in its event loop, so each signal writes a byte to a socket polled next to the X
+// connection, as fingerprint results do
+#[derive(Clone)]
+pub(crate) struct SessionUnlock(Arc<UnixStream>);
+
+impl SessionUnlock {
+    pub(crate) fn wake_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+
+    // true if an Unlock arrived since the last call
+    pub(crate) fn requested(&self) -> bool {
+        let mut drained = [0; 16];
+        let mut requested = false;
+        while matches!((&*self.0).read(&mut drained), Ok(n) if n > 0) {
+            requested = true;
+        }
+        requested
+    }
+}
+
+// holds off suspend until dropped, giving the lock window time to be drawn
+struct SleepInhibitor(#[allow(dead_code)] OwnedFd);
+
+struct Logind {
+    conn: Connection,
+}
+
+impl Logind {
+    fn connect() -> zbus::Result<Self> {
+        Ok(Self {
+            conn: Connection::system()?,
+        })
+    }
+
+    fn manager(&self) -> zbus::Result<Proxy<'static>> {
+        Proxy::new(&self.conn, DESTINATION, MANAGER_PATH, MANAGER_IFACE)
+    }
+
+    fn inhibit_sleep(&self) -> zbus::Result<SleepInhibitor> {
+        let fd: OwnedFd = self.manager()?.call(
+            "Inhibit",
+            &(
+                "sleep",
+                "zlock",
+                "Locking the screen before suspend",
+                "delay",
+            ),
+        )?;
+        Ok(SleepInhibitor(fd))
+    }
+
+    // forwards the session's Lock and the manager's PrepareForSleep signals, and hands
+    // Unlock to whichever lock is up
+    fn watch(&self) -> zbus::Result<(Receiver<LogindEvent>, SessionUnlock)> {
+        let (tx, rx) = mpsc::channel();
+        let manager = self.manager()?;
+        let session_path: OwnedObjectPath =
+            manager.call("GetSessionByPID", &(std::process::id()))?;
+        let session = Proxy::new(&self.conn, DESTINATION, session_path, SESSION_IFACE)?;
+
+        let sleep = manager.receive_signal("PrepareForSleep")?;
+        let sleep_tx = tx.clone();
+        std::thread::spawn(move || {
+            for msg in sleep {
+                let Ok(start) = msg.body().deserialize::<bool>() else {
+                    continue;
+                };
+                let event = match start {
+                    true => LogindEvent::Suspend,
+                    false => LogindEvent::Resume,
+                };
+                if sleep_tx.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        forward(session.receive_signal("Lock")?, LogindEvent::Lock, tx);
+
+        let (wake, mut notify) = UnixStream::pair()?;
+        wake.set_nonblocking(true)?;
+        let unlocks = session.receive_signal("Unlock")?;
+        std::thread::spawn(move || {
+            for _ in unlocks {
+                log::debug!("logind: Unlock");
+                if notify.write_all(&[1]).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok((rx, SessionUnlock(Arc::new(wake))))
+    }
+}
+
+/// Locks on logind's Lock signal and before suspend, holding a delay inhibitor until the
+/// window is up, and unlocks on logind's Unlock signal as the password does
+pub fn run_logind(config: &Config) -> Result<(), LockError> {
+    let logind = Logind::connect().map_err(LockError::Logind)?;
+    let (events, unlock) = logind.watch().map_err(LockError::Logind)?;
+    let mut inhibitor = logind.inhibit_sleep().ok();
+    while let Ok(event) = events.recv() {
+        log::debug!("logind: {event:?}");
+        match event {
+            LogindEvent::Lock | LogindEvent::Suspend => {
+                // an Unlock sent while nothing was locked doesn't carry over to this lock
+                unlock.requested();
+                let mut lock = match Lock::lock_screen(config) {
+                    Ok(lock) => lock,
+                    Err(LockError::AlreadyLocked) => {
+                        log::debug!("already locked");
+                        // the other lock is up, the suspend needn't wait for anything
+                        if let LogindEvent::Suspend = event {
+                            drop(inhibitor.take());
+                        }
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                lock.unlock_on_session_unlock(unlock.clone());
+                drop(inhibitor.take());
+                lock.authenticate()?;
+                drop(lock);
+                // requests that piled up while locked are stale now
+                while events.try_recv().is_ok() {}
+                // the next suspend has to wait for the lock again, whether or not a
+                // Resume was seen while locked
+                if inhibitor.is_none() {
+                    inhibitor = logind.inhibit_sleep().ok();
+                }
+            }
+            LogindEvent::Resume => {
+                if inhibitor.is_none() {
+                    inhibitor = logind.inhibit_sleep().ok();
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// suspends right away; `false` as the interactive flag, polkit won't prompt a locked screen
+pub(crate) fn suspend() -> zbus::Result<()> {
+    Logind::connect()?
+        .manager()?
+        .call_method("Suspend", &(false))?;
+    Ok(())
+}
+
+fn forward(
+    signals: impl Iterator<Item = zbus::Message> + Send + 'static,
+    event: LogindEvent,
+    tx: Sender<LogindEvent>,
+) {
+    std::thread::spawn(move || {
+        for _ in signals {
+            if tx.send(event).is_err() {
+                break;
+            }
+        }
+    });
+}
@@ -1,296 +1,567 @@
-use std::{
-    error::Error,
-    ffi::{CStr, CString},
-    str::Utf8Error,
-    sync::OnceLock,
-    time::Duration,
+use clap::{Parser, Subcommand};
+use std::{path::PathBuf, str::FromStr, time::Duration};
+use zlock::{
+    AuthBackends, Background, Config, EscapeKey, LockBuilder, LockError, Locker, PointerMode,
+    ScaleMode, StatusField, TextPosition, TtyLock, UserLine, WrongPassword,
 };
-use xcb::{
-    x::{self, EventMask},
-    Connection,
-};
-use xkbcommon::xkb;
-
-const MAX_BUF_SIZE: usize = 500;
-const MIN_BUF_CAP: usize = 15;
 
-// TODO: Add proper error handling
-// TODO: Add simple tty lock as well
+// exit statuses besides 0 for unlocked, clap exits with 2 on a bad command line as well and a
+// signal with 128 + its number
+const EXIT_WRONG_PASSWORD: i32 = 1;
+// only a Wayland session refuses flags on its own, clap takes care of the rest
+#[cfg_attr(not(feature = "wayland"), allow(dead_code))]
+const EXIT_USAGE: i32 = 2;
+// nothing was locked
+const EXIT_SETUP: i32 = 3;
+// the lock went away without being unlocked, e.g. the X connection died
+const EXIT_ABORTED: i32 = 4;
+// another zlock already locks the display, this one locked nothing
+const EXIT_ALREADY_LOCKED: i32 = 5;
 
 fn main() {
-    Lock::lock_screen()
-        .expect("failed to lock the screen")
-        .authenticate()
-        .expect("failure occured while trying to authenticate password");
-}
-
-#[derive(Debug, Clone, Copy)]
-enum Auth {
-    Correct,
-    Incorrect,
-}
-
-fn pass_check(pass: &str) -> Auth {
-    let hash = get_hash();
-    if pwhash::unix::verify(pass, hash) {
-        return Auth::Correct;
+    let cli = Cli::parse();
+    // before loading, so problems in the config file are reported too
+    init_logger(cli.verbose);
+    // before anything is read: a setuid zlock handles the config, the environment, files and
+    // commands as whoever started it
+    if let Err(e) = zlock::drop_privileges() {
+        exit_with("failed to start", EXIT_SETUP, e);
+    }
+    // a bare `zlock` locks right away, as `zlock lock` does
+    let mode = cli.mode.unwrap_or(Mode::Lock(cli.lock));
+    let mut config = match &cli.config {
+        Some(path) => Config::load_from(path),
+        None => Config::load(),
+    };
+    mode.settings().apply(&mut config);
+    match mode {
+        Mode::Lock(args) => {
+            config.tty_fallback |= args.tty_fallback;
+            lock_screen(&config, &args);
+        }
+        Mode::Daemon(args) => {
+            zlock::run_daemon(&config, args.idle, args.poll).unwrap_or_else(|e| {
+                exit_with("failure occured while watching for idle", EXIT_ABORTED, e)
+            })
+        }
+        Mode::Tty(_) => lock_tty(&config),
+        Mode::Check(_) => {
+            let correct = zlock::check_stdin(&config)
+                .unwrap_or_else(|e| exit_with("failed to check the password", EXIT_SETUP, e));
+            std::process::exit(if correct { 0 } else { EXIT_WRONG_PASSWORD });
+        }
+        #[cfg(feature = "logind")]
+        Mode::Logind(_) => zlock::run_logind(&config).unwrap_or_else(|e| {
+            exit_with("failure occured while listening to logind", EXIT_ABORTED, e)
+        }),
     }
-    Auth::Incorrect
 }
 
-fn get_hash() -> &'static str {
-    // TODO: Add support for retrieving hash from passwd file if present
-    static HASH: OnceLock<String> = std::sync::OnceLock::new();
-    HASH.get_or_init(|| {
-        let name = CString::new(std::env::var("USER").unwrap()).unwrap();
-        let info = unsafe { libc::getspnam(name.as_ptr()) };
-        if info.is_null() {
-            panic!("Failed to acquire password hash. Make sure the executible is running as root");
+fn lock_screen(config: &Config, args: &LockArgs) {
+    // XWayland only sees X clients, a Wayland session has to be locked by the compositor
+    #[cfg(feature = "wayland")]
+    if std::env::var_os("WAYLAND_DISPLAY").is_some_and(|d| !d.is_empty()) {
+        if args.test || args.run.is_some() {
+            eprintln!("zlock: --test and --run are only supported in X sessions");
+            std::process::exit(EXIT_USAGE);
+        }
+        lock_wayland(config);
+        return;
+    }
+    let mut builder = LockBuilder::from(config);
+    if args.test {
+        builder = builder.test_mode();
+    }
+    if let Some(cmd) = &args.run {
+        builder = builder.run(cmd);
+    }
+    let mut lock = match builder.build() {
+        Ok(lock) => lock,
+        Err(LockError::NoDisplay) if config.tty_fallback && !args.test => {
+            log::warn!("no X display, locking the console instead");
+            lock_tty(config);
+            return;
         }
-        let pass = unsafe { CStr::from_ptr((*info).sp_pwdp) };
-        pass.to_str()
-            .expect("Failed to acquire password hash: cannot convert to String")
-            .to_owned()
-    })
+        // a hotkey fired twice, the screen is locked either way but not by this process
+        Err(LockError::AlreadyLocked) => {
+            log::warn!("{}", LockError::AlreadyLocked);
+            std::process::exit(EXIT_ALREADY_LOCKED);
+        }
+        Err(e) => exit_with("failed to lock the screen", EXIT_SETUP, e),
+    };
+    // the lock is dropped, and its grabs released, before a signal exits below
+    let result = lock.authenticate();
+    drop(lock);
+    result.unwrap_or_else(|e| {
+        exit_with(
+            "failure occured while trying to authenticate password",
+            EXIT_ABORTED,
+            e,
+        )
+    });
 }
 
-// TODO: Implement graceful shutdown/unlock (use Drop trait to: destroy win and cursor, ungrab keyboard and mouse)
-// TODO: Handle multiple screens
-struct Lock {
-    cursor: x::Cursor,
-    lock: x::Window,
-    conn: Connection,
-    scr_no: i32,
+fn lock_tty(config: &Config) {
+    let result = TtyLock::lock_tty(config)
+        .unwrap_or_else(|e| exit_with("failed to lock the terminal", EXIT_SETUP, e))
+        .authenticate();
+    result.unwrap_or_else(|e| {
+        exit_with(
+            "failure occured while trying to authenticate password",
+            EXIT_ABORTED,
+            e,
+        )
+    });
 }
 
-impl Lock {
-    #[inline]
-    fn new() -> Result<Self, Box<dyn Error>> {
-        let (conn, scr_no) = Connection::connect(None)?;
-        let (cursor, lock) = (conn.generate_id(), conn.generate_id());
-        Ok(Self {
-            lock,
-            cursor,
-            conn,
-            scr_no,
-        })
-    }
-
-    #[inline]
-    fn draw_win(&self) -> Result<(), Box<dyn Error>> {
-        let screen = self
-            .conn
-            .get_setup()
-            .roots()
-            .nth(self.scr_no as usize)
-            .expect("unexpected failure while getting screen");
-        self.conn.send_and_check_request(&x::CreateWindow {
-            depth: screen.root_depth(),
-            wid: self.lock,
-            parent: screen.root(),
-            x: 0,
-            y: 0,
-            width: screen.width_in_pixels(),
-            height: screen.height_in_pixels(),
-            border_width: 0,
-            class: x::WindowClass::CopyFromParent,
-            visual: screen.root_visual(),
-            value_list: &[
-                x::Cw::BackPixel(screen.black_pixel()),
-                x::Cw::OverrideRedirect(true),
-                x::Cw::EventMask(x::EventMask::KEY_PRESS),
-            ],
-        })?;
-        self.conn
-            .send_and_check_request(&x::MapWindow { window: self.lock })?;
-        Ok(())
-    }
-
-    #[inline]
-    fn init_cursor(&self) -> Result<(), Box<dyn Error>> {
-        let font: x::Font = self.conn.generate_id();
-        self.conn.send_and_check_request(&x::OpenFont {
-            fid: font,
-            name: "cursor".as_bytes(),
-        })?;
-        self.conn.send_and_check_request(&x::CreateGlyphCursor {
-            cid: self.cursor,
-            source_font: font,
-            mask_font: font,
-            source_char: ' ' as u16,
-            mask_char: ' ' as u16,
-            fore_red: 0,
-            fore_green: 0,
-            fore_blue: 0,
-            back_red: 0,
-            back_green: 0,
-            back_blue: 0,
-        })?;
-        Ok(())
-    }
+#[cfg(feature = "wayland")]
+fn lock_wayland(config: &Config) {
+    let result = zlock::WaylandLock::lock_session(config)
+        .unwrap_or_else(|e| exit_with("failed to lock the session", EXIT_SETUP, e))
+        .authenticate();
+    result.unwrap_or_else(|e| {
+        exit_with(
+            "failure occured while trying to authenticate password",
+            EXIT_ABORTED,
+            e,
+        )
+    });
+}
 
-    #[inline]
-    fn grab_cursor(&self) {
-        self.conn.send_request(&x::GrabPointer {
-            owner_events: false,
-            grab_window: self.lock,
-            event_mask: EventMask::empty(),
-            pointer_mode: x::GrabMode::Async,
-            keyboard_mode: x::GrabMode::Async,
-            confine_to: self.lock,
-            cursor: self.cursor,
-            time: x::CURRENT_TIME,
-        });
+// warnings by default, RUST_LOG takes the usual env_logger filters
+fn init_logger(verbose: bool) {
+    let mut logger =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn"));
+    if verbose {
+        logger.filter_module("zlock", log::LevelFilter::Debug);
     }
+    logger.init();
+}
 
-    #[inline]
-    fn grab_keyboard(&self) {
-        self.conn.send_request(&x::GrabKeyboard {
-            owner_events: true,
-            grab_window: self.lock,
-            time: x::CURRENT_TIME,
-            pointer_mode: x::GrabMode::Async,
-            keyboard_mode: x::GrabMode::Async,
-        });
+// the lock has already cleaned up after itself, exit the way a signal would have or
+// report what went wrong
+fn exit_with(context: &str, code: i32, e: LockError) -> ! {
+    if let LockError::Terminated(signal) = e {
+        std::process::exit(128 + signal);
     }
+    eprintln!("zlock: {context}: {e}");
+    std::process::exit(code);
+}
 
-    #[inline]
-    fn flush(&self) -> Result<(), Box<dyn Error>> {
-        self.conn.flush()?;
-        Ok(())
-    }
+/// A minimal X11 screen locker. Flags override the config file, which overrides the defaults
+#[derive(Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+struct Cli {
+    /// Also log grab attempts, authentication results, hooks and screen changes
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// Config file to read instead of $XDG_CONFIG_HOME/zlock/config
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    mode: Option<Mode>,
+    #[command(flatten)]
+    lock: LockArgs,
+}
 
-    #[inline]
-    fn lock_screen() -> Result<Lock, Box<dyn Error>> {
-        let lock = Lock::new()?;
-        lock.draw_win()?;
-        lock.init_cursor()?;
-        lock.grab_cursor();
-        lock.grab_keyboard();
-        lock.flush()?;
-        Ok(lock)
-    }
+#[derive(Subcommand)]
+enum Mode {
+    /// Lock the X display, or the Wayland session, right away (what a bare `zlock` does)
+    Lock(LockArgs),
+    /// Lock whenever the user has been idle for a while
+    Daemon(DaemonArgs),
+    /// Lock the current terminal instead of the X display
+    Tty(Settings),
+    /// Lock nothing: check one password read from stdin, exit 0 if it's correct, 1 if not
+    Check(Settings),
+    /// Lock on logind's Lock signal and before suspend
+    #[cfg(feature = "logind")]
+    Logind(Settings),
+}
 
-    fn authenticate(&self) -> Result<(), Box<dyn Error>> {
-        let mut handler = InputHandler::new();
-        loop {
-            handler.get_input(&self.conn);
-            let Ok(pass) = handler.build_str() else {
-                handler.clear();
-                continue;
-            };
-            if !pass.is_empty() {
-                if matches!(pass_check(pass), Auth::Correct) {
-                    break;
-                }
-                handler.clear();
-            }
+impl Mode {
+    fn settings(&self) -> &Settings {
+        match self {
+            Mode::Lock(args) => &args.settings,
+            Mode::Daemon(args) => &args.settings,
+            Mode::Tty(settings) | Mode::Check(settings) => settings,
+            #[cfg(feature = "logind")]
+            Mode::Logind(settings) => settings,
         }
-        Ok(())
     }
 }
 
-impl Drop for Lock {
-    fn drop(&mut self) {
-        self.conn.send_request(&x::FreeCursor {
-            cursor: self.cursor,
-        });
-        self.conn.send_request(&x::UngrabKeyboard {
-            time: x::CURRENT_TIME,
-        });
-        self.conn.send_request(&x::UngrabPointer {
-            time: x::CURRENT_TIME,
-        });
-        self.conn
-            .send_request(&x::DestroyWindow { window: self.lock });
-        let _ = self.conn.flush();
-    }
+#[derive(clap::Args)]
+struct LockArgs {
+    /// Check no password: Enter unlocks, and the lock lifts by itself after 10 seconds.
+    /// Only available here, a config file can't turn the password check off
+    #[arg(long)]
+    test: bool,
+    /// Run a command once locked and unlock when it succeeds; the password still unlocks
+    /// early. A failing command leaves the lock in place
+    #[arg(long, value_name = "CMD")]
+    run: Option<String>,
+    /// Lock the console instead when $DISPLAY isn't set
+    #[arg(long)]
+    tty_fallback: bool,
+    #[command(flatten)]
+    settings: Settings,
 }
 
-struct InputHandler {
-    buf: Vec<u8>,
-    len: usize,
-    keyb: Keyb,
+#[derive(clap::Args)]
+struct DaemonArgs {
+    /// Idle time before the daemon locks
+    #[arg(long, value_name = "SECS", default_value = "300", value_parser = parse_secs)]
+    idle: Duration,
+    /// How often the daemon checks for idle, on servers without XSync
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    poll: Duration,
+    #[command(flatten)]
+    settings: Settings,
 }
 
-impl InputHandler {
-    fn new() -> Self {
-        Self {
-            buf: Vec::with_capacity(MIN_BUF_CAP),
-            len: 0,
-            keyb: Keyb::new().expect("failed to acquire keyboard state"),
-        }
-    }
+// what the config file can say too, taken by every mode
+#[derive(clap::Args)]
+struct Settings {
+    /// Account whose password unlocks
+    #[arg(long, value_name = "NAME", help_heading = "Authentication")]
+    user: Option<String>,
+    /// How passwords are checked, several are tried in order (pam,shadow)
+    #[arg(long, value_name = "shadow|pam,...", help_heading = "Authentication")]
+    auth: Option<AuthBackends>,
+    /// Password length cap, in bytes
+    #[arg(long, value_name = "BYTES", help_heading = "Authentication")]
+    max_password_len: Option<usize>,
+    /// Holding BackSpace this long clears everything typed
+    #[arg(long, value_name = "MS", value_parser = parse_millis, help_heading = "Authentication")]
+    backspace_hold_ms: Option<Duration>,
+    /// What Escape does: throw away what was typed, also reset the screen, or nothing
+    #[arg(
+        long,
+        value_name = "clear|reset|ignore",
+        help_heading = "Authentication"
+    )]
+    escape: Option<EscapeKey>,
+    /// Whether a wrong password clears what was typed or keeps it to fix a typo
+    #[arg(long, value_name = "clear|keep", help_heading = "Authentication")]
+    wrong_password: Option<WrongPassword>,
+    /// Check the password as soon as this many characters are typed
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), help_heading = "Authentication")]
+    auto_submit_len: Option<u64>,
+    /// Clear what was typed after this long without a key
+    #[arg(long, value_name = "SECS", value_parser = parse_secs, help_heading = "Authentication")]
+    input_timeout: Option<Duration>,
+    /// Act after this many wrong passwords in one lock
+    #[arg(long, value_name = "ATTEMPTS", value_parser = clap::value_parser!(u32).range(1..), help_heading = "Authentication")]
+    lockout: Option<u32>,
+    /// Command run by the lockout; without one the lock stops accepting passwords
+    #[arg(long, value_name = "CMD", help_heading = "Authentication")]
+    lockout_cmd: Option<String>,
+    /// Wrong passwords in a row before each further one has to wait
+    #[arg(long, value_name = "ATTEMPTS", help_heading = "Authentication")]
+    retry_limit: Option<u32>,
+    /// The longest wait after a wrong password
+    #[arg(long, value_name = "SECS", value_parser = parse_secs, help_heading = "Authentication")]
+    backoff_cap: Option<Duration>,
+    /// Also unlock with a fingerprint through fprintd
+    #[arg(long, help_heading = "Authentication")]
+    fingerprint: bool,
+    /// Unlock on `unlock` sent to $XDG_RUNTIME_DIR/zlock.sock
+    #[arg(long, help_heading = "Authentication")]
+    control_socket: bool,
+    /// Ask for a code from this base32 TOTP secret after the password, needs the totp feature
+    #[arg(long, value_name = "PATH", help_heading = "Authentication")]
+    totp_secret_file: Option<PathBuf>,
 
-    fn clear(&mut self) {
-        self.buf.clear();
-        self.len = 0;
-    }
+    /// Background color, #RRGGBB or random
+    #[arg(long, value_name = "COLOR", help_heading = "Appearance")]
+    background: Option<Background>,
+    /// Background image, needs the image feature
+    #[arg(long, value_name = "PATH", help_heading = "Appearance")]
+    image: Option<PathBuf>,
+    /// How the image is fitted onto each monitor
+    #[arg(long, value_name = "stretch|center|tile", help_heading = "Appearance")]
+    image_mode: Option<ScaleMode>,
+    /// Background color for one output, as named by xrandr; repeatable
+    #[arg(long, value_name = "OUTPUT=COLOR", value_parser = parse_output::<Background>, help_heading = "Appearance")]
+    output_background: Vec<(String, Background)>,
+    /// Background image for one output, as named by xrandr; repeatable
+    #[arg(long, value_name = "OUTPUT=PATH", value_parser = parse_output::<PathBuf>, help_heading = "Appearance")]
+    output_image: Vec<(String, PathBuf)>,
+    /// Indicator color while nothing is typed
+    #[arg(long, value_name = "COLOR", value_parser = zlock::parse_color, help_heading = "Appearance")]
+    idle_color: Option<u32>,
+    /// Indicator color while typing
+    #[arg(long, value_name = "COLOR", value_parser = zlock::parse_color, help_heading = "Appearance")]
+    input_color: Option<u32>,
+    /// Indicator color after a wrong password
+    #[arg(long, value_name = "COLOR", value_parser = zlock::parse_color, help_heading = "Appearance")]
+    error_color: Option<u32>,
+    /// Indicator color while the one-time code is asked for
+    #[arg(long, value_name = "COLOR", value_parser = zlock::parse_color, help_heading = "Appearance")]
+    code_color: Option<u32>,
+    /// Indicator color while Caps Lock is on
+    #[arg(long, value_name = "COLOR", value_parser = zlock::parse_color, help_heading = "Appearance")]
+    caps_color: Option<u32>,
+    /// How long the indicator shows a keystroke or failure before going back to idle
+    #[arg(long, value_name = "MS", value_parser = parse_millis, help_heading = "Appearance")]
+    indicator_ms: Option<Duration>,
+    /// Color of the cursor with --pointer decorative
+    #[arg(long, value_name = "COLOR", value_parser = zlock::parse_color, help_heading = "Appearance")]
+    cursor_color: Option<u32>,
+    /// Outline color of the cursor with --pointer decorative
+    #[arg(long, value_name = "COLOR", value_parser = zlock::parse_color, help_heading = "Appearance")]
+    cursor_outline_color: Option<u32>,
+    /// Keep the pointer visible over the lock windows, short for --pointer visible
+    #[arg(long, conflicts_with = "pointer", help_heading = "Appearance")]
+    show_cursor: bool,
+    /// What the pointer looks like, and whether it moves
+    #[arg(
+        long,
+        value_name = "hidden|frozen|visible|decorative|ungrabbed",
+        help_heading = "Appearance"
+    )]
+    pointer: Option<PointerMode>,
+    /// Draw a clock, formatted with strftime
+    #[arg(long, value_name = "FORMAT", help_heading = "Appearance")]
+    clock: Option<String>,
+    /// Draw one glyph per typed character
+    #[arg(long, value_name = "GLYPH", value_parser = zlock::parse_glyph, help_heading = "Appearance")]
+    dots: Option<char>,
+    /// Draw at most this many glyphs
+    #[arg(long, value_name = "N", help_heading = "Appearance")]
+    max_dots: Option<usize>,
+    /// Show how many wrong passwords were entered
+    #[arg(long, help_heading = "Appearance")]
+    show_failures: bool,
+    /// Show whose password is asked for
+    #[arg(long, value_name = "off|user|user@host", help_heading = "Appearance")]
+    show_user: Option<UserLine>,
+    /// Draw a keyboard to type on by touch or mouse
+    #[arg(long, help_heading = "Appearance")]
+    osk: bool,
+    /// Where the user line goes
+    #[arg(long, value_name = "above|below", help_heading = "Appearance")]
+    user_position: Option<TextPosition>,
+    /// Draw a status line of these fields, e.g. battery,time,network
+    #[arg(long, value_name = "FIELDS", value_parser = parse_status, help_heading = "Appearance")]
+    status: Option<StatusFields>,
+    /// Where the status line goes
+    #[arg(long, value_name = "above|below", help_heading = "Appearance")]
+    status_position: Option<TextPosition>,
+    /// Core X font for the clock and messages
+    #[arg(long, value_name = "NAME", help_heading = "Appearance")]
+    font: Option<String>,
+    /// Draw with an opaque 32-bit ARGB window, for compositors
+    #[arg(long, help_heading = "Appearance")]
+    argb: bool,
+    /// Fade the lock in over this long, needs a compositor
+    #[arg(long, value_name = "MS", value_parser = parse_millis, help_heading = "Appearance")]
+    fade_in_ms: Option<Duration>,
+    /// Fade the lock out over this long once unlocked, needs a compositor
+    #[arg(long, value_name = "MS", value_parser = parse_millis, help_heading = "Appearance")]
+    fade_out_ms: Option<Duration>,
+    /// Flash the whole screen this long after a wrong password
+    #[arg(long, value_name = "MS", value_parser = parse_millis, help_heading = "Appearance")]
+    flash_ms: Option<Duration>,
+    /// Color of that flash
+    #[arg(long, value_name = "COLOR", value_parser = zlock::parse_color, help_heading = "Appearance")]
+    flash_color: Option<u32>,
 
-    fn push_char(&mut self, c: char) {
-        if self.len == MAX_BUF_SIZE {
-            self.clear();
-        }
-        self.buf.push(c as _);
-        self.len += 1;
-    }
+    /// Power the monitors off after this long without typing
+    #[arg(long, value_name = "SECS", value_parser = parse_secs, help_heading = "Behavior")]
+    dpms: Option<Duration>,
+    /// Switch the X screensaver and DPMS timeouts off while locked
+    #[arg(long, help_heading = "Behavior")]
+    inhibit_screensaver: bool,
+    /// Suspend the machine after this long without typing, needs the logind feature
+    #[arg(long, value_name = "SECS", value_parser = parse_secs, help_heading = "Behavior")]
+    suspend_after: Option<Duration>,
+    /// X bell volume on a wrong password, -100 to 100 or off
+    #[arg(long, value_name = "VOLUME", value_parser = parse_bell, help_heading = "Behavior")]
+    bell: Option<Bell>,
+    /// Command run on a wrong password
+    #[arg(long, value_name = "CMD", help_heading = "Behavior")]
+    bell_cmd: Option<String>,
+    /// Let the volume keys work through pactl while locked
+    #[arg(long, help_heading = "Behavior")]
+    volume_keys: bool,
+    /// Keys sent on to the root window instead of typed: keysym names or `media`
+    #[arg(long, value_name = "KEYS", value_parser = parse_keys, help_heading = "Behavior")]
+    passthrough_keys: Option<Keys>,
+    /// Keys that check the password instead of Return, by keysym name
+    #[arg(long, value_name = "KEYS", value_parser = parse_keys, help_heading = "Behavior")]
+    submit_keys: Option<Keys>,
+    /// Keys that do what Escape does instead of it
+    #[arg(long, value_name = "KEYS", value_parser = parse_keys, help_heading = "Behavior")]
+    clear_keys: Option<Keys>,
+    /// Keys that delete a character instead of BackSpace
+    #[arg(long, value_name = "KEYS", value_parser = parse_keys, help_heading = "Behavior")]
+    delete_keys: Option<Keys>,
+    /// Also grab each keyboard and mouse through XInput2
+    #[arg(long, help_heading = "Behavior")]
+    xi2_grab: bool,
+    /// Write locked/unlocked to $XDG_RUNTIME_DIR/zlock.state
+    #[arg(long, help_heading = "Behavior")]
+    state_file: bool,
+    /// Log the time of every wrong password to this file
+    #[arg(long, value_name = "PATH", help_heading = "Behavior")]
+    attempt_log: Option<PathBuf>,
+    /// Command run right before the screen is grabbed
+    #[arg(long, value_name = "CMD", help_heading = "Behavior")]
+    pre_lock: Option<String>,
+    /// Command run once the user has authenticated
+    #[arg(long, value_name = "CMD", help_heading = "Behavior")]
+    post_unlock: Option<String>,
+    /// Lock only this output, by the name xrandr lists, and leave the rest of the desktop usable
+    #[arg(long, value_name = "NAME", help_heading = "Behavior")]
+    lock_output: Option<String>,
+}
 
-    fn pop_char(&mut self) {
-        self.buf.pop();
-        self.len = self.len.saturating_sub(1);
-    }
+// clap would take a Vec as a repeated flag, the list is one value here as in the config
+#[derive(Clone)]
+struct Keys(Vec<String>);
 
-    fn build_str(&self) -> Result<&str, Utf8Error> {
-        std::str::from_utf8(&self.buf[..self.len])
-    }
+#[derive(Clone)]
+struct StatusFields(Vec<StatusField>);
 
-    fn get_input(&mut self, conn: &Connection) {
-        loop {
-            let xcb::Event::X(x::Event::KeyPress(key_press)) =
-                conn.wait_for_event().expect("failed to poll for event")
-            else {
-                continue;
-            };
-            let code = key_press.detail();
-            match self.keyb.keycode_to_keysym(code) {
-                xkb::Keysym::Return => {
-                    break;
-                }
-                xkb::Keysym::Escape => {
-                    self.clear();
-                }
-                xkb::Keysym::BackSpace => {
-                    self.pop_char();
-                }
-                other => {
-                    let Some(ch) = Keyb::keysym_to_char(other) else {
-                        // password will be invalid anyway if it's not a valid char
-                        // clearing it will fail auth correctly
-                        self.clear();
-                        break;
-                    };
+// `--bell off` is a value too, so the volume is optional inside the flag's own option
+#[derive(Clone)]
+struct Bell(Option<i8>);
 
-                    self.push_char(ch);
-                }
+impl Settings {
+    fn apply(&self, config: &mut Config) {
+        fn set<T: Clone>(target: &mut T, value: &Option<T>) {
+            if let Some(value) = value {
+                *target = value.clone();
+            }
+        }
+        fn set_some<T: Clone>(target: &mut Option<T>, value: &Option<T>) {
+            if value.is_some() {
+                target.clone_from(value);
             }
         }
+        set_some(&mut config.user, &self.user);
+        set(&mut config.backends, &self.auth);
+        set(&mut config.max_password_len, &self.max_password_len);
+        set_some(&mut config.backspace_hold, &self.backspace_hold_ms);
+        set(&mut config.escape, &self.escape);
+        set(&mut config.wrong_password, &self.wrong_password);
+        if let Some(len) = self.auto_submit_len {
+            config.auto_submit_len = Some(len as usize);
+        }
+        set_some(&mut config.input_timeout, &self.input_timeout);
+        set_some(&mut config.lockout_attempts, &self.lockout);
+        set_some(&mut config.lockout_cmd, &self.lockout_cmd);
+        set_some(&mut config.retry_limit, &self.retry_limit);
+        set_some(&mut config.backoff_cap, &self.backoff_cap);
+        set_some(&mut config.background, &self.background);
+        set_some(&mut config.image, &self.image);
+        set_some(&mut config.attempt_log, &self.attempt_log);
+        set_some(&mut config.totp_secret_file, &self.totp_secret_file);
+        set(&mut config.image_mode, &self.image_mode);
+        config
+            .output_backgrounds
+            .extend(self.output_background.iter().cloned());
+        config
+            .output_images
+            .extend(self.output_image.iter().cloned());
+        set(&mut config.colors.idle, &self.idle_color);
+        set(&mut config.colors.input, &self.input_color);
+        set(&mut config.colors.error, &self.error_color);
+        set(&mut config.colors.caps, &self.caps_color);
+        set(&mut config.colors.flash, &self.flash_color);
+        set(&mut config.colors.code, &self.code_color);
+        set(&mut config.colors.cursor, &self.cursor_color);
+        set(
+            &mut config.colors.cursor_outline,
+            &self.cursor_outline_color,
+        );
+        set_some(&mut config.indicator_timeout, &self.indicator_ms);
+        set_some(&mut config.clock, &self.clock);
+        set_some(&mut config.dots, &self.dots);
+        set(&mut config.max_dots, &self.max_dots);
+        set(&mut config.show_user, &self.show_user);
+        set(&mut config.user_position, &self.user_position);
+        if let Some(StatusFields(fields)) = &self.status {
+            config.status.clone_from(fields);
+        }
+        set(&mut config.status_position, &self.status_position);
+        set_some(&mut config.font, &self.font);
+        set_some(&mut config.fade_in, &self.fade_in_ms);
+        set_some(&mut config.fade_out, &self.fade_out_ms);
+        set_some(&mut config.flash, &self.flash_ms);
+        set_some(&mut config.dpms, &self.dpms);
+        set_some(&mut config.suspend_after, &self.suspend_after);
+        if let Some(Bell(bell)) = self.bell {
+            config.bell = bell;
+        }
+        set_some(&mut config.bell_cmd, &self.bell_cmd);
+        for (target, keys) in [
+            (&mut config.passthrough_keys, &self.passthrough_keys),
+            (&mut config.submit_keys, &self.submit_keys),
+            (&mut config.clear_keys, &self.clear_keys),
+            (&mut config.delete_keys, &self.delete_keys),
+        ] {
+            if let Some(Keys(keys)) = keys {
+                target.clone_from(keys);
+            }
+        }
+        set_some(&mut config.pre_lock, &self.pre_lock);
+        set_some(&mut config.post_unlock, &self.post_unlock);
+        set_some(&mut config.lock_output, &self.lock_output);
+        set(&mut config.pointer, &self.pointer);
+        if self.show_cursor {
+            config.pointer = PointerMode::Visible;
+        }
+        config.show_failures |= self.show_failures;
+        config.osk |= self.osk;
+        config.argb |= self.argb;
+        config.volume_keys |= self.volume_keys;
+        config.inhibit_screensaver |= self.inhibit_screensaver;
+        config.xi2_grab |= self.xi2_grab;
+        config.state_file |= self.state_file;
+        config.fingerprint |= self.fingerprint;
+        config.control_socket |= self.control_socket;
     }
 }
 
-struct Keyb(xkb::State);
+fn parse_secs(value: &str) -> Result<Duration, String> {
+    value
+        .parse()
+        .map(Duration::from_secs)
+        .map_err(|_| "expected a number of seconds".to_owned())
+}
 
-impl Keyb {
-    fn new() -> Option<Self> {
-        let context = xkb::Context::new(0);
-        xkb::Keymap::new_from_names(&context, "", "", "", "", None, 0)
-            .map(|kmap| Keyb(xkb::State::new(&kmap)))
-    }
+// `DP-1=#1d2021`, split at the first `=` since output names never contain one
+fn parse_output<T>(value: &str) -> Result<(String, T), String>
+where
+    T: FromStr,
+    T::Err: ToString,
+{
+    let (output, setting) = value
+        .split_once('=')
+        .filter(|(output, _)| !output.is_empty())
+        .ok_or_else(|| "expected OUTPUT=VALUE".to_owned())?;
+    let setting = setting.parse().map_err(|e: T::Err| e.to_string())?;
+    Ok((output.to_owned(), setting))
+}
 
-    fn keycode_to_keysym(&self, code: x::Keycode) -> xkb::Keysym {
-        self.0.key_get_one_sym(xkb::Keycode::new(code as u32))
-    }
+fn parse_millis(value: &str) -> Result<Duration, String> {
+    value
+        .parse()
+        .map(Duration::from_millis)
+        .map_err(|_| "expected a number of milliseconds".to_owned())
+}
 
-    fn keysym_to_char(key: xkb::Keysym) -> Option<char> {
-        char::from_u32(key.raw())
-    }
+fn parse_keys(value: &str) -> Result<Keys, String> {
+    Ok(Keys(zlock::parse_keys(value)))
+}
+
+fn parse_status(value: &str) -> Result<StatusFields, String> {
+    zlock::parse_status(value).map(StatusFields)
+}
+
+fn parse_bell(value: &str) -> Result<Bell, String> {
+    zlock::parse_bell(value).map(Bell)
 }
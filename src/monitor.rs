@@ -0,0 +1,71 @@
+use xcb::{randr, x, Connection};
+
+// an active output and the area it shows; `name` is RandR's (`DP-1`, `HDMI-A-0`, ...), None
+// for the whole-screen fallback
+pub(crate) struct Output {
+    pub(crate) name: Option<String>,
+    pub(crate) area: x::Rectangle,
+}
+
+// every active output on the screen behind `root`, or the whole screen when RandR has
+// nothing to say (no extension, no crtcs, nested servers)
+pub(crate) fn outputs(conn: &Connection, root: x::Window, width: u16, height: u16) -> Vec<Output> {
+    let whole = Output {
+        name: None,
+        area: x::Rectangle {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        },
+    };
+    let cookie = conn.send_request(&randr::GetScreenResourcesCurrent { window: root });
+    let Ok(resources) = conn.wait_for_reply(cookie) else {
+        return vec![whole];
+    };
+    let cookies: Vec<_> = resources
+        .crtcs()
+        .iter()
+        .map(|&crtc| {
+            conn.send_request(&randr::GetCrtcInfo {
+                crtc,
+                config_timestamp: resources.config_timestamp(),
+            })
+        })
+        .collect();
+    let outputs: Vec<_> = cookies
+        .into_iter()
+        .filter_map(|cookie| conn.wait_for_reply(cookie).ok())
+        .filter(|crtc| crtc.width() > 0 && crtc.height() > 0)
+        .map(|crtc| Output {
+            // a cloned crtc drives several outputs, the first one names it
+            name: crtc
+                .outputs()
+                .first()
+                .and_then(|&output| output_name(conn, output, resources.config_timestamp())),
+            area: x::Rectangle {
+                x: crtc.x(),
+                y: crtc.y(),
+                width: crtc.width(),
+                height: crtc.height(),
+            },
+        })
+        .collect();
+    if outputs.is_empty() {
+        return vec![whole];
+    }
+    outputs
+}
+
+fn output_name(
+    conn: &Connection,
+    output: randr::Output,
+    config_timestamp: x::Timestamp,
+) -> Option<String> {
+    let cookie = conn.send_request(&randr::GetOutputInfo {
+        output,
+        config_timestamp,
+    });
+    let info = conn.wait_for_reply(cookie).ok()?;
+    Some(String::from_utf8_lossy(info.name()).into_owned())
+}
@@ -0,0 +1,181 @@
+use crate::{text::TextFont, LockError};
+use xcb::{x, Connection};
+
+const ROWS: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+// widest row, in key widths; the bottom row is Shift, Space and Enter
+const COLUMNS: u16 = 10;
+const MAX_KEY: u16 = 64;
+// gap between keys and below the keyboard
+const GAP: u16 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OskKey {
+    Char(char),
+    Shift,
+    Delete,
+    Submit,
+}
+
+// a QWERTY keyboard drawn along the bottom of each lock window, for touchscreens without a
+// physical keyboard. Taps arrive as core button presses, which X emulates for touch
+#[derive(Debug, Default)]
+pub(crate) struct Osk {
+    shift: bool,
+}
+
+impl Osk {
+    // Shift only changes the labels, the typed chars follow them
+    pub(crate) fn toggle_shift(&mut self) {
+        self.shift = !self.shift;
+    }
+
+    pub(crate) fn hit(&self, width: u16, height: u16, x: i16, y: i16) -> Option<OskKey> {
+        self.keys(width, height)
+            .into_iter()
+            .find(|(rect, _)| {
+                (rect.x..rect.x + rect.width as i16).contains(&x)
+                    && (rect.y..rect.y + rect.height as i16).contains(&y)
+            })
+            .map(|(_, key)| key)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn draw(
+        &self,
+        conn: &Connection,
+        window: x::Window,
+        gc: x::Gcontext,
+        font: &TextFont,
+        color: u32,
+        width: u16,
+        height: u16,
+    ) -> Result<(), LockError> {
+        let keys = self.keys(width, height);
+        let top = keys
+            .iter()
+            .map(|(rect, _)| rect.y)
+            .min()
+            .unwrap_or_default();
+        conn.send_request(&x::ClearArea {
+            exposures: false,
+            window,
+            x: 0,
+            y: top,
+            width,
+            height: height.saturating_sub(top as u16),
+        });
+        conn.send_request(&x::ChangeGc {
+            gc,
+            value_list: &[x::Gc::Foreground(color)],
+        });
+        let rects: Vec<x::Rectangle> = keys.iter().map(|(rect, _)| *rect).collect();
+        conn.send_request(&x::PolyRectangle {
+            drawable: x::Drawable::Window(window),
+            gc,
+            rectangles: &rects,
+        });
+        for (rect, key) in &keys {
+            let label = self.label(*key);
+            font.draw_in(conn, window, gc, *rect, label.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn label(&self, key: OskKey) -> String {
+        match key {
+            OskKey::Char(' ') => "space".to_owned(),
+            OskKey::Char(ch) => ch.to_string(),
+            OskKey::Shift if self.shift => "SHIFT".to_owned(),
+            OskKey::Shift => "shift".to_owned(),
+            OskKey::Delete => "<-".to_owned(),
+            OskKey::Submit => "enter".to_owned(),
+        }
+    }
+
+    // every key and where it sits, scaled to the screen and centered at its bottom
+    fn keys(&self, width: u16, height: u16) -> Vec<(x::Rectangle, OskKey)> {
+        let size = (width / (COLUMNS + 1)).min(height / 12).min(MAX_KEY);
+        let step = size + GAP;
+        let left = |count: u16| (width.saturating_sub(count * step) / 2) as i16;
+        let rows = ROWS.len() as u16 + 1;
+        let top = height.saturating_sub(rows * step + GAP) as i16;
+        let rect = |x: i16, row: u16, keys: u16| x::Rectangle {
+            x,
+            y: top + (row * step) as i16,
+            width: keys * step - GAP,
+            height: size,
+        };
+        let mut keys = Vec::new();
+        for (row, chars) in ROWS.iter().enumerate() {
+            let row = row as u16;
+            // the last letter row ends in Delete
+            let count = chars.len() as u16 + u16::from(row == 3);
+            let x = left(count);
+            for (column, ch) in chars.chars().enumerate() {
+                let ch = if self.shift {
+                    ch.to_ascii_uppercase()
+                } else {
+                    ch
+                };
+                keys.push((
+                    rect(x + (column as u16 * step) as i16, row, 1),
+                    OskKey::Char(ch),
+                ));
+            }
+            if row == 3 {
+                let column = chars.len() as u16;
+                keys.push((rect(x + (column * step) as i16, row, 1), OskKey::Delete));
+            }
+        }
+        // Shift and Enter two keys wide, Space taking up the rest
+        let row = ROWS.len() as u16;
+        let x = left(COLUMNS);
+        keys.push((rect(x, row, 2), OskKey::Shift));
+        keys.push((
+            rect(x + (2 * step) as i16, row, COLUMNS - 4),
+            OskKey::Char(' '),
+        ));
+        keys.push((
+            rect(x + ((COLUMNS - 2) * step) as i16, row, 2),
+            OskKey::Submit,
+        ));
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Osk, OskKey};
+
+    #[test]
+    fn every_key_is_hit_in_its_middle() {
+        let mut osk = Osk::default();
+        for shift in [false, true] {
+            for (rect, key) in osk.keys(1920, 1080) {
+                let (x, y) = (
+                    rect.x + rect.width as i16 / 2,
+                    rect.y + rect.height as i16 / 2,
+                );
+                assert_eq!(osk.hit(1920, 1080, x, y), Some(key), "shift {shift}");
+            }
+            osk.toggle_shift();
+        }
+    }
+
+    #[test]
+    fn shift_types_capitals_and_the_middle_of_the_screen_types_nothing() {
+        let mut osk = Osk::default();
+        assert_eq!(osk.hit(1920, 1080, 960, 540), None);
+        let q = osk
+            .keys(1920, 1080)
+            .into_iter()
+            .find(|(_, key)| *key == OskKey::Char('q'))
+            .map(|(rect, _)| rect)
+            .unwrap();
+        osk.toggle_shift();
+        assert_eq!(
+            osk.hit(1920, 1080, q.x + 1, q.y + 1),
+            Some(OskKey::Char('Q'))
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use crate::LockError;
+use std::{io, os::unix::process::CommandExt, process::Command};
+
+// zlock may be installed setuid root (or setgid shadow) so the shadow backend can read
+// password hashes, and those privileges are needed for nothing else. The effective ids go
+// back to the caller's at start, so files, sockets, the environment and commands are all
+// handled with the caller's permissions; the saved ids keep the privileges to take back
+// for the moment a hash is looked up
+
+#[derive(Debug, Clone, Copy)]
+struct Ids {
+    real: u32,
+    effective: u32,
+    saved: u32,
+}
+
+fn uids() -> Ids {
+    let (mut real, mut effective, mut saved) = (0, 0, 0);
+    unsafe { libc::getresuid(&mut real, &mut effective, &mut saved) };
+    Ids {
+        real,
+        effective,
+        saved,
+    }
+}
+
+fn gids() -> Ids {
+    let (mut real, mut effective, mut saved) = (0, 0, 0);
+    unsafe { libc::getresgid(&mut real, &mut effective, &mut saved) };
+    Ids {
+        real,
+        effective,
+        saved,
+    }
+}
+
+/// Sets the effective uid and gid to the real ones when zlock runs setuid or setgid. The
+/// privileges stay in the saved ids, only taken back while a password hash is looked up.
+/// Call it first thing, before any config file or environment variable is read
+pub fn drop_privileges() -> Result<(), LockError> {
+    let (uid, gid) = (uids(), gids());
+    // the gid first, changing it may need the privileged uid
+    if gid.effective != gid.real && unsafe { libc::setegid(gid.real) } != 0 {
+        return Err(LockError::Privileges(io::Error::last_os_error()));
+    }
+    if uid.effective != uid.real && unsafe { libc::seteuid(uid.real) } != 0 {
+        return Err(LockError::Privileges(io::Error::last_os_error()));
+    }
+    if uid.saved != uid.real || gid.saved != gid.real {
+        log::debug!("running as uid {} with the privileges set aside", uid.real);
+    }
+    Ok(())
+}
+
+// setuid or setgid: someone else's privileges are held, dropped or not
+pub(crate) fn setuid() -> bool {
+    let (uid, gid) = (uids(), gids());
+    uid.effective != uid.real
+        || uid.saved != uid.real
+        || gid.effective != gid.real
+        || gid.saved != gid.real
+}
+
+// setuid or setgid with the privileges still in effect, drop_privileges not called yet
+pub(crate) fn elevated() -> bool {
+    let (uid, gid) = (uids(), gids());
+    uid.effective != uid.real || gid.effective != gid.real
+}
+
+// root now, or setuid root with the privileges set aside
+pub(crate) fn root() -> bool {
+    let uid = uids();
+    uid.effective == 0 || uid.saved == 0
+}
+
+// runs `f` with the privileges drop_privileges set aside, for reading a hash; without
+// anything set aside it just runs
+pub(crate) fn privileged<T>(f: impl FnOnce() -> T) -> T {
+    let (uid, gid) = (uids(), gids());
+    let raise_uid = uid.effective != uid.saved;
+    let raise_gid = gid.effective != gid.saved;
+    if !raise_uid && !raise_gid {
+        return f();
+    }
+    unsafe {
+        if raise_uid {
+            libc::seteuid(uid.saved);
+        }
+        if raise_gid {
+            libc::setegid(gid.saved);
+        }
+    }
+    let result = f();
+    // carrying on with the privileges would be worse than not carrying on at all
+    let dropped = unsafe {
+        (!raise_gid || libc::setegid(gid.effective) == 0)
+            && (!raise_uid || libc::seteuid(uid.effective) == 0)
+    };
+    if !dropped {
+        log::error!(
+            "failed to drop privileges again: {}",
+            io::Error::last_os_error()
+        );
+        std::process::abort();
+    }
+    result
+}
+
+// `sh -c cmd` as the real user, whether or not the privileges were dropped before: the
+// command is the user's own and never gets more than the user has
+pub(crate) fn shell(cmd: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(cmd);
+    if setuid() {
+        let (uid, gid) = (uids(), gids());
+        command.uid(uid.real).gid(gid.real);
+    }
+    command
+}
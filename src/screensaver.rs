@@ -0,0 +1,79 @@
+use crate::LockError;
+use xcb::{dpms, x, Connection};
+
+// the server's own screensaver and DPMS timeouts, switched off while locked so no other
+// program's settings blank the screen under the lock; only the lock's `dpms` still does.
+// Whatever was set before comes back on unlock
+pub(crate) struct ScreenSaver {
+    timeout: i16,
+    interval: i16,
+    prefer_blanking: x::Blanking,
+    allow_exposures: x::Exposures,
+    // standby, suspend and off, None without DPMS
+    dpms: Option<(u16, u16, u16)>,
+}
+
+impl ScreenSaver {
+    pub(crate) fn inhibit(conn: &Connection) -> Result<Self, LockError> {
+        let saver = conn
+            .wait_for_reply(conn.send_request(&x::GetScreenSaver {}))
+            .map_err(LockError::Connection)?;
+        let dpms = if conn
+            .active_extensions()
+            .any(|ext| ext == xcb::Extension::Dpms)
+        {
+            let timeouts = conn
+                .wait_for_reply(conn.send_request(&dpms::GetTimeouts {}))
+                .map_err(LockError::Connection)?;
+            Some((
+                timeouts.standby_timeout(),
+                timeouts.suspend_timeout(),
+                timeouts.off_timeout(),
+            ))
+        } else {
+            None
+        };
+        let inhibited = Self {
+            timeout: saver.timeout() as i16,
+            interval: saver.interval() as i16,
+            prefer_blanking: saver.prefer_blanking(),
+            allow_exposures: saver.allow_exposures(),
+            dpms,
+        };
+        // a timeout of 0 turns the screensaver off, and a running one is stopped
+        conn.send_request(&x::SetScreenSaver {
+            timeout: 0,
+            interval: inhibited.interval,
+            prefer_blanking: inhibited.prefer_blanking,
+            allow_exposures: inhibited.allow_exposures,
+        });
+        conn.send_request(&x::ForceScreenSaver {
+            mode: x::ScreenSaver::Reset,
+        });
+        if inhibited.dpms.is_some() {
+            conn.send_request(&dpms::SetTimeouts {
+                standby_timeout: 0,
+                suspend_timeout: 0,
+                off_timeout: 0,
+            });
+        }
+        log::debug!("screensaver and DPMS timeouts off while locked");
+        Ok(inhibited)
+    }
+
+    pub(crate) fn restore(&self, conn: &Connection) {
+        conn.send_request(&x::SetScreenSaver {
+            timeout: self.timeout,
+            interval: self.interval,
+            prefer_blanking: self.prefer_blanking,
+            allow_exposures: self.allow_exposures,
+        });
+        if let Some((standby_timeout, suspend_timeout, off_timeout)) = self.dpms {
+            conn.send_request(&dpms::SetTimeouts {
+                standby_timeout,
+                suspend_timeout,
+                off_timeout,
+            });
+        }
+    }
+}
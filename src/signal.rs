@@ -0,0 +1,143 @@
+use crate::LockError;
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM},
+    flag,
+};
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+// signals that would otherwise kill us while holding the grabs
+const SIGNALS: [i32; 3] = [SIGTERM, SIGINT, SIGHUP];
+
+// installed once per process: the handlers only record the signal while a lock is up,
+// and fall back to the default action (terminating) otherwise
+static HANDLERS: OnceLock<Result<Handlers, io::ErrorKind>> = OnceLock::new();
+
+struct Handlers {
+    received: Arc<AtomicUsize>,
+    idle: Arc<AtomicBool>,
+}
+
+impl Handlers {
+    fn install() -> io::Result<Self> {
+        let handlers = Handlers {
+            received: Arc::new(AtomicUsize::new(0)),
+            idle: Arc::new(AtomicBool::new(true)),
+        };
+        for signal in SIGNALS {
+            flag::register_conditional_default(signal, Arc::clone(&handlers.idle))?;
+            flag::register_usize(signal, Arc::clone(&handlers.received), signal as usize)?;
+        }
+        Ok(handlers)
+    }
+}
+
+/// Turns SIGTERM, SIGINT and SIGHUP into [`LockError::Terminated`] for as long as it lives,
+/// so the lock unwinds through its `Drop` instead of dying with the grabs in place
+pub(crate) struct SignalGuard(&'static Handlers);
+
+impl SignalGuard {
+    pub(crate) fn new() -> Result<Self, LockError> {
+        let handlers = HANDLERS
+            .get_or_init(|| Handlers::install().map_err(|e| e.kind()))
+            .as_ref()
+            .map_err(|&kind| LockError::Signal(kind.into()))?;
+        handlers.received.store(0, Ordering::SeqCst);
+        handlers.idle.store(false, Ordering::SeqCst);
+        Ok(SignalGuard(handlers))
+    }
+
+    pub(crate) fn check(&self) -> Result<(), LockError> {
+        match self.0.received.load(Ordering::SeqCst) {
+            0 => Ok(()),
+            signal => {
+                log::info!("received signal {signal}, unlocking");
+                Err(LockError::Terminated(signal as i32))
+            }
+        }
+    }
+
+    // sleeps in short steps so a signal doesn't have to wait out a long backoff
+    pub(crate) fn sleep(&self, duration: Duration) -> Result<(), LockError> {
+        sleep_in_steps(duration, Instant::now, std::thread::sleep, || self.check())
+    }
+}
+
+// the clock and the pause are passed in so a test can sleep without waiting
+fn sleep_in_steps(
+    duration: Duration,
+    now: impl Fn() -> Instant,
+    mut pause: impl FnMut(Duration),
+    check: impl Fn() -> Result<(), LockError>,
+) -> Result<(), LockError> {
+    const STEP: Duration = Duration::from_millis(100);
+    let deadline = now() + duration;
+    loop {
+        check()?;
+        let left = deadline.saturating_duration_since(now());
+        if left.is_zero() {
+            return Ok(());
+        }
+        pause(left.min(STEP));
+    }
+}
+
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        self.0.idle.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock::backoff_delay;
+    use std::cell::{Cell, RefCell};
+
+    #[test]
+    fn a_backoff_sleeps_in_steps_until_the_deadline() {
+        let start = Instant::now();
+        let clock = Cell::new(start);
+        let pauses = RefCell::new(Vec::new());
+        let delay = backoff_delay(6, 3, Duration::from_secs(30)).unwrap();
+        sleep_in_steps(
+            delay,
+            || clock.get(),
+            |step| {
+                pauses.borrow_mut().push(step);
+                clock.set(clock.get() + step);
+            },
+            || Ok(()),
+        )
+        .unwrap();
+        assert_eq!(clock.get() - start, Duration::from_secs(4));
+        assert_eq!(pauses.borrow().len(), 40);
+        assert!(pauses
+            .borrow()
+            .iter()
+            .all(|&step| step == Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn a_signal_cuts_the_backoff_short() {
+        let start = Instant::now();
+        let clock = Cell::new(start);
+        let result = sleep_in_steps(
+            Duration::from_secs(30),
+            || clock.get(),
+            |step| clock.set(clock.get() + step),
+            || match clock.get() - start >= Duration::from_secs(1) {
+                true => Err(LockError::Terminated(SIGTERM)),
+                false => Ok(()),
+            },
+        );
+        assert!(matches!(result, Err(LockError::Terminated(SIGTERM))));
+        assert_eq!(clock.get() - start, Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+// lock state for status bars and scripts: `locked` while a lock is up, `unlocked` once it's
+// gone. The lock never depends on it, failing to write is only logged
+pub(crate) struct StateFile(PathBuf);
+
+impl StateFile {
+    // marks $XDG_RUNTIME_DIR/zlock.state locked until the returned value is dropped
+    pub(crate) fn locked() -> Option<Self> {
+        let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) else {
+            log::warn!("state_file needs $XDG_RUNTIME_DIR, not writing the lock state");
+            return None;
+        };
+        let file = StateFile(PathBuf::from(dir).join("zlock.state"));
+        file.write("locked");
+        Some(file)
+    }
+
+    // written aside and renamed over, a reader never sees a half-written file
+    fn write(&self, state: &str) {
+        let tmp = self.0.with_extension("state.tmp");
+        let written = std::fs::write(&tmp, format!("{state}\n"))
+            .and_then(|()| std::fs::rename(&tmp, &self.0));
+        match written {
+            Ok(()) => log::debug!("{} is now {state}", self.0.display()),
+            Err(e) => log::warn!("failed to write {}: {e}", self.0.display()),
+        }
+    }
+}
+
+impl Drop for StateFile {
+    fn drop(&mut self) {
+        self.write("unlocked");
+    }
+}
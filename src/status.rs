@@ -0,0 +1,163 @@
+use crate::clock::format_time;
+use std::{
+    fs,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+// sysfs is only read this often, the time is formatted every tick
+const REFRESH: Duration = Duration::from_secs(10);
+const SEPARATOR: &str = "  |  ";
+
+/// One part of the status line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusField {
+    /// Charge of the first battery in `/sys/class/power_supply`, and whether it's charging
+    Battery,
+    /// The time as `%H:%M`
+    Time,
+    /// The first network interface that's up, or `offline`
+    Network,
+}
+
+impl FromStr for StatusField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "battery" => Ok(StatusField::Battery),
+            "time" => Ok(StatusField::Time),
+            "network" | "net" => Ok(StatusField::Network),
+            other => Err(format!(
+                "invalid status field `{other}`, expected battery, time or network"
+            )),
+        }
+    }
+}
+
+// a line of battery, time and network, in the order the fields were given. Everything comes
+// from sysfs files that are there or not, so a tick never blocks on anything
+pub(crate) struct Status {
+    fields: Vec<StatusField>,
+    battery: Option<String>,
+    network: String,
+    read: Option<Instant>,
+    text: String,
+}
+
+impl Status {
+    pub(crate) fn new(fields: Vec<StatusField>) -> Self {
+        Self {
+            fields,
+            battery: None,
+            network: String::new(),
+            read: None,
+            text: String::new(),
+        }
+    }
+
+    pub(crate) fn text(&self) -> &str {
+        &self.text
+    }
+
+    // true when the text changed and needs to be drawn again
+    pub(crate) fn tick(&mut self) -> bool {
+        if self.read.is_none_or(|read| read.elapsed() >= REFRESH) {
+            self.read = Some(Instant::now());
+            if self.fields.contains(&StatusField::Battery) {
+                self.battery = battery(Path::new("/sys/class/power_supply"));
+            }
+            if self.fields.contains(&StatusField::Network) {
+                self.network = network(Path::new("/sys/class/net"));
+            }
+        }
+        let text = self
+            .fields
+            .iter()
+            .filter_map(|field| match field {
+                StatusField::Battery => self.battery.clone(),
+                StatusField::Time => Some(format_time("%H:%M")),
+                StatusField::Network => Some(self.network.clone()),
+            })
+            .collect::<Vec<_>>()
+            .join(SEPARATOR);
+        if text == self.text {
+            return false;
+        }
+        self.text = text;
+        true
+    }
+}
+
+// None on a desktop without a battery, the field is left out then
+fn battery(dir: &Path) -> Option<String> {
+    let mut supplies: Vec<_> = fs::read_dir(dir).ok()?.flatten().collect();
+    // BAT0 before BAT1, read_dir has no order
+    supplies.sort_by_key(|entry| entry.file_name());
+    supplies.iter().find_map(|supply| {
+        let path = supply.path();
+        if read(&path.join("type"))? != "Battery" {
+            return None;
+        }
+        battery_text(&read(&path.join("capacity"))?, &read(&path.join("status"))?)
+    })
+}
+
+fn battery_text(capacity: &str, status: &str) -> Option<String> {
+    let capacity: u8 = capacity.parse().ok()?;
+    Some(match status {
+        "Charging" => format!("battery {capacity}% charging"),
+        _ => format!("battery {capacity}%"),
+    })
+}
+
+fn network(dir: &Path) -> String {
+    let mut interfaces: Vec<_> = fs::read_dir(dir)
+        .map(|entries| entries.flatten().collect())
+        .unwrap_or_default();
+    interfaces.sort_by_key(|entry| entry.file_name());
+    interfaces
+        .iter()
+        .filter(|interface| interface.file_name() != "lo")
+        .find(|interface| read(&interface.path().join("operstate")).as_deref() == Some("up"))
+        .map_or_else(
+            || "offline".to_owned(),
+            |interface| interface.file_name().to_string_lossy().into_owned(),
+        )
+}
+
+fn read(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn battery_text_shows_charge_and_charging() {
+        assert_eq!(
+            battery_text("83", "Discharging").as_deref(),
+            Some("battery 83%")
+        );
+        assert_eq!(
+            battery_text("40", "Charging").as_deref(),
+            Some("battery 40% charging")
+        );
+        assert_eq!(battery_text("", "Full"), None);
+    }
+
+    #[test]
+    fn fields_keep_their_order() {
+        let mut status = Status::new(vec![StatusField::Network, StatusField::Battery]);
+        status.network = "wlan0".to_owned();
+        status.battery = Some("battery 83%".to_owned());
+        status.read = Some(Instant::now());
+        assert!(status.tick());
+        assert_eq!(status.text(), "wlan0  |  battery 83%");
+        assert!(!status.tick());
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant, SystemTime};
+
+// a monotonic clock that runs this far behind the wall clock between two ticks was stopped,
+// the machine was asleep
+const SLEEP_GAP: Duration = Duration::from_secs(5);
+
+// asks logind to suspend once the lock has sat idle for `delay`, once per idle stretch: the
+// next key or a resume allows another
+pub(crate) struct Suspend {
+    delay: Duration,
+    requested: bool,
+    // both clocks at the last tick; Instant stands still while suspended, SystemTime doesn't
+    mono: Instant,
+    wall: SystemTime,
+}
+
+impl Suspend {
+    // None without logind to ask
+    pub(crate) fn new(delay: Duration) -> Option<Self> {
+        if cfg!(not(feature = "logind")) {
+            log::warn!("zlock was built without the logind feature, it won't suspend");
+            return None;
+        }
+        Some(Self {
+            delay,
+            requested: false,
+            mono: Instant::now(),
+            wall: SystemTime::now(),
+        })
+    }
+
+    // true when the machine slept since the last tick; a clock set forward looks the same,
+    // which only costs a needless regrab
+    pub(crate) fn resumed(&mut self) -> bool {
+        let (mono, wall) = (Instant::now(), SystemTime::now());
+        let slept = wall
+            .duration_since(self.wall)
+            .is_ok_and(|passed| passed > mono.duration_since(self.mono) + SLEEP_GAP);
+        (self.mono, self.wall) = (mono, wall);
+        if slept {
+            self.requested = false;
+        }
+        slept
+    }
+
+    pub(crate) fn tick(&mut self, idle: Duration) {
+        if self.requested || idle < self.delay {
+            return;
+        }
+        // a failed request isn't retried until the next key, it would spam the bus every tick
+        self.requested = true;
+        log::debug!("idle for {idle:?}, suspending");
+        #[cfg(feature = "logind")]
+        if let Err(e) = crate::logind::suspend() {
+            log::warn!("logind: can't suspend: {e}");
+        }
+    }
+
+    pub(crate) fn wake(&mut self) {
+        self.requested = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Suspend;
+    use std::time::{Duration, Instant, SystemTime};
+
+    #[test]
+    fn a_wall_clock_ahead_of_the_monotonic_one_is_a_resume() {
+        let mut suspend = Suspend {
+            delay: Duration::from_secs(60),
+            requested: true,
+            mono: Instant::now(),
+            wall: SystemTime::now() - Duration::from_secs(3600),
+        };
+        assert!(suspend.resumed());
+        assert!(!suspend.requested);
+        assert!(!suspend.resumed());
+    }
+}
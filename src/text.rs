@@ -0,0 +1,181 @@
+use crate::LockError;
+use std::str::FromStr;
+use xcb::{x, Connection};
+
+// every X server has it, it's also the fallback for a font that can't be opened
+pub(crate) const FONT: &str = "fixed";
+// gap between the indicator and the text drawn above or below it
+pub(crate) const TEXT_MARGIN: i16 = 24;
+
+// a core X font for the bits of text on the lock screen (clock, dots)
+pub(crate) struct TextFont {
+    font: x::Font,
+    ascent: i16,
+    descent: i16,
+}
+
+impl TextFont {
+    // `name` is an XLFD or alias as listed by xlsfonts
+    pub(crate) fn open(conn: &Connection, name: &str) -> Result<Self, LockError> {
+        let font: x::Font = conn.generate_id();
+        if let Err(e) = conn.send_and_check_request(&x::OpenFont {
+            fid: font,
+            name: name.as_bytes(),
+        }) {
+            if name == FONT {
+                return Err(LockError::Font(e));
+            }
+            log::warn!("failed to open the font {name}, falling back to {FONT}: {e}");
+            return Self::open(conn, FONT);
+        }
+        let reply = conn
+            .wait_for_reply(conn.send_request(&x::QueryFont {
+                font: x::Fontable::Font(font),
+            }))
+            .map_err(LockError::Connection)?;
+        Ok(Self {
+            font,
+            ascent: reply.font_ascent(),
+            descent: reply.font_descent(),
+        })
+    }
+
+    pub(crate) fn id(&self) -> x::Font {
+        self.font
+    }
+
+    pub(crate) fn ascent(&self) -> i16 {
+        self.ascent
+    }
+
+    pub(crate) fn line_height(&self) -> i16 {
+        self.ascent + self.descent
+    }
+
+    // clears the full-width band around `baseline`, since the previous text may have been
+    // wider, then draws `text` centered in it
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn draw_centered(
+        &self,
+        conn: &Connection,
+        window: x::Window,
+        gc: x::Gcontext,
+        color: u32,
+        width: u16,
+        baseline: i16,
+        text: &[u8],
+    ) -> Result<(), LockError> {
+        // ImageText8 only takes up to 255 bytes
+        let text = &text[..text.len().min(255)];
+        let chars: Vec<x::Char2b> = text
+            .iter()
+            .map(|&byte2| x::Char2b { byte1: 0, byte2 })
+            .collect();
+        let extents = conn
+            .wait_for_reply(conn.send_request(&x::QueryTextExtents {
+                font: x::Fontable::Font(self.font),
+                string: &chars,
+            }))
+            .map_err(LockError::Connection)?;
+        conn.send_request(&x::ClearArea {
+            exposures: false,
+            window,
+            x: 0,
+            y: baseline - self.ascent,
+            width,
+            height: (self.ascent + self.descent) as u16,
+        });
+        conn.send_request(&x::ChangeGc {
+            gc,
+            value_list: &[x::Gc::Foreground(color)],
+        });
+        conn.send_request(&x::ImageText8 {
+            drawable: x::Drawable::Window(window),
+            gc,
+            x: ((width as i32 - extents.overall_width()) / 2) as i16,
+            y: baseline,
+            string: text,
+        });
+        Ok(())
+    }
+
+    // centers `text` in `rect` in the gc's current foreground, leaving the rest of it alone
+    pub(crate) fn draw_in(
+        &self,
+        conn: &Connection,
+        window: x::Window,
+        gc: x::Gcontext,
+        rect: x::Rectangle,
+        text: &[u8],
+    ) -> Result<(), LockError> {
+        let text = &text[..text.len().min(255)];
+        let chars: Vec<x::Char2b> = text
+            .iter()
+            .map(|&byte2| x::Char2b { byte1: 0, byte2 })
+            .collect();
+        let extents = conn
+            .wait_for_reply(conn.send_request(&x::QueryTextExtents {
+                font: x::Fontable::Font(self.font),
+                string: &chars,
+            }))
+            .map_err(LockError::Connection)?;
+        let baseline =
+            rect.y as i32 + (rect.height as i32 + (self.ascent - self.descent) as i32) / 2;
+        conn.send_request(&x::ImageText8 {
+            drawable: x::Drawable::Window(window),
+            gc,
+            x: (rect.x as i32 + (rect.width as i32 - extents.overall_width()) / 2) as i16,
+            y: baseline as i16,
+            string: text,
+        });
+        Ok(())
+    }
+}
+
+/// Whose password the lock asks for, as a line of text next to the indicator
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UserLine {
+    #[default]
+    Off,
+    /// The user name alone
+    User,
+    /// `user@host`
+    UserHost,
+}
+
+impl FromStr for UserLine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" | "false" => Ok(UserLine::Off),
+            "user" | "true" => Ok(UserLine::User),
+            "user@host" | "host" => Ok(UserLine::UserHost),
+            other => Err(format!(
+                "invalid user line `{other}`, expected off, user or user@host"
+            )),
+        }
+    }
+}
+
+/// Which side of the indicator a line of text goes on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextPosition {
+    #[default]
+    Above,
+    Below,
+}
+
+impl FromStr for TextPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "above" => Ok(TextPosition::Above),
+            "below" => Ok(TextPosition::Below),
+            other => Err(format!(
+                "invalid position `{other}`, expected above or below"
+            )),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::LockError;
+use hmac::{Hmac, Mac, NewMac};
+use sha1::Sha1;
+use std::{
+    os::unix::fs::MetadataExt,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zeroize::Zeroizing;
+
+// what every authenticator app uses unless told otherwise (RFC 6238)
+const STEP: u64 = 30;
+const DIGITS: usize = 6;
+// a code from the step before or after still counts, phones and laptops drift apart
+const SKEW: u64 = 1;
+
+// a shared secret for time-based one-time codes, HMAC-SHA1 over 30s steps, six digits. Kept
+// only in memory, zeroed once the lock is gone and never logged
+pub(crate) struct Totp {
+    secret: Zeroizing<Vec<u8>>,
+}
+
+impl Totp {
+    // the base32 secret an authenticator app was set up with, alone in a file only its owner
+    // may read; anything looser is refused rather than trusted
+    pub(crate) fn load(path: &Path) -> Result<Self, LockError> {
+        let fail = |reason: String| LockError::Totp(format!("{}: {reason}", path.display()));
+        let meta = std::fs::metadata(path).map_err(|e| fail(e.to_string()))?;
+        if meta.mode() & 0o077 != 0 {
+            return Err(fail(format!(
+                "readable by others ({:o}), chmod 600 it",
+                meta.mode() & 0o777
+            )));
+        }
+        let text = Zeroizing::new(std::fs::read_to_string(path).map_err(|e| fail(e.to_string()))?);
+        let secret = base32_decode(text.trim())
+            .filter(|secret| !secret.is_empty())
+            .ok_or_else(|| fail("not a base32 secret".to_owned()))?;
+        Ok(Self { secret })
+    }
+
+    pub(crate) fn check(&self, code: &str) -> bool {
+        if code.len() != DIGITS || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return false;
+        }
+        let Ok(code) = code.parse::<u32>() else {
+            return false;
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            / STEP;
+        (now.saturating_sub(SKEW)..=now + SKEW).any(|step| self.code(step) == code)
+    }
+
+    // RFC 4226's dynamic truncation of the step's HMAC
+    fn code(&self, step: u64) -> u32 {
+        let mut mac = Hmac::<Sha1>::new_varkey(&self.secret).expect("HMAC takes keys of any size");
+        mac.update(&step.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+        let offset = usize::from(hash[hash.len() - 1] & 0x0f);
+        let value = u32::from_be_bytes([
+            hash[offset],
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]) & 0x7fff_ffff;
+        value % 10u32.pow(DIGITS as u32)
+    }
+}
+
+// RFC 4648 base32 as apps show it: any case, spaces between groups and padding allowed
+fn base32_decode(text: &str) -> Option<Zeroizing<Vec<u8>>> {
+    let mut out = Zeroizing::new(Vec::with_capacity(text.len() * 5 / 8));
+    let (mut bits, mut len) = (0u64, 0);
+    for c in text.bytes().filter(|c| !matches!(c, b' ' | b'-' | b'=')) {
+        let value = match c.to_ascii_uppercase() {
+            c @ b'A'..=b'Z' => c - b'A',
+            c @ b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        bits = bits << 5 | u64::from(value);
+        len += 5;
+        if len >= 8 {
+            len -= 8;
+            out.push((bits >> len) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base32_decode, Totp};
+    use zeroize::Zeroizing;
+
+    // RFC 6238's SHA1 test vectors, cut to six digits
+    #[test]
+    fn matches_the_rfc_test_vectors() {
+        let totp = Totp {
+            secret: Zeroizing::new(b"12345678901234567890".to_vec()),
+        };
+        assert_eq!(totp.code(59 / 30), 287082);
+        assert_eq!(totp.code(1111111109 / 30), 81804);
+        assert_eq!(totp.code(20000000000 / 30), 353130);
+    }
+
+    #[test]
+    fn decodes_base32_as_apps_show_it() {
+        let secret = base32_decode("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap();
+        assert_eq!(secret.as_slice(), b"12345678901234567890");
+        assert!(base32_decode("not base32!").is_none());
+    }
+}
@@ -0,0 +1,332 @@
+use crate::{
+    attempts::AttemptLog,
+    auth::{resolve_user, Auth, Duress},
+    lock::{
+        backoff_delay, guess_brake, spawn_hook, Lockout, BACKOFF_CAP, INPUT_TIMEOUT, RETRY_LIMIT,
+    },
+    privilege::privileged,
+    signal::SignalGuard,
+    state::StateFile,
+    AuthBackends, Config, LockError, Locker,
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    os::fd::AsRawFd,
+    time::{Duration, Instant},
+};
+use zeroize::Zeroize;
+
+// from linux/vt.h, libc doesn't export them
+const VT_LOCKSWITCH: libc::c_ulong = 0x560b;
+const VT_UNLOCKSWITCH: libc::c_ulong = 0x560c;
+// how often the read loop wakes up without input, in ms
+const TICK: libc::c_int = 1000;
+// how long check_stdin takes to say no, so a script can't guess passwords at full speed
+const REJECT_DELAY: Duration = Duration::from_secs(2);
+
+/// A locked console: echo and VT switching stay off until the user has authenticated
+pub struct TtyLock {
+    tty: File,
+    saved: libc::termios,
+    vt_locked: bool,
+    backends: AuthBackends,
+    user: String,
+    pin: Option<String>,
+    duress: Option<Duress>,
+    input_timeout: Duration,
+    max_len: usize,
+    post_unlock: Option<String>,
+    bell: bool,
+    bell_cmd: Option<String>,
+    lockout: Option<Lockout>,
+    retry_limit: u32,
+    backoff_cap: Duration,
+    state: Option<StateFile>,
+    attempt_log: Option<AttemptLog>,
+    signals: SignalGuard,
+}
+
+impl TtyLock {
+    /// Locks the controlling terminal, for consoles without an X server.
+    /// Disabling VT switching needs a Linux virtual console and root (or CAP_SYS_TTY_CONFIG),
+    /// without it the lock only covers the current terminal
+    pub fn lock_tty(config: &Config) -> Result<TtyLock, LockError> {
+        let user = resolve_user(config.user.as_deref())?;
+        log::debug!("authenticating as {user}");
+        let backends = config.backends.preflight(&user)?;
+        let tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .map_err(LockError::Tty)?;
+        let fd = tty.as_raw_fd();
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+            return Err(LockError::Tty(io::Error::last_os_error()));
+        }
+        if let Some(cmd) = &config.pre_lock {
+            spawn_hook(cmd);
+        }
+        let (retry_limit, backoff_cap) = guess_brake(
+            config.retry_limit.unwrap_or(RETRY_LIMIT),
+            config.backoff_cap.unwrap_or(BACKOFF_CAP),
+        );
+        let mut lock = TtyLock {
+            tty,
+            saved,
+            vt_locked: false,
+            backends,
+            user,
+            pin: config.pin_hash.clone(),
+            duress: config.duress(),
+            input_timeout: config.input_timeout.unwrap_or(INPUT_TIMEOUT),
+            max_len: config.max_password_len,
+            post_unlock: config.post_unlock.clone(),
+            // a terminal bell has no volume, any setting turns it on
+            bell: config.bell.is_some(),
+            bell_cmd: config.bell_cmd.clone(),
+            lockout: config.lockout(),
+            retry_limit,
+            backoff_cap,
+            state: None,
+            attempt_log: config.attempt_log.as_deref().map(AttemptLog::open),
+            signals: SignalGuard::new()?,
+        };
+        lock.vt_locked = privileged(|| unsafe { libc::ioctl(fd, VT_LOCKSWITCH as _) }) == 0;
+        if !lock.vt_locked {
+            log::warn!(
+                "failed to disable VT switching: {}",
+                io::Error::last_os_error()
+            );
+        }
+        let mut raw = saved;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(fd, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(LockError::Tty(io::Error::last_os_error()));
+        }
+        lock.write(b"\x1b[2J\x1b[HThis console is locked by zlock.\r\n")?;
+        match lock
+            .attempt_log
+            .as_ref()
+            .map_or(0, AttemptLog::since_unlock)
+        {
+            0 => {}
+            1 => lock.write(b"1 failed attempt since the last unlock.\r\n")?,
+            n => {
+                lock.write(format!("{n} failed attempts since the last unlock.\r\n").as_bytes())?
+            }
+        }
+        log::info!("locked the terminal");
+        if config.state_file {
+            lock.state = StateFile::locked();
+        }
+        Ok(lock)
+    }
+
+    // raw mode turns off output processing too, hence the explicit \r
+    fn write(&mut self, text: &[u8]) -> Result<(), LockError> {
+        self.tty.write_all(text).map_err(LockError::Tty)
+    }
+
+    // None once a tick passes without input
+    fn read_byte(&mut self) -> Result<Option<u8>, LockError> {
+        let mut pfd = libc::pollfd {
+            fd: self.tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut pfd, 1, TICK) } <= 0 {
+            return Ok(None);
+        }
+        let mut byte = [0];
+        match self.tty.read(&mut byte) {
+            Ok(0) => Err(LockError::Tty(io::ErrorKind::UnexpectedEof.into())),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Ok(None),
+            Err(e) => Err(LockError::Tty(e)),
+        }
+    }
+
+    // swallows every key until a signal ends the lock, no password is checked anymore
+    fn locked_out(&mut self) -> Result<(), LockError> {
+        loop {
+            self.signals.check()?;
+            self.read_byte()?;
+        }
+    }
+
+    // keys typed while backing off are dropped
+    fn discard_input(&self) {
+        unsafe { libc::tcflush(self.tty.as_raw_fd(), libc::TCIFLUSH) };
+    }
+}
+
+impl Locker for TtyLock {
+    fn authenticate(&mut self) -> Result<(), LockError> {
+        // never grows past max_len, so the password is never copied by a reallocation
+        let mut buf: Vec<u8> = Vec::with_capacity(self.max_len);
+        let mut failures = 0;
+        let mut last_input = Instant::now();
+        self.write(b"Password: ")?;
+        loop {
+            self.signals.check()?;
+            let Some(byte) = self.read_byte()? else {
+                if !buf.is_empty() && last_input.elapsed() >= self.input_timeout {
+                    buf.zeroize();
+                }
+                continue;
+            };
+            last_input = Instant::now();
+            match byte {
+                b'\r' | b'\n' => {}
+                // backspace and delete remove the whole last char, which may span several bytes
+                0x08 | 0x7f => {
+                    let len = buf.iter().rposition(|&b| b & 0xc0 != 0x80).unwrap_or(0);
+                    buf[len..].zeroize();
+                    buf.truncate(len);
+                    continue;
+                }
+                // ctrl-u and escape start over
+                0x15 | 0x1b => {
+                    buf.zeroize();
+                    continue;
+                }
+                byte if byte < 0x20 => continue,
+                // past the cap further input is dropped, what was typed so far stays
+                byte => {
+                    if buf.len() < self.max_len {
+                        buf.push(byte);
+                    }
+                    continue;
+                }
+            }
+            if buf.is_empty() {
+                self.write(b"\r\nPassword: ")?;
+                continue;
+            }
+            let auth = match std::str::from_utf8(&buf) {
+                Ok(pass) => {
+                    self.backends
+                        .check(&self.user, self.pin.as_deref(), self.duress.as_ref(), pass)
+                }
+                // not something a password could be, wiped and counted like any wrong one
+                Err(_) => Auth::Incorrect,
+            };
+            match auth {
+                Auth::Correct => break,
+                Auth::Duress => {
+                    if let Some(duress) = &self.duress {
+                        spawn_hook(&duress.cmd);
+                    }
+                }
+                Auth::Incorrect => {}
+            }
+            buf.zeroize();
+            if self.bell {
+                self.write(b"\x07")?;
+            }
+            if let Some(cmd) = &self.bell_cmd {
+                spawn_hook(cmd);
+            }
+            self.write(b"\r\nIncorrect password\r\nPassword: ")?;
+            failures += 1;
+            log::info!("incorrect password, {failures} failed attempt(s)");
+            if let Some(log) = &self.attempt_log {
+                log.failed();
+            }
+            if self
+                .lockout
+                .as_ref()
+                .is_some_and(|l| l.on_failure(failures))
+            {
+                self.write(b"\r\nToo many failed attempts, locked out.\r\n")?;
+                return self.locked_out();
+            }
+            if let Some(delay) = backoff_delay(failures, self.retry_limit, self.backoff_cap) {
+                self.signals.sleep(delay)?;
+                self.discard_input();
+            }
+        }
+        buf.zeroize();
+        log::info!("unlocked after {failures} failed attempt(s)");
+        if let Some(log) = &self.attempt_log {
+            log.unlocked();
+        }
+        if let Some(cmd) = &self.post_unlock {
+            spawn_hook(cmd);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TtyLock {
+    fn drop(&mut self) {
+        let fd = self.tty.as_raw_fd();
+        let _ = self.tty.write_all(b"\r\n");
+        unsafe {
+            libc::tcsetattr(fd, libc::TCSAFLUSH, &self.saved);
+            if self.vt_locked {
+                privileged(|| libc::ioctl(fd, VT_UNLOCKSWITCH as _));
+            }
+        }
+    }
+}
+
+/// Checks a single password read from stdin, up to the first newline, for external auth
+/// agents and scripts. Nothing is locked and no hooks run, only the duress command does;
+/// true if the password (or PIN) is correct. Only root may check another user's password,
+/// and a wrong one is only reported after a delay
+pub fn check_stdin(config: &Config) -> Result<bool, LockError> {
+    let user = resolve_user(config.user.as_deref())?;
+    let backends = config.backends.preflight(&user)?;
+    let mut buf = read_stdin_line(config.max_password_len)?;
+    let result = match std::str::from_utf8(&buf) {
+        Ok(pass) if !pass.is_empty() => {
+            let duress = config.duress();
+            let auth = backends.check(&user, config.pin_hash.as_deref(), duress.as_ref(), pass);
+            if let (Auth::Duress, Some(duress)) = (auth, &duress) {
+                spawn_hook(&duress.cmd);
+            }
+            matches!(auth, Auth::Correct)
+        }
+        _ => false,
+    };
+    buf.zeroize();
+    log::info!(
+        "stdin password {}",
+        if result { "accepted" } else { "rejected" }
+    );
+    if !result {
+        std::thread::sleep(REJECT_DELAY);
+    }
+    Ok(result)
+}
+
+// byte by byte straight from the fd: a buffered reader would keep its own copy of the
+// password around. Bytes past `max` are read and dropped so the line still ends cleanly
+fn read_stdin_line(max: usize) -> Result<Vec<u8>, LockError> {
+    let mut buf = Vec::with_capacity(max);
+    let mut byte = 0u8;
+    loop {
+        let read = unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) };
+        match read {
+            0 => break,
+            1 if byte == b'\n' => break,
+            1 if buf.len() < max => buf.push(byte),
+            1 => {}
+            _ => {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    buf.zeroize();
+                    return Err(LockError::Stdin(e));
+                }
+            }
+        }
+    }
+    byte.zeroize();
+    if buf.last() == Some(&b'\r') {
+        buf.pop();
+    }
+    Ok(buf)
+}
@@ -0,0 +1,272 @@
+use crate::LockError;
+use std::str::FromStr;
+use xcb::{x, Connection};
+
+/// How a background image is fitted onto each monitor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// Resized to exactly cover the monitor, ignoring aspect ratio
+    #[default]
+    Stretch,
+    /// Drawn at its own size in the middle of the monitor
+    Center,
+    /// Repeated from the monitor's top left corner
+    Tile,
+}
+
+impl FromStr for ScaleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stretch" => Ok(ScaleMode::Stretch),
+            "center" => Ok(ScaleMode::Center),
+            "tile" => Ok(ScaleMode::Tile),
+            other => Err(format!(
+                "invalid scaling mode `{other}`, expected stretch, center or tile"
+            )),
+        }
+    }
+}
+
+// per-output colors without any image: filled straight into a pixmap used as the window's
+// background, so the server repaints it on its own whenever the window is exposed
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn fill_outputs(
+    conn: &Connection,
+    window: x::Window,
+    depth: u8,
+    width: u16,
+    height: u16,
+    background: u32,
+    fills: &[(x::Rectangle, u32)],
+) -> Result<(), LockError> {
+    let pixmap: x::Pixmap = conn.generate_id();
+    let gc: x::Gcontext = conn.generate_id();
+    conn.send_and_check_request(&x::CreatePixmap {
+        depth,
+        pid: pixmap,
+        drawable: x::Drawable::Window(window),
+        width,
+        height,
+    })
+    .map_err(LockError::CreateWindow)?;
+    conn.send_request(&x::CreateGc {
+        cid: gc,
+        drawable: x::Drawable::Pixmap(pixmap),
+        value_list: &[],
+    });
+    let whole = x::Rectangle {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    };
+    for (area, pixel) in std::iter::once((whole, background)).chain(fills.iter().copied()) {
+        conn.send_request(&x::ChangeGc {
+            gc,
+            value_list: &[x::Gc::Foreground(pixel)],
+        });
+        conn.send_request(&x::PolyFillRectangle {
+            drawable: x::Drawable::Pixmap(pixmap),
+            gc,
+            rectangles: &[area],
+        });
+    }
+    set_background(conn, window, pixmap, gc);
+    Ok(())
+}
+
+// hands the pixmap to the window and repaints it; the window keeps its own reference
+fn set_background(conn: &Connection, window: x::Window, pixmap: x::Pixmap, gc: x::Gcontext) {
+    conn.send_request(&x::ChangeWindowAttributes {
+        window,
+        value_list: &[x::Cw::BackPixmap(pixmap)],
+    });
+    conn.send_request(&x::ClearArea {
+        exposures: true,
+        window,
+        x: 0,
+        y: 0,
+        width: 0,
+        height: 0,
+    });
+    conn.send_request(&x::FreeGc { gc });
+    conn.send_request(&x::FreePixmap { pixmap });
+}
+
+#[cfg(feature = "image")]
+pub(crate) use render::{apply, Wallpaper};
+
+#[cfg(feature = "image")]
+mod render {
+    use super::ScaleMode;
+    use crate::{color::pixel_converter, LockError};
+    use image::{imageops, RgbImage};
+    use std::path::Path;
+    use xcb::{x, Connection};
+
+    pub(crate) struct Wallpaper {
+        image: RgbImage,
+        mode: ScaleMode,
+    }
+
+    // an output's area, the pixel it's filled with and the image drawn over it, if any
+    pub(crate) type Area<'a> = (x::Rectangle, u32, Option<&'a Wallpaper>);
+
+    // renders into a pixmap used as the window's background, so the server repaints
+    // it on its own whenever the window is exposed
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn apply(
+        conn: &Connection,
+        screen: &x::Screen,
+        window: x::Window,
+        depth: u8,
+        background: u32,
+        alpha: u32,
+        areas: &[Area],
+    ) -> Result<(), LockError> {
+        let (width, height) = (screen.width_in_pixels(), screen.height_in_pixels());
+        let setup = conn.get_setup();
+        let bpp = setup
+            .pixmap_formats()
+            .iter()
+            .find(|format| format.depth() == depth)
+            .map(|format| format.bits_per_pixel());
+        if bpp != Some(32) {
+            return Err(LockError::Image(format!(
+                "unsupported depth {depth} for background images"
+            )));
+        }
+
+        let mut pixels = vec![background; width as usize * height as usize];
+        let convert = pixel_converter(screen);
+        let to_pixel = |rgb| convert(rgb) | alpha;
+        for (area, pixel, wallpaper) in areas {
+            fill(&mut pixels, width, area, *pixel);
+            if let Some(wallpaper) = wallpaper {
+                wallpaper.paint(&mut pixels, width, area, to_pixel);
+            }
+        }
+        let lsb = matches!(setup.image_byte_order(), x::ImageOrder::LsbFirst);
+        let data: Vec<u8> = pixels
+            .iter()
+            .flat_map(|&px| match lsb {
+                true => px.to_le_bytes(),
+                false => px.to_be_bytes(),
+            })
+            .collect();
+
+        let pixmap: x::Pixmap = conn.generate_id();
+        let gc: x::Gcontext = conn.generate_id();
+        conn.send_and_check_request(&x::CreatePixmap {
+            depth,
+            pid: pixmap,
+            drawable: x::Drawable::Window(window),
+            width,
+            height,
+        })
+        .map_err(LockError::CreateWindow)?;
+        conn.send_request(&x::CreateGc {
+            cid: gc,
+            drawable: x::Drawable::Pixmap(pixmap),
+            value_list: &[],
+        });
+        // stay well below the maximum request size, the header needs some room too
+        let row = width as usize * 4;
+        let max_bytes = conn.get_maximum_request_length() as usize * 4 - 64;
+        let rows = (max_bytes / row).clamp(1, height as usize);
+        for (i, strip) in data.chunks(rows * row).enumerate() {
+            conn.send_request(&x::PutImage {
+                format: x::ImageFormat::ZPixmap,
+                drawable: x::Drawable::Pixmap(pixmap),
+                gc,
+                width,
+                height: (strip.len() / row) as u16,
+                dst_x: 0,
+                dst_y: (i * rows) as i16,
+                left_pad: 0,
+                depth,
+                data: strip,
+            });
+        }
+        super::set_background(conn, window, pixmap, gc);
+        Ok(())
+    }
+
+    // the part of `area` that's on the screen
+    fn fill(pixels: &mut [u32], stride: u16, area: &x::Rectangle, pixel: u32) {
+        let left = (area.x.max(0) as usize).min(stride as usize);
+        let right = (area.x as i64 + area.width as i64).clamp(0, stride as i64) as usize;
+        let top = area.y.max(0) as usize;
+        let bottom = (area.y as i64 + area.height as i64).max(0) as usize;
+        for row in pixels
+            .chunks_mut(stride as usize)
+            .skip(top)
+            .take(bottom.saturating_sub(top))
+        {
+            row[left..right.max(left)].fill(pixel);
+        }
+    }
+
+    impl Wallpaper {
+        pub(crate) fn load(path: &Path, mode: ScaleMode) -> Result<Self, LockError> {
+            let image = image::open(path)
+                .map_err(|e| LockError::Image(e.to_string()))?
+                .into_rgb8();
+            Ok(Self { image, mode })
+        }
+
+        fn paint(
+            &self,
+            pixels: &mut [u32],
+            stride: u16,
+            monitor: &x::Rectangle,
+            to_pixel: impl Fn(u32) -> u32,
+        ) {
+            let stretched;
+            let image = match self.mode {
+                ScaleMode::Stretch => {
+                    stretched = imageops::resize(
+                        &self.image,
+                        monitor.width.into(),
+                        monitor.height.into(),
+                        imageops::FilterType::Triangle,
+                    );
+                    &stretched
+                }
+                ScaleMode::Center | ScaleMode::Tile => &self.image,
+            };
+            let (iw, ih) = (image.width() as i64, image.height() as i64);
+            let (mw, mh) = (monitor.width as i64, monitor.height as i64);
+            // offset of the image's origin inside the monitor
+            let (ox, oy) = match self.mode {
+                ScaleMode::Center => ((mw - iw) / 2, (mh - ih) / 2),
+                ScaleMode::Stretch | ScaleMode::Tile => (0, 0),
+            };
+            for my in 0..mh {
+                for mx in 0..mw {
+                    let (mut ix, mut iy) = (mx - ox, my - oy);
+                    if let ScaleMode::Tile = self.mode {
+                        ix = ix.rem_euclid(iw);
+                        iy = iy.rem_euclid(ih);
+                    }
+                    if ix < 0 || iy < 0 || ix >= iw || iy >= ih {
+                        continue;
+                    }
+                    let sx = monitor.x as i64 + mx;
+                    let sy = monitor.y as i64 + my;
+                    if sx < 0 || sy < 0 || sx >= stride as i64 {
+                        continue;
+                    }
+                    let Some(px) = pixels.get_mut(sy as usize * stride as usize + sx as usize)
+                    else {
+                        continue;
+                    };
+                    let [r, g, b] = image.get_pixel(ix as u32, iy as u32).0;
+                    *px = to_pixel(u32::from_be_bytes([0, r, g, b]));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,673 @@
+use crate::{
+    attempts::AttemptLog,
+    auth::{resolve_user, Auth, Duress},
+    color::Background,
+    input::{Bindings, EscapeKey, InputEvent, InputHandler, WrongPassword},
+    lock::{
+        backoff_delay, guess_brake, spawn_hook, Indicator, Lockout, BACKOFF_CAP, INDICATOR_SIZE,
+        INDICATOR_TIMEOUT, INPUT_TIMEOUT, RETRY_LIMIT,
+    },
+    signal::SignalGuard,
+    state::StateFile,
+    AuthBackends, Colors, Config, LockError, Locker,
+};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Write},
+    os::fd::{AsFd, AsRawFd, FromRawFd},
+    time::{Duration, Instant},
+};
+use wayland_client::{
+    delegate_noop,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{
+        wl_buffer, wl_compositor, wl_keyboard, wl_output, wl_registry, wl_seat, wl_shm,
+        wl_shm_pool, wl_surface,
+    },
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols::ext::session_lock::v1::client::{
+    ext_session_lock_manager_v1::ExtSessionLockManagerV1,
+    ext_session_lock_surface_v1::{self, ExtSessionLockSurfaceV1},
+    ext_session_lock_v1::{self, ExtSessionLockV1},
+};
+use xkbcommon::xkb;
+
+// how often the event loop wakes up without input
+const TICK: Duration = Duration::from_secs(1);
+// the compositor decides whether we get the lock, it shouldn't take long
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A locked Wayland session, through the compositor's ext-session-lock-v1 support. The
+/// compositor keeps every output covered and all input on the lock surfaces; if zlock dies,
+/// the session stays locked
+pub struct WaylandLock {
+    queue: EventQueue<State>,
+    state: State,
+    backends: AuthBackends,
+    user: String,
+    pin: Option<String>,
+    duress: Option<Duress>,
+    input_timeout: Duration,
+    indicator_timeout: Duration,
+    indicator_since: Instant,
+    lockout: Option<Lockout>,
+    retry_limit: u32,
+    backoff_cap: Duration,
+    locked_out: bool,
+    wrong_password: WrongPassword,
+    post_unlock: Option<String>,
+    state_file: Option<StateFile>,
+    // the count since the last unlock is only logged, there's no text to show it in
+    attempt_log: Option<AttemptLog>,
+    signals: SignalGuard,
+}
+
+// everything the Wayland event handlers touch
+struct State {
+    compositor: wl_compositor::WlCompositor,
+    shm: wl_shm::WlShm,
+    lock: ExtSessionLockV1,
+    surfaces: Vec<Surface>,
+    keyboard: Option<wl_keyboard::WlKeyboard>,
+    // created once the seat sends its keymap
+    handler: Option<InputHandler>,
+    max_len: usize,
+    clear_on_hold: Option<Duration>,
+    escape: EscapeKey,
+    bindings: Bindings,
+    auto_submit: Option<usize>,
+    // translated key presses, handled by the lock loop after each dispatch
+    input: VecDeque<InputEvent>,
+    locked: bool,
+    finished: bool,
+    background: u32,
+    colors: Colors,
+    indicator: Indicator,
+}
+
+// one lock surface per output, sized by the compositor
+struct Surface {
+    // the registry name of the output, to notice it going away
+    name: u32,
+    output: wl_output::WlOutput,
+    surface: wl_surface::WlSurface,
+    lock_surface: ExtSessionLockSurfaceV1,
+    size: Option<(u32, u32)>,
+}
+
+impl WaylandLock {
+    /// Asks the compositor to lock the session and covers every output. Fails if the
+    /// compositor lacks ext-session-lock-v1 or refuses, e.g. because another locker is running
+    pub fn lock_session(config: &Config) -> Result<WaylandLock, LockError> {
+        let user = resolve_user(config.user.as_deref())?;
+        log::debug!("authenticating as {user}");
+        let backends = config.backends.preflight(&user)?;
+        let signals = SignalGuard::new()?;
+        let conn = Connection::connect_to_env().map_err(wayland_error)?;
+        let (globals, queue) = registry_queue_init::<State>(&conn).map_err(wayland_error)?;
+        let qh = queue.handle();
+        let compositor = globals
+            .bind::<wl_compositor::WlCompositor, _, _>(&qh, 4..=6, ())
+            .map_err(wayland_error)?;
+        let shm = globals
+            .bind::<wl_shm::WlShm, _, _>(&qh, 1..=1, ())
+            .map_err(wayland_error)?;
+        let manager = globals
+            .bind::<ExtSessionLockManagerV1, _, _>(&qh, 1..=1, ())
+            .map_err(|_| {
+                LockError::Wayland("the compositor doesn't support ext-session-lock-v1".into())
+            })?;
+        // without a seat there's nothing to type with, the lock could never be lifted
+        globals
+            .bind::<wl_seat::WlSeat, _, _>(&qh, 1..=7, ())
+            .map_err(wayland_error)?;
+        if let Some(cmd) = &config.pre_lock {
+            spawn_hook(cmd);
+        }
+        let mut state = State {
+            compositor,
+            shm,
+            lock: manager.lock(&qh, ()),
+            surfaces: Vec::new(),
+            keyboard: None,
+            handler: None,
+            max_len: config.max_password_len,
+            clear_on_hold: config.backspace_hold,
+            escape: config.escape,
+            bindings: Bindings::new(&config.key_bindings()),
+            auto_submit: config.auto_submit_len,
+            input: VecDeque::new(),
+            locked: false,
+            finished: false,
+            background: config.background.map_or(0, Background::resolve),
+            colors: config.colors,
+            indicator: Indicator::Idle,
+        };
+        manager.destroy();
+        for global in globals.contents().clone_list() {
+            if global.interface == wl_output::WlOutput::interface().name {
+                state.add_output(globals.registry(), global.name, global.version, &qh);
+            }
+        }
+        let (retry_limit, backoff_cap) = guess_brake(
+            config.retry_limit.unwrap_or(RETRY_LIMIT),
+            config.backoff_cap.unwrap_or(BACKOFF_CAP),
+        );
+        let mut lock = WaylandLock {
+            queue,
+            state,
+            backends,
+            user,
+            pin: config.pin_hash.clone(),
+            duress: config.duress(),
+            input_timeout: config.input_timeout.unwrap_or(INPUT_TIMEOUT),
+            indicator_timeout: config.indicator_timeout.unwrap_or(INDICATOR_TIMEOUT),
+            indicator_since: Instant::now(),
+            lockout: config.lockout(),
+            retry_limit,
+            backoff_cap,
+            locked_out: false,
+            wrong_password: config.wrong_password,
+            post_unlock: config.post_unlock.clone(),
+            state_file: None,
+            attempt_log: config.attempt_log.as_deref().map(AttemptLog::open),
+            signals,
+        };
+        let started = Instant::now();
+        while !lock.state.locked {
+            lock.signals.check()?;
+            if lock.state.finished {
+                return Err(LockError::Wayland(
+                    "the compositor refused the lock, is another locker running?".into(),
+                ));
+            }
+            if started.elapsed() >= LOCK_TIMEOUT {
+                return Err(LockError::Wayland(
+                    "the compositor didn't confirm the lock".into(),
+                ));
+            }
+            lock.dispatch(TICK)?;
+        }
+        log::info!("locked {} output(s)", lock.state.surfaces.len());
+        if config.state_file {
+            lock.state_file = StateFile::locked();
+        }
+        Ok(lock)
+    }
+
+    // handles whatever arrives within `timeout`, returns early once anything did
+    fn dispatch(&mut self, timeout: Duration) -> Result<(), LockError> {
+        self.queue.flush().map_err(wayland_error)?;
+        if self
+            .queue
+            .dispatch_pending(&mut self.state)
+            .map_err(wayland_error)?
+            > 0
+        {
+            return Ok(());
+        }
+        if let Some(guard) = self.queue.prepare_read() {
+            let mut pfd = libc::pollfd {
+                fd: guard.connection_fd().as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+            // EINTR is left for the signal check at the top of the loop
+            if unsafe { libc::poll(&mut pfd, 1, ms) } > 0 {
+                match guard.read() {
+                    Ok(_) => {}
+                    Err(wayland_client::backend::WaylandError::Io(e))
+                        if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(wayland_error(e)),
+                }
+            }
+        }
+        self.queue
+            .dispatch_pending(&mut self.state)
+            .map_err(wayland_error)?;
+        Ok(())
+    }
+
+    fn show(&mut self, indicator: Indicator) {
+        self.indicator_since = Instant::now();
+        self.state.indicator = indicator;
+        self.state.redraw(&self.queue.handle());
+    }
+
+    fn flash_expired(&self) -> bool {
+        matches!(self.state.indicator, Indicator::Input | Indicator::Error)
+            && self.indicator_since.elapsed() >= self.indicator_timeout
+    }
+
+    // what typing did to the indicator, the handler may not exist before the keymap arrives
+    fn for_input(&self) -> Indicator {
+        self.state
+            .handler
+            .as_ref()
+            .map_or(Indicator::Idle, Indicator::for_input)
+    }
+}
+
+impl Locker for WaylandLock {
+    fn authenticate(&mut self) -> Result<(), LockError> {
+        let mut failures = 0;
+        let mut last_input = Instant::now();
+        loop {
+            self.signals.check()?;
+            if self.state.finished {
+                return Err(LockError::Wayland("the compositor ended the lock".into()));
+            }
+            self.dispatch(TICK)?;
+            if self.state.input.is_empty() {
+                if let Some(handler) = &mut self.state.handler {
+                    // don't leave a half-typed password lying around once the user walks away
+                    if !handler.is_empty() && last_input.elapsed() >= self.input_timeout {
+                        handler.clear();
+                        let indicator = self.for_input();
+                        self.show(indicator);
+                    }
+                }
+                if self.flash_expired() {
+                    self.show(Indicator::Idle);
+                }
+                continue;
+            }
+            let Some(event) = self.state.input.pop_front() else {
+                continue;
+            };
+            match event {
+                InputEvent::Edit => {
+                    last_input = Instant::now();
+                    let indicator = self.for_input();
+                    self.show(indicator);
+                    continue;
+                }
+                InputEvent::Reset => {
+                    last_input = Instant::now();
+                    self.show(Indicator::Idle);
+                    continue;
+                }
+                InputEvent::Submit => last_input = Instant::now(),
+                // volume and pass-through keys are left to the compositor's own bindings
+                _ => continue,
+            }
+            let Some(handler) = &mut self.state.handler else {
+                continue;
+            };
+            if self.locked_out {
+                handler.clear();
+                self.show(Indicator::Error);
+                continue;
+            }
+            let Ok(pass) = handler.build_str() else {
+                handler.clear();
+                continue;
+            };
+            if pass.is_empty() {
+                self.show(Indicator::Idle);
+                continue;
+            }
+            match self
+                .backends
+                .check(&self.user, self.pin.as_deref(), self.duress.as_ref(), pass)
+            {
+                Auth::Correct => break,
+                Auth::Duress => {
+                    if let Some(duress) = &self.duress {
+                        spawn_hook(&duress.cmd);
+                    }
+                }
+                Auth::Incorrect => {}
+            }
+            if self.wrong_password == WrongPassword::Clear {
+                handler.clear();
+            }
+            self.show(Indicator::Error);
+            failures += 1;
+            log::info!("incorrect password, {failures} failed attempt(s)");
+            if let Some(log) = &self.attempt_log {
+                log.failed();
+            }
+            self.queue.flush().map_err(wayland_error)?;
+            if let Some(lockout) = &self.lockout {
+                self.locked_out = lockout.on_failure(failures);
+            }
+            if let Some(delay) = backoff_delay(failures, self.retry_limit, self.backoff_cap) {
+                log::debug!("backing off for {delay:?}");
+                self.signals.sleep(delay)?;
+                self.dispatch(Duration::ZERO)?;
+                self.state.input.clear();
+            }
+        }
+        log::info!("unlocked after {failures} failed attempt(s)");
+        if let Some(log) = &self.attempt_log {
+            log.unlocked();
+        }
+        self.state.lock.unlock_and_destroy();
+        self.state.locked = false;
+        // the compositor has to see the unlock before the connection goes away
+        self.queue
+            .roundtrip(&mut self.state)
+            .map_err(wayland_error)?;
+        if let Some(cmd) = &self.post_unlock {
+            spawn_hook(cmd);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WaylandLock {
+    // without an unlock the compositor keeps the session locked after we're gone, which is
+    // the point; a lock that was never granted is withdrawn
+    fn drop(&mut self) {
+        if !self.state.locked {
+            self.state.lock.destroy();
+        }
+        for surface in self.state.surfaces.drain(..) {
+            surface.destroy();
+        }
+        if let Some(keyboard) = self.state.keyboard.take() {
+            keyboard.release();
+        }
+        let _ = self.queue.flush();
+    }
+}
+
+impl State {
+    fn add_output(
+        &mut self,
+        registry: &wl_registry::WlRegistry,
+        name: u32,
+        version: u32,
+        qh: &QueueHandle<State>,
+    ) {
+        let output = registry.bind::<wl_output::WlOutput, _, _>(name, version.min(4), qh, ());
+        let surface = self.compositor.create_surface(qh, ());
+        let lock_surface = self.lock.get_lock_surface(&surface, &output, qh, name);
+        log::debug!("covering output {name}");
+        self.surfaces.push(Surface {
+            name,
+            output,
+            surface,
+            lock_surface,
+            size: None,
+        });
+    }
+
+    fn redraw(&self, qh: &QueueHandle<State>) {
+        for surface in &self.surfaces {
+            if let Err(e) = self.draw(surface, qh) {
+                log::warn!("failed to draw the lock surface: {e}");
+            }
+        }
+    }
+
+    // a fresh buffer every time, drawn into a memfd: the background with the indicator
+    // square in the middle
+    fn draw(&self, surface: &Surface, qh: &QueueHandle<State>) -> io::Result<()> {
+        let Some((width, height)) = surface.size else {
+            return Ok(());
+        };
+        let (width, height) = (width as usize, height as usize);
+        let stride = width * 4;
+        let size = i32::try_from(stride * height)
+            .map_err(|_| io::Error::other("the output is too large"))?;
+        let fd = unsafe { libc::memfd_create(c"zlock".as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        let color = match self.indicator {
+            Indicator::Idle => self.colors.idle,
+            Indicator::Input => self.colors.input,
+            Indicator::Error => self.colors.error,
+            Indicator::CapsLock => self.colors.caps,
+        };
+        let side = (INDICATOR_SIZE as usize).min(width).min(height);
+        let (left, top) = ((width - side) / 2, (height - side) / 2);
+        let plain: Vec<u8> = std::iter::repeat_n(self.background.to_le_bytes(), width)
+            .flatten()
+            .collect();
+        let mut marked = plain.clone();
+        for pixel in marked[left * 4..(left + side) * 4].chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color.to_le_bytes());
+        }
+        let mut out = io::BufWriter::new(&mut file);
+        for row in 0..height {
+            let line = if (top..top + side).contains(&row) {
+                &marked
+            } else {
+                &plain
+            };
+            out.write_all(line)?;
+        }
+        out.flush()?;
+        drop(out);
+        let pool = self.shm.create_pool(file.as_fd(), size, qh, ());
+        let buffer = pool.create_buffer(
+            0,
+            width as i32,
+            height as i32,
+            stride as i32,
+            wl_shm::Format::Xrgb8888,
+            qh,
+            (),
+        );
+        pool.destroy();
+        surface.surface.attach(Some(&buffer), 0, 0);
+        surface
+            .surface
+            .damage_buffer(0, 0, width as i32, height as i32);
+        surface.surface.commit();
+        Ok(())
+    }
+}
+
+impl Surface {
+    fn destroy(self) {
+        self.lock_surface.destroy();
+        self.surface.destroy();
+        if self.output.version() >= 3 {
+            self.output.release();
+        }
+    }
+}
+
+fn wayland_error(e: impl std::fmt::Display) -> LockError {
+    LockError::Wayland(e.to_string())
+}
+
+// outputs plugged in while locked get covered too, unplugged ones are let go
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } if interface == wl_output::WlOutput::interface().name => {
+                state.add_output(registry, name, version, qh);
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                if let Some(index) = state.surfaces.iter().position(|s| s.name == name) {
+                    log::debug!("output {name} went away");
+                    state.surfaces.swap_remove(index).destroy();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtSessionLockV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ExtSessionLockV1,
+        event: ext_session_lock_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_session_lock_v1::Event::Locked => state.locked = true,
+            ext_session_lock_v1::Event::Finished => state.finished = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtSessionLockSurfaceV1, u32> for State {
+    fn event(
+        state: &mut Self,
+        lock_surface: &ExtSessionLockSurfaceV1,
+        event: ext_session_lock_surface_v1::Event,
+        _: &u32,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let ext_session_lock_surface_v1::Event::Configure {
+            serial,
+            width,
+            height,
+        } = event
+        {
+            lock_surface.ack_configure(serial);
+            let Some(index) = state
+                .surfaces
+                .iter()
+                .position(|s| &s.lock_surface == lock_surface)
+            else {
+                return;
+            };
+            state.surfaces[index].size = Some((width, height));
+            let surface = &state.surfaces[index];
+            if let Err(e) = state.draw(surface, qh) {
+                log::warn!("failed to draw the lock surface: {e}");
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for State {
+    fn event(
+        state: &mut Self,
+        seat: &wl_seat::WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_seat::Event::Capabilities {
+            capabilities: WEnum::Value(capabilities),
+        } = event
+        {
+            if capabilities.contains(wl_seat::Capability::Keyboard) && state.keyboard.is_none() {
+                state.keyboard = Some(seat.get_keyboard(qh, ()));
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_keyboard::WlKeyboard, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_keyboard::WlKeyboard,
+        event: wl_keyboard::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_keyboard::Event::Keymap {
+                format: WEnum::Value(wl_keyboard::KeymapFormat::XkbV1),
+                fd,
+                size,
+            } => {
+                let context = xkb::Context::new(0);
+                let keymap = unsafe {
+                    xkb::Keymap::new_from_fd(
+                        &context,
+                        fd,
+                        size as usize,
+                        xkb::KEYMAP_FORMAT_TEXT_V1,
+                        xkb::KEYMAP_COMPILE_NO_FLAGS,
+                    )
+                };
+                match keymap {
+                    Ok(Some(keymap)) => {
+                        // a new keymap starts a fresh password, like a layout change on X
+                        let mut handler = InputHandler::from_keymap(&keymap, state.max_len);
+                        handler.clear_on_hold(state.clear_on_hold);
+                        handler.escape_key(state.escape);
+                        handler.bind_keys(state.bindings.clone());
+                        handler.auto_submit(state.auto_submit);
+                        state.handler = Some(handler);
+                    }
+                    Ok(None) => log::warn!("failed to compile the seat's keymap"),
+                    Err(e) => log::warn!("failed to read the seat's keymap: {e}"),
+                }
+            }
+            wl_keyboard::Event::Key {
+                key,
+                state: WEnum::Value(key_state),
+                ..
+            } => {
+                let Some(handler) = &mut state.handler else {
+                    return;
+                };
+                let Ok(code) = u8::try_from(key + 8) else {
+                    return;
+                };
+                let down = key_state == wl_keyboard::KeyState::Pressed;
+                if let Some(event) = handler.key(code, down) {
+                    state.input.push_back(event);
+                }
+            }
+            wl_keyboard::Event::Modifiers {
+                mods_depressed,
+                mods_latched,
+                mods_locked,
+                group,
+                ..
+            } => {
+                if let Some(handler) = &mut state.handler {
+                    handler.set_modifiers(mods_depressed, mods_latched, mods_locked, group);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// the compositor releases a buffer once it's done with it, each is only ever used once
+impl Dispatch<wl_buffer::WlBuffer, ()> for State {
+    fn event(
+        _: &mut Self,
+        buffer: &wl_buffer::WlBuffer,
+        event: wl_buffer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_buffer::Event::Release = event {
+            buffer.destroy();
+        }
+    }
+}
+
+delegate_noop!(State: ignore wl_compositor::WlCompositor);
+delegate_noop!(State: ignore wl_shm::WlShm);
+delegate_noop!(State: ignore wl_shm_pool::WlShmPool);
+delegate_noop!(State: ignore wl_surface::WlSurface);
+delegate_noop!(State: ignore wl_output::WlOutput);
+delegate_noop!(State: ExtSessionLockManagerV1);
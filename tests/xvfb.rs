@@ -0,0 +1,426 @@
+//! Locks a private Xvfb server and drives the lock through XTEST, the way a user would.
+//! Needs `Xvfb` on the PATH: `cargo test --features xvfb-tests`
+#![cfg(feature = "xvfb-tests")]
+
+use std::{
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use xcb::{x, xtest, Connection};
+use zlock::{Auth, Authenticator, Lock, LockError, LockEvent, Locker, PointerMode};
+
+const WIDTH: u16 = 800;
+const HEIGHT: u16 = 600;
+const STARTUP: Duration = Duration::from_secs(5);
+// keys typed back to back, well past what auto-repeat sends in a second
+const STORM: usize = 500;
+const XK_RETURN: u32 = 0xff0d;
+const XK_A: u32 = 0x61;
+// core event codes, as XTEST takes them
+const KEY_PRESS: u8 = 2;
+const KEY_RELEASE: u8 = 3;
+
+// DISPLAY is process wide, one server at a time
+static SERVER: Mutex<()> = Mutex::new(());
+// a fresh display number per server, the previous one's lock file may still be around
+static NEXT_DISPLAY: AtomicU32 = AtomicU32::new(0);
+
+// a throwaway X server, killed on drop
+struct Xvfb {
+    child: Child,
+    conn: Connection,
+    root: x::Window,
+}
+
+impl Xvfb {
+    fn start() -> Self {
+        let display = format!(
+            ":{}",
+            100 + std::process::id() % 100 * 10 + NEXT_DISPLAY.fetch_add(1, Ordering::Relaxed)
+        );
+        let child = Command::new("Xvfb")
+            .args([&display, "-screen", "0", &format!("{WIDTH}x{HEIGHT}x24")])
+            .args(["-nolisten", "tcp"])
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Xvfb should be installed");
+        let started = Instant::now();
+        let conn = loop {
+            match Connection::connect_with_extensions(Some(&display), &[xcb::Extension::Test], &[])
+            {
+                Ok((conn, _)) => break conn,
+                Err(_) if started.elapsed() < STARTUP => thread::sleep(Duration::from_millis(50)),
+                Err(e) => panic!("Xvfb didn't come up on {display}: {e}"),
+            }
+        };
+        std::env::set_var("DISPLAY", &display);
+        let root = conn.get_setup().roots().next().unwrap().root();
+        Self { child, conn, root }
+    }
+
+    // the override-redirect, viewable top-level windows
+    fn lock_windows(&self) -> Vec<x::Window> {
+        let tree = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&x::QueryTree { window: self.root }))
+            .unwrap();
+        tree.children()
+            .iter()
+            .copied()
+            .filter(|&window| {
+                let attrs = self
+                    .conn
+                    .wait_for_reply(self.conn.send_request(&x::GetWindowAttributes { window }))
+                    .unwrap();
+                attrs.override_redirect() && attrs.map_state() == x::MapState::Viewable
+            })
+            .collect()
+    }
+
+    // the window is mapped before the keyboard is grabbed, keys sent in between would be lost
+    fn wait_for_lock(&self) -> x::Window {
+        until("the lock to grab the keyboard", || !self.keyboard_free());
+        *self
+            .lock_windows()
+            .first()
+            .expect("no lock window showed up")
+    }
+
+    fn keyboard_free(&self) -> bool {
+        let status = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&x::GrabKeyboard {
+                owner_events: false,
+                grab_window: self.root,
+                time: x::CURRENT_TIME,
+                pointer_mode: x::GrabMode::Async,
+                keyboard_mode: x::GrabMode::Async,
+            }))
+            .unwrap()
+            .status();
+        if status == x::GrabStatus::Success {
+            self.conn
+                .send_and_check_request(&x::UngrabKeyboard {
+                    time: x::CURRENT_TIME,
+                })
+                .unwrap();
+        }
+        status != x::GrabStatus::AlreadyGrabbed
+    }
+
+    fn pointer_free(&self) -> bool {
+        let status = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&x::GrabPointer {
+                owner_events: false,
+                grab_window: self.root,
+                event_mask: x::EventMask::empty(),
+                pointer_mode: x::GrabMode::Async,
+                keyboard_mode: x::GrabMode::Async,
+                confine_to: x::WINDOW_NONE,
+                cursor: x::CURSOR_NONE,
+                time: x::CURRENT_TIME,
+            }))
+            .unwrap()
+            .status();
+        if status == x::GrabStatus::Success {
+            self.conn
+                .send_and_check_request(&x::UngrabPointer {
+                    time: x::CURRENT_TIME,
+                })
+                .unwrap();
+        }
+        status != x::GrabStatus::AlreadyGrabbed
+    }
+
+    fn screensaver_timeout(&self) -> u16 {
+        self.conn
+            .wait_for_reply(self.conn.send_request(&x::GetScreenSaver {}))
+            .unwrap()
+            .timeout()
+    }
+
+    fn window_exists(&self, window: x::Window) -> bool {
+        self.conn
+            .wait_for_reply(self.conn.send_request(&x::GetWindowAttributes { window }))
+            .is_ok()
+    }
+
+    fn keycode(&self, keysym: u32) -> u8 {
+        let setup = self.conn.get_setup();
+        let (min, max) = (setup.min_keycode(), setup.max_keycode());
+        let map = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&x::GetKeyboardMapping {
+                first_keycode: min,
+                count: max - min + 1,
+            }))
+            .unwrap();
+        let per = map.keysyms_per_keycode() as usize;
+        let index = map
+            .keysyms()
+            .iter()
+            .position(|&sym| sym == keysym)
+            .expect("the keysym should be mapped");
+        min + (index / per) as u8
+    }
+
+    fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+
+    fn tap(&self, keysym: u32) {
+        let detail = self.keycode(keysym);
+        for r#type in [KEY_PRESS, KEY_RELEASE] {
+            self.conn
+                .send_and_check_request(&xtest::FakeInput {
+                    r#type,
+                    detail,
+                    time: x::CURRENT_TIME,
+                    root: self.root,
+                    root_x: 0,
+                    root_y: 0,
+                    deviceid: 0,
+                })
+                .unwrap();
+        }
+    }
+}
+
+impl Drop for Xvfb {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+fn until(what: &str, mut done: impl FnMut() -> bool) {
+    let started = Instant::now();
+    while !done() {
+        assert!(started.elapsed() < STARTUP, "timed out waiting for {what}");
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+// test mode, so no password or shadow access is needed: Enter unlocks
+fn spawn_lock() -> thread::JoinHandle<Result<(), String>> {
+    thread::spawn(|| {
+        let mut lock = Lock::builder()
+            .test_mode()
+            .build()
+            .map_err(|e| e.to_string())?;
+        lock.authenticate().map_err(|e| e.to_string())
+    })
+}
+
+#[test]
+fn covers_the_screen_with_an_override_redirect_window() {
+    let _server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+    let xvfb = Xvfb::start();
+    let lock = spawn_lock();
+    let window = xvfb.wait_for_lock();
+    let geometry = xvfb
+        .conn
+        .wait_for_reply(xvfb.conn.send_request(&x::GetGeometry {
+            drawable: x::Drawable::Window(window),
+        }))
+        .unwrap();
+    assert_eq!(
+        (
+            geometry.x(),
+            geometry.y(),
+            geometry.width(),
+            geometry.height()
+        ),
+        (0, 0, WIDTH, HEIGHT)
+    );
+    xvfb.tap(XK_RETURN);
+    assert_eq!(lock.join().unwrap(), Ok(()));
+    until("the lock window to go away", || {
+        xvfb.lock_windows().is_empty()
+    });
+    assert!(xvfb.keyboard_free());
+}
+
+#[test]
+fn typed_keys_reach_the_input_handler() {
+    let _server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+    let xvfb = Xvfb::start();
+    let lock = spawn_lock();
+    xvfb.wait_for_lock();
+    xvfb.tap(XK_A);
+    xvfb.tap(XK_A);
+    xvfb.tap(XK_RETURN);
+    // well before test mode's own timeout would end it
+    until("Enter to end the lock", || lock.is_finished());
+    assert_eq!(lock.join().unwrap(), Ok(()));
+}
+
+// a key-repeat storm is drained in bursts, one redraw each, and doesn't hold up the Enter
+// typed right after it
+#[test]
+fn a_key_repeat_storm_is_drained_promptly() {
+    let _server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+    let xvfb = Xvfb::start();
+    let lock = spawn_lock();
+    xvfb.wait_for_lock();
+    let started = Instant::now();
+    for _ in 0..STORM {
+        xvfb.tap(XK_A);
+    }
+    xvfb.tap(XK_RETURN);
+    until("Enter to end the lock", || lock.is_finished());
+    assert_eq!(lock.join().unwrap(), Ok(()));
+    eprintln!("{STORM} keys and Enter handled in {:?}", started.elapsed());
+}
+
+#[test]
+fn a_dying_server_ends_the_lock_with_an_error() {
+    let _server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+    let mut xvfb = Xvfb::start();
+    let lock = spawn_lock();
+    xvfb.wait_for_lock();
+    xvfb.kill();
+    until("the lock to notice", || lock.is_finished());
+    let error = lock.join().unwrap().unwrap_err();
+    assert!(error.contains("lost the connection"), "{error}");
+}
+
+#[test]
+fn an_embedded_lock_reports_its_unlock() {
+    let _server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+    let xvfb = Xvfb::start();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let seen = Arc::clone(&events);
+    let lock = thread::spawn(move || {
+        let mut lock = Lock::builder().test_mode().build().unwrap();
+        assert!(lock.is_locked());
+        lock.on_event(move |event| seen.lock().unwrap().push(event));
+        lock.authenticate().unwrap();
+        (lock.is_locked(), lock.failed_attempts())
+    });
+    xvfb.wait_for_lock();
+    xvfb.tap(XK_RETURN);
+    until("Enter to end the lock", || lock.is_finished());
+    assert_eq!(lock.join().unwrap(), (false, 0));
+    assert_eq!(*events.lock().unwrap(), [LockEvent::Unlocked]);
+}
+
+#[test]
+fn a_keyboard_only_lock_leaves_the_pointer_alone() {
+    let _server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+    let xvfb = Xvfb::start();
+    let lock = thread::spawn(|| {
+        let mut lock = Lock::builder()
+            .test_mode()
+            .pointer(PointerMode::Ungrabbed)
+            .build()
+            .map_err(|e| e.to_string())?;
+        lock.authenticate().map_err(|e| e.to_string())
+    });
+    xvfb.wait_for_lock();
+    assert!(xvfb.pointer_free());
+    xvfb.tap(XK_RETURN);
+    until("Enter to end the lock", || lock.is_finished());
+    assert_eq!(lock.join().unwrap(), Ok(()));
+}
+
+#[test]
+fn the_screensaver_is_off_while_locked_and_restored_after() {
+    let _server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+    let xvfb = Xvfb::start();
+    let before = xvfb.screensaver_timeout();
+    assert_ne!(before, 0);
+    let lock = thread::spawn(|| {
+        let mut lock = Lock::builder()
+            .test_mode()
+            .inhibit_screensaver(true)
+            .build()
+            .map_err(|e| e.to_string())?;
+        lock.authenticate().map_err(|e| e.to_string())
+    });
+    xvfb.wait_for_lock();
+    until("the screensaver to be switched off", || {
+        xvfb.screensaver_timeout() == 0
+    });
+    xvfb.tap(XK_RETURN);
+    until("Enter to end the lock", || lock.is_finished());
+    assert_eq!(lock.join().unwrap(), Ok(()));
+    assert_eq!(xvfb.screensaver_timeout(), before);
+}
+
+// a lock that's dropped without ever being unlocked, as when the caller bails out. The
+// connection closing would release everything too, this checks nothing outlives the drop
+#[test]
+fn dropping_a_lock_releases_the_grabs_and_windows() {
+    let _server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+    let xvfb = Xvfb::start();
+    let (release, released) = mpsc::channel();
+    let lock = thread::spawn(move || {
+        let lock = Lock::builder()
+            .test_mode()
+            .build()
+            .map_err(|e| e.to_string())?;
+        released.recv().map_err(|e| e.to_string())?;
+        drop(lock);
+        Ok::<_, String>(())
+    });
+    let window = xvfb.wait_for_lock();
+    assert!(!xvfb.pointer_free());
+    release.send(()).unwrap();
+    assert_eq!(lock.join().unwrap(), Ok(()));
+    until("the keyboard grab to go", || xvfb.keyboard_free());
+    until("the pointer grab to go", || xvfb.pointer_free());
+    until("the lock window to be destroyed", || {
+        !xvfb.window_exists(window)
+    });
+    assert!(xvfb.lock_windows().is_empty());
+}
+
+#[test]
+fn an_unknown_output_is_refused_before_anything_is_locked() {
+    let _server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+    let xvfb = Xvfb::start();
+    let built = Lock::builder().test_mode().output("NOT-AN-OUTPUT").build();
+    assert!(matches!(
+        &built,
+        Err(LockError::InvalidOutput(name, _)) if name == "NOT-AN-OUTPUT"
+    ));
+    assert!(xvfb.lock_windows().is_empty());
+    assert!(xvfb.keyboard_free());
+}
+
+struct Fixed(&'static str);
+
+impl Authenticator for Fixed {
+    fn verify(&mut self, password: &str) -> Auth {
+        match password == self.0 {
+            true => Auth::Correct,
+            false => Auth::Incorrect,
+        }
+    }
+}
+
+#[test]
+fn a_custom_authenticator_decides_what_unlocks() {
+    let _server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+    let xvfb = Xvfb::start();
+    let lock = thread::spawn(|| {
+        let mut lock = Lock::builder().authenticator(Fixed("aa")).build().unwrap();
+        lock.authenticate().unwrap();
+        lock.failed_attempts()
+    });
+    xvfb.wait_for_lock();
+    xvfb.tap(XK_A);
+    xvfb.tap(XK_RETURN);
+    xvfb.tap(XK_A);
+    xvfb.tap(XK_A);
+    xvfb.tap(XK_RETURN);
+    until("the right password to end the lock", || lock.is_finished());
+    assert_eq!(lock.join().unwrap(), 1);
+}